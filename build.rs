@@ -0,0 +1,25 @@
+use std::env;
+use std::process::Command;
+
+/// 记录编译器版本、目标平台和构建配置，供 `git-tools version --verbose` 输出
+fn main() {
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| String::from("rustc"));
+    let rustc_version = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|version| version.trim().to_string())
+        .unwrap_or_else(|| String::from("unknown"));
+
+    println!("cargo:rustc-env=GT_RUSTC_VERSION={}", rustc_version);
+    println!(
+        "cargo:rustc-env=GT_BUILD_TARGET={}",
+        env::var("TARGET").unwrap_or_default()
+    );
+    println!(
+        "cargo:rustc-env=GT_BUILD_PROFILE={}",
+        env::var("PROFILE").unwrap_or_default()
+    );
+    println!("cargo:rerun-if-changed=build.rs");
+}
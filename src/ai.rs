@@ -1,497 +1,3943 @@
 use anyhow::Result;
 
-use crate::config::Settings;
-use crate::git::ConflictFile;
-use reqwest::Client;
+use crate::audit;
+use crate::cache::ResolutionCache;
+use crate::config::{ConflictStyle, LfsPolicy, Provider, Settings};
+use crate::git::{ConflictFile, ConflictKind};
+use crate::language;
+use reqwest::{Client, RequestBuilder};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use thiserror::Error;
+use tokio::sync::OnceCell;
 use tracing::*;
 
+/// 内置的系统提示词模板，第一个为默认模板，重试时可以依次轮换
+const DEFAULT_PROMPT_TEMPLATES: [&str; 3] = [
+    "You are a Git merge conflict resolver. Analyze the conflict and choose the most appropriate resolution. Return ONLY the resolved content without any explanation.",
+    "You are a Git merge conflict resolver. Decide which side of the conflict is correct and keep that version only, discarding the other. Return ONLY the resolved content without any explanation.",
+    "You are a Git merge conflict resolver. Combine the changes from both sides so that no intent from either version is lost. Return ONLY the resolved content without any explanation.",
+];
+
+/// 批量解决冲突时的系统提示词，要求模型以 JSON 返回每个文件的解决方案
+const BATCH_SYSTEM_PROMPT: &str = "You are a Git merge conflict resolver. You will receive several conflicts, each introduced by a line of the form \"=== FILE: <path> ===\". Resolve every conflict and respond with ONLY a JSON object of the form {\"resolutions\": {\"<path>\": \"<resolved content>\"}} containing one entry per file, without any explanation.";
+
+/// 启用 explain 时追加到系统提示词后的要求，让模型以 JSON 返回解决方案和理由
+const EXPLAIN_INSTRUCTION: &str = "Respond with ONLY a JSON object of the form {\"resolution\": \"<resolved content>\", \"reasoning\": \"<why you resolved the conflict this way>\"}.";
+
+/// 未启用上下文窗口自适应时，每段冲突内容提取的最大长度
+const MAX_CONTEXT_LENGTH: usize = 500;
+
+/// 响应中可能表示实际使用的模型的字段
+const RESPONSE_MODEL_KEYS: [&str; 2] = ["model", "modelVersion"];
+
+/// 模型元数据中可能表示上下文窗口大小的字段
+const CONTEXT_WINDOW_KEYS: [&str; 5] = [
+    "context_window",
+    "context_length",
+    "max_context_length",
+    "max_model_len",
+    "inputTokenLimit",
+];
+
+/// 常见模型的上下文窗口大小（token 数），在服务端不提供元数据时使用
+fn static_context_window(model: &str) -> u32 {
+    let model = model.to_lowercase();
+    if model.starts_with("gemini") {
+        1_048_576
+    } else if model.starts_with("claude") {
+        200_000
+    } else if model.starts_with("gpt-4o")
+        || model.starts_with("gpt-4-turbo")
+        || model.starts_with("gpt-4.1")
+        || model.starts_with("o1")
+        || model.starts_with("o3")
+    {
+        128_000
+    } else if model.starts_with("gpt-4-32k") {
+        32_768
+    } else if model.starts_with("gpt-3.5-turbo") {
+        16_385
+    } else {
+        8_192
+    }
+}
+
+/// 截取不超过 max_len 字节的前缀，不会从多字节字符中间切断
+fn truncate_at_char_boundary(text: &str, max_len: usize) -> &str {
+    if text.len() <= max_len {
+        return text;
+    }
+
+    let end = text
+        .char_indices()
+        .map(|(i, _)| i)
+        .take_while(|&i| i <= max_len)
+        .last()
+        .unwrap_or(0);
+    &text[..end]
+}
+
+/// 检查内容中是否残留冲突标记
+///
+/// 只检查行首的 `<<<<<<<` 和 `>>>>>>>`，单独的 `=======` 在 Markdown 等文件中是合法内容
+fn contains_conflict_markers(content: &str) -> bool {
+    content
+        .lines()
+        .any(|line| line.starts_with("<<<<<<<") || line.starts_with(">>>>>>>"))
+}
+
+/// 检查解决方案能否写入文件：不能残留冲突标记，JSON、YAML 等结构化文件必须能够解析
+fn check_resolution(conflict: &ConflictFile, content: &str) -> Result<()> {
+    if contains_conflict_markers(content) {
+        return Err(anyhow::anyhow!(
+            "AI resolution still contains conflict markers"
+        ));
+    }
+    language::check_format(&conflict.path, &conflict.our_content, content)
+}
+
+/// 宽松地解析模型返回的 JSON
+///
+/// 模型偶尔会返回带代码块标记、前后夹杂说明文字或带尾随逗号的 JSON，
+/// 依次尝试清理这些问题后再解析，并记录进行了哪些清理
+fn parse_lenient_json<T: DeserializeOwned>(text: &str) -> Result<T> {
+    let cleanups = [
+        (
+            "stripped code fence",
+            strip_code_fence as fn(&str) -> String,
+        ),
+        ("extracted outermost object", extract_json_object),
+        ("removed trailing commas", remove_trailing_commas),
+    ];
+
+    let mut candidate = text.trim().to_string();
+    let mut last_error = match serde_json::from_str(&candidate) {
+        Ok(value) => return Ok(value),
+        Err(e) => e,
+    };
+
+    let mut applied = Vec::new();
+    for (description, cleanup) in cleanups {
+        let cleaned = cleanup(&candidate);
+        if cleaned == candidate {
+            continue;
+        }
+        candidate = cleaned;
+        applied.push(description);
+
+        match serde_json::from_str(&candidate) {
+            Ok(value) => {
+                info!(
+                    "Parsed AI JSON response after cleanup: {}",
+                    applied.join(", ")
+                );
+                return Ok(value);
+            }
+            Err(e) => last_error = e,
+        }
+    }
+
+    Err(anyhow::anyhow!(
+        "Failed to parse AI JSON response: {}",
+        last_error
+    ))
+}
+
+/// 去掉包裹内容的 Markdown 代码块标记
+fn strip_code_fence(text: &str) -> String {
+    let trimmed = text.trim();
+    if !trimmed.starts_with("```") {
+        return trimmed.to_string();
+    }
+
+    // 去掉首行（可能带有语言标记）和结尾的代码块标记
+    let body = trimmed.split_once('\n').map(|(_, rest)| rest).unwrap_or("");
+    body.trim_end()
+        .strip_suffix("```")
+        .unwrap_or(body)
+        .trim()
+        .to_string()
+}
+
+/// 提取第一个 `{` 到最后一个 `}` 之间的内容
+fn extract_json_object(text: &str) -> String {
+    match (text.find('{'), text.rfind('}')) {
+        (Some(start), Some(end)) if start < end => text[start..=end].to_string(),
+        _ => text.to_string(),
+    }
+}
+
+/// 去掉对象和数组中的尾随逗号，忽略字符串内的逗号
+fn remove_trailing_commas(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut result = String::with_capacity(text.len());
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (i, &c) in chars.iter().enumerate() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+        } else if c == '"' {
+            in_string = true;
+        } else if c == ',' {
+            let next = chars[i + 1..].iter().find(|c| !c.is_whitespace());
+            if matches!(next, Some('}') | Some(']')) {
+                continue;
+            }
+        }
+        result.push(c);
+    }
+
+    result
+}
+
+/// AI 接口返回的失败状态
+///
+/// message 为错误说明：响应体是结构化的错误时为附带处理建议的说明，否则为原始响应体
+#[derive(Debug, Error)]
+pub enum ApiError {
+    /// 被限流（429）或服务暂时不可用（503），retry_after 为服务端要求的等待时间
+    #[error("API request was rate limited with status {status}: {message}")]
+    RateLimited {
+        status: reqwest::StatusCode,
+        retry_after: Option<std::time::Duration>,
+        message: String,
+    },
+    /// 除 408 和 429 以外的 4xx 错误（以及额度用尽的 429），例如 API 密钥无效或请求格式错误，
+    /// 重试也不会成功
+    #[error("API request failed with status {status}: {message}")]
+    Client {
+        status: reqwest::StatusCode,
+        message: String,
+    },
+    /// 其他失败状态
+    #[error("API request failed with status {status}: {message}")]
+    Status {
+        status: reqwest::StatusCode,
+        message: String,
+    },
+}
+
+impl ApiError {
+    /// 重试是否可能成功
+    pub fn is_retryable(&self) -> bool {
+        !matches!(self, ApiError::Client { .. })
+    }
+
+    /// 响应的 HTTP 状态码
+    pub fn status(&self) -> reqwest::StatusCode {
+        match self {
+            ApiError::RateLimited { status, .. }
+            | ApiError::Client { status, .. }
+            | ApiError::Status { status, .. } => *status,
+        }
+    }
+}
+
+/// 是否为不应重试的客户端错误
+fn is_client_error(error: &anyhow::Error) -> bool {
+    matches!(error.downcast_ref::<ApiError>(), Some(api_error) if !api_error.is_retryable())
+}
+
+/// OpenAI 等服务返回的结构化错误 `{ "error": { "message", "type", "code" } }`
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: ErrorDetail,
+}
+
+#[derive(Deserialize)]
+struct ErrorDetail {
+    #[serde(default)]
+    message: String,
+    #[serde(default, rename = "type")]
+    kind: Option<String>,
+    /// OpenAI 为字符串，Gemini 为数字状态码
+    #[serde(default)]
+    code: Option<serde_json::Value>,
+}
+
+/// 常见错误的类别，用于给出处理建议
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ErrorCategory {
+    /// API 密钥无效或没有权限
+    Auth,
+    /// 账户额度已用尽
+    Quota,
+    /// 模型或部署不存在
+    ModelNotFound,
+    Other,
+}
+
+impl ErrorCategory {
+    fn classify(status: reqwest::StatusCode, detail: &ErrorDetail) -> Self {
+        let code = detail
+            .code
+            .as_ref()
+            .and_then(|code| code.as_str())
+            .unwrap_or_default();
+        let kind = detail.kind.as_deref().unwrap_or_default();
+
+        if code == "insufficient_quota" || kind == "insufficient_quota" {
+            ErrorCategory::Quota
+        } else if code == "model_not_found"
+            || code == "DeploymentNotFound"
+            || kind == "not_found_error"
+            || status == reqwest::StatusCode::NOT_FOUND
+        {
+            ErrorCategory::ModelNotFound
+        } else if code == "invalid_api_key"
+            || kind == "authentication_error"
+            || kind == "permission_error"
+            || status == reqwest::StatusCode::UNAUTHORIZED
+            || status == reqwest::StatusCode::FORBIDDEN
+        {
+            ErrorCategory::Auth
+        } else {
+            ErrorCategory::Other
+        }
+    }
+}
+
+/// 将失败响应的响应体转换为错误说明，无法解析时原样返回
+fn describe_api_error(
+    status: reqwest::StatusCode,
+    body: &str,
+    model: &str,
+) -> (ErrorCategory, String) {
+    let Ok(ErrorResponse { error }) = serde_json::from_str::<ErrorResponse>(body) else {
+        return (ErrorCategory::Other, body.to_string());
+    };
+
+    let category = ErrorCategory::classify(status, &error);
+    let hint = match category {
+        ErrorCategory::Auth => {
+            "authentication failed — check openai_api_key (or GT_OPENAI_API_KEY / OPENAI_API_KEY)"
+                .to_string()
+        }
+        ErrorCategory::Quota => {
+            "quota exceeded — check the plan and billing details of the account".to_string()
+        }
+        ErrorCategory::ModelNotFound => {
+            format!("model '{}' not found — check Settings.model", model)
+        }
+        ErrorCategory::Other => return (category, error.message),
+    };
+    (category, format!("{} ({})", error.message, hint))
+}
+
+/// 单次重试的最长等待时间，服务端要求更长的 Retry-After 时也只等待这么久
+const MAX_BACKOFF: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// 指数退避的最大指数，500ms * 2^6 = 32s
+const MAX_BACKOFF_EXPONENT: u32 = 6;
+
+/// 解析 Retry-After 响应头，只支持秒数形式，HTTP 日期形式返回 None 并回退到指数退避
+fn parse_retry_after(value: &str) -> Option<std::time::Duration> {
+    value
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(|secs| std::time::Duration::from_secs(secs).min(MAX_BACKOFF))
+}
+
+/// 第 attempt 次失败后的重试等待时间：指数退避的一半加上随机的另一半，
+/// 避免多个冲突同时失败后在同一时刻重试
+fn backoff_with_jitter(attempt: u32) -> std::time::Duration {
+    let base = 500 * 2u64.pow(attempt.min(MAX_BACKOFF_EXPONENT));
+    let random = std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish();
+    std::time::Duration::from_millis(base / 2 + random % (base / 2 + 1)).min(MAX_BACKOFF)
+}
+
+/// 批量解决冲突时模型返回的 JSON
+#[derive(Deserialize)]
+struct BatchResolution {
+    resolutions: HashMap<String, String>,
+}
+
+/// 启用 explain 时模型返回的 JSON
+#[derive(Deserialize)]
+struct ExplainedResolution {
+    resolution: String,
+    #[serde(default)]
+    reasoning: String,
+}
+
+/// AI给出的解决方案
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Resolution {
+    /// 写入文件的内容
+    pub content: String,
+    /// 选择该方案的理由，只在启用 explain 时提供
+    pub reasoning: Option<String>,
+    /// 给出该方案的模型实际发送的请求次数，来自缓存、LFS 策略或批量请求时为 0
+    #[serde(skip)]
+    pub attempts: u32,
+    /// 给出该方案的模型，按 LFS 策略选择时为 None；旧版本写入的缓存中没有该字段
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+/// 流式解决冲突时传给回调的事件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamEvent<'a> {
+    /// 收到的一段回复内容
+    Token(&'a str),
+    /// 上一次尝试失败，开始第 attempt 次尝试（共 max_attempts 次），之前输出的内容作废
+    Retry { attempt: u32, max_attempts: u32 },
+    /// 主模型失败，改用备用模型重新生成，之前输出的内容作废
+    Fallback { model: &'a str },
+}
+
+/// 连通性检查的结果
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct CheckReport {
+    /// 请求的模型
+    pub requested_model: String,
+    /// 服务端在响应中返回的模型，部分服务不返回
+    pub model: Option<String>,
+    /// AI对示例冲突给出的解决方案
+    pub resolution: String,
+    /// 请求耗时（毫秒）
+    pub elapsed_ms: u64,
+}
+
+/// 请求消耗的 token 数
+#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl TokenUsage {
+    /// 按每千 token 的价格估算费用，两种价格都未配置时返回 None
+    pub fn estimated_cost(
+        &self,
+        prompt_price: Option<f64>,
+        completion_price: Option<f64>,
+    ) -> Option<f64> {
+        if prompt_price.is_none() && completion_price.is_none() {
+            return None;
+        }
+
+        let prompt = self.prompt_tokens as f64 / 1000.0 * prompt_price.unwrap_or(0.0);
+        let completion = self.completion_tokens as f64 / 1000.0 * completion_price.unwrap_or(0.0);
+        Some(prompt + completion)
+    }
+}
+
+impl std::ops::AddAssign for TokenUsage {
+    fn add_assign(&mut self, other: Self) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+    }
+}
+
+impl std::ops::Sub for TokenUsage {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self {
+            prompt_tokens: self.prompt_tokens.saturating_sub(other.prompt_tokens),
+            completion_tokens: self
+                .completion_tokens
+                .saturating_sub(other.completion_tokens),
+        }
+    }
+}
+
 pub struct ConflictResolver {
     client: Client,
     settings: Settings,
-    #[cfg(test)]
-    api_url: Option<String>,
+    provider: Box<dyn AiProvider>,
+    /// 本次运行中缓存的上下文窗口大小
+    context_window: OnceCell<u32>,
+    /// 本次运行中所有请求（包括失败的重试）累计消耗的 token
+    usage: Mutex<TokenUsage>,
+    /// 本次运行中由AI成功解决的冲突数
+    resolved_conflicts: AtomicUsize,
+    /// 每个由AI解决的冲突文件所用的模型
+    resolved_by: Mutex<HashMap<String, String>>,
+    /// 解决方案的磁盘缓存，未设置时每次都请求AI
+    cache: Option<ResolutionCache>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 struct ChatMessage {
     role: String,
     content: String,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone)]
 struct ChatRequest {
     model: String,
     messages: Vec<ChatMessage>,
     temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+    /// 流式响应默认不包含 token 用量，需要请求在最后一个事件中附带
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<StreamOptions>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct StreamOptions {
+    include_usage: bool,
 }
 
 #[derive(Deserialize)]
 struct ChatChoice {
     message: ChatMessage,
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
+
+/// 回复是否因达到 max_tokens 被截断，截断的内容写入文件会破坏文件，因此丢弃
+fn is_truncated(reason: Option<&str>, max_tokens_reason: &str) -> bool {
+    let truncated = reason == Some(max_tokens_reason);
+    if truncated {
+        warn!("AI response was cut off by max_tokens, discarding it");
+    }
+    truncated
+}
+
+#[derive(Deserialize)]
+struct ChatUsage {
+    #[serde(default)]
+    prompt_tokens: u64,
+    #[serde(default)]
+    completion_tokens: u64,
 }
 
 #[derive(Deserialize)]
 struct ChatResponse {
     choices: Vec<ChatChoice>,
+    #[serde(default)]
+    usage: Option<ChatUsage>,
 }
 
-impl ConflictResolver {
-    pub fn new(settings: Settings) -> Self {
-        Self {
-            client: Client::new(),
-            settings,
-            #[cfg(test)]
-            api_url: None,
-        }
+#[derive(Deserialize)]
+struct ChatDelta {
+    #[serde(default)]
+    content: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatStreamChoice {
+    delta: ChatDelta,
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
+
+/// 流式响应中每个 server-sent event 携带的数据
+#[derive(Deserialize)]
+struct ChatStreamChunk {
+    #[serde(default)]
+    choices: Vec<ChatStreamChoice>,
+    /// 请求了 include_usage 时，最后一个事件的 choices 为空，只携带用量
+    #[serde(default)]
+    usage: Option<ChatUsage>,
+}
+
+/// 解析流式响应中一个事件的数据，返回其中的增量内容和 token 用量
+fn parse_stream_delta(data: &str) -> Result<(Option<String>, TokenUsage)> {
+    let chunk: ChatStreamChunk = serde_json::from_str(data)
+        .map_err(|e| anyhow::anyhow!("Failed to parse stream chunk: {}, Data: {}", e, data))?;
+    let usage = chunk
+        .usage
+        .map(|usage| TokenUsage {
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+        })
+        .unwrap_or_default();
+    let Some(choice) = chunk.choices.into_iter().next() else {
+        return Ok((None, usage));
+    };
+    if is_truncated(choice.finish_reason.as_deref(), "length") {
+        return Err(anyhow::anyhow!("AI response exceeded max_tokens"));
     }
+    Ok((choice.delta.content, usage))
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct GeminiPart {
+    text: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct GeminiContent {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    role: Option<String>,
+    #[serde(default)]
+    parts: Vec<GeminiPart>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct GeminiGenerationConfig {
+    temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<u32>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct GeminiRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system_instruction: Option<GeminiContent>,
+    contents: Vec<GeminiContent>,
+    generation_config: GeminiGenerationConfig,
+}
+
+impl GeminiRequest {
+    /// 将 chat 格式的请求转换为 Gemini 的 contents/parts 格式
+    fn from_chat(request: &ChatRequest) -> Self {
+        let mut system_instruction = None;
+        let mut contents = Vec::new();
+
+        for message in &request.messages {
+            let parts = vec![GeminiPart {
+                text: message.content.clone(),
+            }];
+            match message.role.as_str() {
+                "system" => {
+                    system_instruction = Some(GeminiContent { role: None, parts });
+                }
+                "assistant" => contents.push(GeminiContent {
+                    role: Some("model".to_string()),
+                    parts,
+                }),
+                _ => contents.push(GeminiContent {
+                    role: Some("user".to_string()),
+                    parts,
+                }),
+            }
+        }
 
-    #[cfg(test)]
-    pub fn with_api_url(settings: Settings, api_url: String) -> Self {
         Self {
-            client: Client::new(),
-            settings,
-            api_url: Some(api_url),
+            system_instruction,
+            contents,
+            generation_config: GeminiGenerationConfig {
+                temperature: request.temperature,
+                max_output_tokens: request.max_tokens,
+            },
         }
     }
+}
 
-    fn extract_conflict_content(content: &str) -> String {
-        // 如果是大文件，只提取最相关的上下文
-        const MAX_CONTEXT_LENGTH: usize = 500; // 提取的最大长度
-        const CONTEXT_LINES: usize = 3; // 冲突附近要保留的上下文行数
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiCandidate {
+    content: GeminiContent,
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
 
-        let lines: Vec<&str> = content.lines().collect();
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiUsage {
+    #[serde(default)]
+    prompt_token_count: u64,
+    #[serde(default)]
+    candidates_token_count: u64,
+}
 
-        // 找到包含冲突标记的行
-        let mut conflict_start = None;
-        let mut conflict_end = None;
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiResponse {
+    #[serde(default)]
+    candidates: Vec<GeminiCandidate>,
+    #[serde(default)]
+    usage_metadata: Option<GeminiUsage>,
+}
 
-        for (i, line) in lines.iter().enumerate() {
-            if line.contains("<<<<<<<") {
-                conflict_start = Some(i);
-            } else if line.contains(">>>>>>>") {
-                conflict_end = Some(i);
+#[derive(Serialize, Debug)]
+struct AnthropicRequest {
+    model: String,
+    max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    messages: Vec<ChatMessage>,
+    temperature: f32,
+}
+
+/// Anthropic 要求必须指定回复的最大 token 数，未配置 max_tokens 时使用该值
+const ANTHROPIC_MAX_TOKENS: u32 = 4096;
+
+/// 调用 Anthropic 接口时使用的 API 版本
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+impl AnthropicRequest {
+    /// 将 chat 格式的请求转换为 messages 格式，system 消息单独放在顶层字段
+    fn from_chat(request: &ChatRequest) -> Self {
+        let mut system = None;
+        let mut messages = Vec::new();
+
+        for message in &request.messages {
+            if message.role == "system" {
+                system = Some(message.content.clone());
+            } else {
+                messages.push(ChatMessage {
+                    role: message.role.clone(),
+                    content: message.content.clone(),
+                });
             }
         }
 
-        // 如果找不到冲突标记，返回截断的原始内容
-        if conflict_start.is_none() || conflict_end.is_none() {
-            return if content.len() > MAX_CONTEXT_LENGTH {
-                format!("{}... (truncated)", &content[..MAX_CONTEXT_LENGTH])
-            } else {
-                content.to_string()
-            };
+        Self {
+            model: request.model.clone(),
+            max_tokens: request.max_tokens.unwrap_or(ANTHROPIC_MAX_TOKENS),
+            system,
+            messages,
+            temperature: request.temperature,
         }
+    }
+}
 
-        // 计算要包含的行范围
-        let start = conflict_start.unwrap().saturating_sub(CONTEXT_LINES);
-        let end = (conflict_end.unwrap() + CONTEXT_LINES + 1).min(lines.len());
+#[derive(Deserialize)]
+struct AnthropicContentBlock {
+    #[serde(default)]
+    text: String,
+}
 
-        // 提取冲突相关内容
-        let relevant_lines: Vec<&str> = lines[start..end].to_vec();
-        let result = relevant_lines.join("\n");
+#[derive(Deserialize)]
+struct AnthropicUsage {
+    #[serde(default)]
+    input_tokens: u64,
+    #[serde(default)]
+    output_tokens: u64,
+}
 
-        // 如果提取的内容仍然太长，进行截断
-        if result.len() > MAX_CONTEXT_LENGTH {
-            format!("{}... (truncated)", &result[..MAX_CONTEXT_LENGTH])
-        } else {
-            result
-        }
+#[derive(Deserialize)]
+struct AnthropicResponse {
+    #[serde(default)]
+    content: Vec<AnthropicContentBlock>,
+    #[serde(default)]
+    stop_reason: Option<String>,
+    #[serde(default)]
+    usage: Option<AnthropicUsage>,
+}
+
+/// AI 服务提供商的接口地址、认证方式以及请求和响应格式
+///
+/// 重试、超时等逻辑由 `ConflictResolver` 统一处理
+trait AiProvider: Send + Sync {
+    /// 官方 API 的基础地址
+    fn default_base_url(&self) -> &str;
+
+    /// 生成回复的接口地址
+    fn endpoint(&self, base_url: &str, model: &str) -> String;
+
+    /// 查询模型元数据的接口地址
+    fn model_info_endpoint(&self, base_url: &str, model: &str) -> String {
+        format!("{}/models/{}", base_url, model)
     }
 
-    pub async fn resolve_conflict(&self, conflict: &ConflictFile) -> Result<String> {
-        let system_prompt = "You are a Git merge conflict resolver. Analyze the conflict and choose the most appropriate resolution. Return ONLY the resolved content without any explanation.";
+    /// 添加认证相关的请求头
+    fn authorize(&self, builder: RequestBuilder, api_key: &str) -> RequestBuilder;
 
-        // 精简冲突描述，减少发送的文本量
-        // 提取 our_content 中的冲突内容
-        let our_content = Self::extract_conflict_content(&conflict.our_content);
-        let their_content = Self::extract_conflict_content(&conflict.their_content);
-        let base_content = conflict
-            .base_content
-            .as_ref()
-            .map(|content| Self::extract_conflict_content(content))
+    /// 将 chat 格式的请求转换为提供商的请求体
+    fn request_body(&self, builder: RequestBuilder, request: &ChatRequest) -> RequestBuilder;
+
+    /// 从响应中提取模型的回复和消耗的 token 数
+    fn parse_response(&self, body: &str) -> serde_json::Result<(Option<String>, TokenUsage)>;
+
+    /// 是否支持 OpenAI 兼容的流式响应
+    fn supports_streaming(&self) -> bool {
+        false
+    }
+}
+
+/// OpenAI 及兼容 chat completions 接口的服务
+struct OpenAiProvider;
+
+impl AiProvider for OpenAiProvider {
+    fn default_base_url(&self) -> &'static str {
+        "https://api.openai.com/v1"
+    }
+
+    fn endpoint(&self, base_url: &str, _model: &str) -> String {
+        format!("{}/chat/completions", base_url)
+    }
+
+    fn authorize(&self, builder: RequestBuilder, api_key: &str) -> RequestBuilder {
+        builder.header("Authorization", format!("Bearer {}", api_key))
+    }
+
+    fn request_body(&self, builder: RequestBuilder, request: &ChatRequest) -> RequestBuilder {
+        builder.json(request)
+    }
+
+    fn parse_response(&self, body: &str) -> serde_json::Result<(Option<String>, TokenUsage)> {
+        let response: ChatResponse = serde_json::from_str(body)?;
+        let usage = response
+            .usage
+            .map(|usage| TokenUsage {
+                prompt_tokens: usage.prompt_tokens,
+                completion_tokens: usage.completion_tokens,
+            })
             .unwrap_or_default();
+        let content = response
+            .choices
+            .into_iter()
+            .next()
+            .filter(|choice| !is_truncated(choice.finish_reason.as_deref(), "length"))
+            .map(|choice| choice.message.content);
+        Ok((content, usage))
+    }
 
-        let conflict_description = format!(
-            "Resolve this Git merge conflict in {}. Here are the conflicting parts:\n\n\
-            Our version: {}\n\n\
-            Their version: {}\n\n\
-            {}",
-            conflict.path,
-            our_content,
-            their_content,
-            if !base_content.is_empty() {
-                format!("Base version: {}", base_content)
-            } else {
-                String::new()
-            }
-        );
+    fn supports_streaming(&self) -> bool {
+        true
+    }
+}
 
-        let request = ChatRequest {
-            model: self.settings.model.clone(),
-            messages: vec![
-                ChatMessage {
-                    role: "system".to_string(),
-                    content: system_prompt.to_string(),
-                },
-                ChatMessage {
-                    role: "user".to_string(),
-                    content: conflict_description,
-                },
-            ],
-            temperature: 0.7,
-        };
+/// Google Gemini generateContent 接口
+struct GeminiProvider;
+
+impl AiProvider for GeminiProvider {
+    fn default_base_url(&self) -> &'static str {
+        "https://generativelanguage.googleapis.com/v1beta"
+    }
+
+    fn endpoint(&self, base_url: &str, model: &str) -> String {
+        format!("{}/models/{}:generateContent", base_url, model)
+    }
+
+    fn authorize(&self, builder: RequestBuilder, api_key: &str) -> RequestBuilder {
+        builder.header("x-goog-api-key", api_key)
+    }
+
+    fn request_body(&self, builder: RequestBuilder, request: &ChatRequest) -> RequestBuilder {
+        builder.json(&GeminiRequest::from_chat(request))
+    }
+
+    fn parse_response(&self, body: &str) -> serde_json::Result<(Option<String>, TokenUsage)> {
+        let response: GeminiResponse = serde_json::from_str(body)?;
+        let usage = response
+            .usage_metadata
+            .map(|usage| TokenUsage {
+                prompt_tokens: usage.prompt_token_count,
+                completion_tokens: usage.candidates_token_count,
+            })
+            .unwrap_or_default();
+        let content = response
+            .candidates
+            .into_iter()
+            .next()
+            .filter(|candidate| !is_truncated(candidate.finish_reason.as_deref(), "MAX_TOKENS"))
+            .map(|candidate| {
+                candidate
+                    .content
+                    .parts
+                    .into_iter()
+                    .map(|part| part.text)
+                    .collect()
+            });
+        Ok((content, usage))
+    }
+}
+
+/// Anthropic Claude messages 接口
+struct AnthropicProvider;
+
+impl AiProvider for AnthropicProvider {
+    fn default_base_url(&self) -> &'static str {
+        "https://api.anthropic.com/v1"
+    }
+
+    fn endpoint(&self, base_url: &str, _model: &str) -> String {
+        format!("{}/messages", base_url)
+    }
+
+    fn authorize(&self, builder: RequestBuilder, api_key: &str) -> RequestBuilder {
+        builder
+            .header("x-api-key", api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+    }
+
+    fn request_body(&self, builder: RequestBuilder, request: &ChatRequest) -> RequestBuilder {
+        builder.json(&AnthropicRequest::from_chat(request))
+    }
+
+    fn parse_response(&self, body: &str) -> serde_json::Result<(Option<String>, TokenUsage)> {
+        let response: AnthropicResponse = serde_json::from_str(body)?;
+        let usage = response
+            .usage
+            .map(|usage| TokenUsage {
+                prompt_tokens: usage.input_tokens,
+                completion_tokens: usage.output_tokens,
+            })
+            .unwrap_or_default();
+        if is_truncated(response.stop_reason.as_deref(), "max_tokens") {
+            return Ok((None, usage));
+        }
+        let content = response.content.into_iter().next().map(|block| block.text);
+        Ok((content, usage))
+    }
+}
+
+/// 本地运行的 Ollama，使用 OpenAI 兼容的 chat completions 接口
+struct OllamaProvider;
+
+impl AiProvider for OllamaProvider {
+    fn default_base_url(&self) -> &'static str {
+        "http://localhost:11434/v1"
+    }
+
+    fn endpoint(&self, base_url: &str, model: &str) -> String {
+        OpenAiProvider.endpoint(base_url, model)
+    }
+
+    fn authorize(&self, builder: RequestBuilder, api_key: &str) -> RequestBuilder {
+        OpenAiProvider.authorize(builder, api_key)
+    }
+
+    fn request_body(&self, builder: RequestBuilder, request: &ChatRequest) -> RequestBuilder {
+        OpenAiProvider.request_body(builder, request)
+    }
+
+    fn parse_response(&self, body: &str) -> serde_json::Result<(Option<String>, TokenUsage)> {
+        OpenAiProvider.parse_response(body)
+    }
+
+    fn supports_streaming(&self) -> bool {
+        OpenAiProvider.supports_streaming()
+    }
+}
+
+/// Azure OpenAI，按部署名称访问 chat completions 接口，请求和响应格式与 OpenAI 相同
+struct AzureProvider {
+    /// 资源地址，例如 `https://my-resource.openai.azure.com`
+    endpoint: String,
+    /// 部署名称，未设置时使用模型名
+    deployment: Option<String>,
+    api_version: String,
+}
+
+impl AiProvider for AzureProvider {
+    fn default_base_url(&self) -> &str {
+        self.endpoint.trim_end_matches('/')
+    }
+
+    fn endpoint(&self, base_url: &str, model: &str) -> String {
+        format!(
+            "{}/openai/deployments/{}/chat/completions?api-version={}",
+            base_url,
+            self.deployment.as_deref().unwrap_or(model),
+            self.api_version
+        )
+    }
+
+    fn model_info_endpoint(&self, base_url: &str, model: &str) -> String {
+        format!(
+            "{}/openai/models/{}?api-version={}",
+            base_url, model, self.api_version
+        )
+    }
+
+    fn authorize(&self, builder: RequestBuilder, api_key: &str) -> RequestBuilder {
+        builder.header("api-key", api_key)
+    }
+
+    fn request_body(&self, builder: RequestBuilder, request: &ChatRequest) -> RequestBuilder {
+        OpenAiProvider.request_body(builder, request)
+    }
+
+    fn parse_response(&self, body: &str) -> serde_json::Result<(Option<String>, TokenUsage)> {
+        OpenAiProvider.parse_response(body)
+    }
+
+    fn supports_streaming(&self) -> bool {
+        OpenAiProvider.supports_streaming()
+    }
+}
+
+/// 根据配置选择提供商的实现
+fn provider_for(settings: &Settings) -> Box<dyn AiProvider> {
+    match settings.provider {
+        Provider::OpenAi => Box::new(OpenAiProvider),
+        Provider::Gemini => Box::new(GeminiProvider),
+        Provider::Anthropic => Box::new(AnthropicProvider),
+        Provider::Ollama => Box::new(OllamaProvider),
+        Provider::Azure => Box::new(AzureProvider {
+            endpoint: settings.azure_endpoint.clone().unwrap_or_default(),
+            deployment: settings.azure_deployment.clone(),
+            api_version: settings.azure_api_version.clone(),
+        }),
+    }
+}
+
+/// 建立连接的超时时间（秒）
+const CONNECT_TIMEOUT_SECS: u64 = 10;
+
+/// 每个主机保留的空闲连接数，批量解决冲突时复用连接以避免重复握手
+const POOL_MAX_IDLE_PER_HOST: usize = 8;
+
+/// 构建在整个运行期间复用的 HTTP 客户端
+fn build_client(settings: &Settings) -> Client {
+    Client::builder()
+        .pool_max_idle_per_host(POOL_MAX_IDLE_PER_HOST)
+        .connect_timeout(std::time::Duration::from_secs(CONNECT_TIMEOUT_SECS))
+        .timeout(std::time::Duration::from_secs(settings.timeout_seconds))
+        .build()
+        .unwrap_or_else(|e| {
+            warn!("Failed to build HTTP client, using defaults: {}", e);
+            Client::new()
+        })
+}
+
+impl ConflictResolver {
+    pub fn new(settings: Settings) -> Self {
+        Self {
+            client: build_client(&settings),
+            provider: provider_for(&settings),
+            settings,
+            context_window: OnceCell::new(),
+            usage: Mutex::new(TokenUsage::default()),
+            resolved_conflicts: AtomicUsize::new(0),
+            resolved_by: Mutex::new(HashMap::new()),
+            cache: None,
+        }
+    }
+
+    /// 使用磁盘缓存，解决冲突前先查找缓存，成功后写回
+    pub fn with_cache(mut self, cache: ResolutionCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// 查找缓存的解决方案
+    fn cached_resolution(&self, conflict: &ConflictFile) -> Option<Resolution> {
+        let cache = self.cache.as_ref()?;
+        let resolution = cache.get(&ResolutionCache::key(conflict, &self.settings.model))?;
+        // 启用 explain 时需要理由，没有理由的缓存不可用
+        if self.settings.explain && resolution.reasoning.is_none() {
+            return None;
+        }
+        info!("Using cached resolution for {}", conflict.path);
+        Some(resolution)
+    }
+
+    /// 从缓存中删除冲突的解决方案，例如用户拒绝了该方案时
+    pub fn forget_resolution(&self, conflict: &ConflictFile) {
+        if let Some(cache) = &self.cache {
+            let key = ResolutionCache::key(conflict, &self.settings.model);
+            if let Err(e) = cache.remove(&key) {
+                warn!(
+                    "Failed to remove cached resolution for {}: {}",
+                    conflict.path, e
+                );
+            }
+        }
+    }
+
+    /// 将解决方案写入缓存，写入失败不影响冲突的解决
+    fn store_resolution(&self, conflict: &ConflictFile, resolution: &Resolution) {
+        if let Some(cache) = &self.cache {
+            let key = ResolutionCache::key(conflict, &self.settings.model);
+            if let Err(e) = cache.put(&key, resolution) {
+                warn!("Failed to cache resolution for {}: {}", conflict.path, e);
+            }
+        }
+    }
+
+    /// 本次运行中累计消耗的 token 数
+    pub fn usage(&self) -> TokenUsage {
+        *self.usage.lock().unwrap()
+    }
+
+    /// 解决该冲突文件所用的模型，使用缓存时为缓存中记录的模型；没有由AI解决时返回 None
+    pub fn resolved_by(&self, path: &str) -> Option<String> {
+        self.resolved_by.lock().unwrap().get(path).cloned()
+    }
+
+    // 缓存的解决方案由哪个模型给出，旧的缓存条目没有记录时只能认为是配置的模型
+    fn cached_model<'a>(&'a self, resolution: &'a Resolution) -> &'a str {
+        resolution.model.as_deref().unwrap_or(&self.settings.model)
+    }
+
+    fn record_model(&self, conflict: &ConflictFile, model: &str) {
+        self.resolved_by
+            .lock()
+            .unwrap()
+            .insert(conflict.path.clone(), model.to_string());
+    }
+
+    /// 本次运行中由AI成功解决的冲突数
+    pub fn resolved_conflicts(&self) -> usize {
+        self.resolved_conflicts.load(Ordering::Relaxed)
+    }
+
+    /// 按配置的每千 token 价格估算累计费用，未配置价格时返回 None
+    pub fn estimated_cost(&self) -> Option<f64> {
+        self.usage().estimated_cost(
+            self.settings.prompt_price_per_1k,
+            self.settings.completion_price_per_1k,
+        )
+    }
+
+    /// 获取模型的上下文窗口大小，结果在本次运行中缓存
+    ///
+    /// 优先使用配置中指定的值，其次查询服务端的模型元数据，
+    /// 查询失败时回退到内置的静态表
+    pub async fn context_window(&self) -> u32 {
+        *self
+            .context_window
+            .get_or_init(|| async {
+                if let Some(window) = self.settings.context_window {
+                    return window;
+                }
+
+                match self.query_context_window().await {
+                    Ok(window) => {
+                        info!(
+                            "Context window for model {} from endpoint: {}",
+                            self.settings.model, window
+                        );
+                        window
+                    }
+                    Err(e) => {
+                        let window = static_context_window(&self.settings.model);
+                        warn!(
+                            "Failed to query context window for model {}: {}. Using static value {}",
+                            self.settings.model, e, window
+                        );
+                        window
+                    }
+                }
+            })
+            .await
+    }
+
+    /// 查询模型元数据的地址
+    fn model_info_endpoint(&self) -> String {
+        if let Some(url) = &self.settings.model_info_url {
+            return url.clone();
+        }
+
+        self.provider
+            .model_info_endpoint(self.base_url(), &self.settings.model)
+    }
+
+    /// API 基础地址，优先使用配置中的 base_url
+    fn base_url(&self) -> &str {
+        self.settings
+            .base_url
+            .as_deref()
+            .map(|url| url.trim_end_matches('/'))
+            .unwrap_or_else(|| self.provider.default_base_url())
+    }
+
+    /// 向服务端查询模型的上下文窗口大小
+    async fn query_context_window(&self) -> Result<u32> {
+        let url = self.model_info_endpoint();
+        debug!("Querying model metadata: {}", url);
+
+        let mut builder = self
+            .client
+            .get(&url)
+            .timeout(std::time::Duration::from_secs(
+                self.settings.timeout_seconds,
+            ));
+        if let Some(api_key) = &self.settings.openai_api_key {
+            builder = self.provider.authorize(builder, api_key);
+        }
+
+        let response = builder.send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!(
+                "Model metadata request failed with status {}",
+                response.status()
+            ));
+        }
+
+        let metadata: serde_json::Value = response.json().await?;
+        CONTEXT_WINDOW_KEYS
+            .iter()
+            .find_map(|key| metadata.get(*key).and_then(|value| value.as_u64()))
+            .map(|window| window.min(u32::MAX as u64) as u32)
+            .ok_or_else(|| anyhow::anyhow!("Model metadata does not include a context window"))
+    }
+
+    /// 每段冲突内容可使用的最大长度
+    async fn max_context_length(&self) -> usize {
+        if !self.settings.context_window_from_model && self.settings.context_window.is_none() {
+            return MAX_CONTEXT_LENGTH;
+        }
+
+        // 为回复预留一半窗口，按每个 token 约 4 个字符估算，
+        // 剩余部分由我们的版本、他们的版本和基础版本平分
+        let window = self.context_window().await as usize;
+        (window / 2 * 4 / 3).max(MAX_CONTEXT_LENGTH)
+    }
+
+    /// 小文件原样发送，只有超过 max_full_context_bytes 的文件才提取冲突附近的内容
+    fn conflict_context(&self, content: &str, max_length: usize) -> String {
+        if content.len() <= self.settings.max_full_context_bytes {
+            content.to_string()
+        } else {
+            Self::extract_conflict_content(content, max_length)
+        }
+    }
+
+    fn extract_conflict_content(content: &str, max_length: usize) -> String {
+        // 如果是大文件，只提取最相关的上下文
+        const CONTEXT_LINES: usize = 3; // 冲突附近要保留的上下文行数
+
+        let lines: Vec<&str> = content.lines().collect();
+
+        // 找到包含冲突标记的行
+        let mut conflict_start = None;
+        let mut conflict_end = None;
+
+        for (i, line) in lines.iter().enumerate() {
+            if line.contains("<<<<<<<") {
+                conflict_start = Some(i);
+            } else if line.contains(">>>>>>>") {
+                conflict_end = Some(i);
+            }
+        }
+
+        // 如果找不到冲突标记，返回截断的原始内容
+        if conflict_start.is_none() || conflict_end.is_none() {
+            return if content.len() > max_length {
+                format!(
+                    "{}... (truncated)",
+                    truncate_at_char_boundary(content, max_length)
+                )
+            } else {
+                content.to_string()
+            };
+        }
+
+        // 计算要包含的行范围
+        let start = conflict_start.unwrap().saturating_sub(CONTEXT_LINES);
+        let end = (conflict_end.unwrap() + CONTEXT_LINES + 1).min(lines.len());
+
+        // 提取冲突相关内容
+        let relevant_lines: Vec<&str> = lines[start..end].to_vec();
+        let result = relevant_lines.join("\n");
+
+        // 如果提取的内容仍然太长，进行截断
+        if result.len() > max_length {
+            format!(
+                "{}... (truncated)",
+                truncate_at_char_boundary(&result, max_length)
+            )
+        } else {
+            result
+        }
+    }
+
+    /// 向配置的服务发送一个很小的示例冲突，一次性验证 API 密钥、基础地址、模型和网络
+    ///
+    /// 只请求一次，不重试也不使用缓存，便于在合并前快速发现配置问题
+    pub async fn check(&self) -> Result<CheckReport> {
+        let conflict = ConflictFile {
+            path: "git-tools-check.txt".to_string(),
+            raw_path: b"git-tools-check.txt".to_vec(),
+            our_content: "hello\n".to_string(),
+            their_content: "hello world\n".to_string(),
+            base_content: Some("hello\n".to_string()),
+            marked_content: None,
+            is_binary: false,
+            kind: ConflictKind::Content,
+        };
+
+        let model = self.settings.model.clone();
+        let request = ChatRequest {
+            model: model.clone(),
+            messages: vec![
+                ChatMessage {
+                    role: "system".to_string(),
+                    content: self.prompt_templates()[0].clone(),
+                },
+                ChatMessage {
+                    role: "user".to_string(),
+                    content: self.describe_conflict(&conflict).await,
+                },
+            ],
+            temperature: self.settings.temperature,
+            max_tokens: self.settings.max_tokens,
+            stream: false,
+            stream_options: None,
+        };
+        let timeout = std::time::Duration::from_secs(self.settings.timeout_seconds);
+
+        let started = std::time::Instant::now();
+        let response = self
+            .send_request(&self.endpoint(&model), &request, timeout)
+            .await?;
+        let response_text = response
+            .text()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to get response text: {}", e))?;
+        let elapsed_ms = started.elapsed().as_millis() as u64;
+        debug!("AI API response: {}", response_text);
+
+        let resolution = self.parse_response_text(&response_text)?;
+        let echoed_model = serde_json::from_str::<serde_json::Value>(&response_text)
+            .ok()
+            .and_then(|body| {
+                RESPONSE_MODEL_KEYS
+                    .iter()
+                    .find_map(|key| body.get(*key)?.as_str().map(str::to_string))
+            });
+
+        Ok(CheckReport {
+            requested_model: model,
+            model: echoed_model,
+            resolution,
+            elapsed_ms,
+        })
+    }
+
+    /// 是否启用了流式输出
+    pub fn streaming(&self) -> bool {
+        self.settings.stream
+    }
+
+    /// 是否将多个冲突合并到一个请求中解决
+    pub fn batch(&self) -> bool {
+        self.settings.batch
+    }
+
+    /// 同时请求解决方案的冲突数
+    pub fn concurrency(&self) -> usize {
+        self.settings.concurrency.max(1)
+    }
+
+    /// 是否要求AI说明解决方案的理由
+    pub fn explain(&self) -> bool {
+        self.settings.explain
+    }
+
+    /// 是否只输出解决方案相对于我们的版本的差异
+    pub fn show_diff(&self) -> bool {
+        self.settings.show_diff
+    }
+
+    /// 冲突内容合计的最大字节数，超过时不发送给AI
+    pub fn max_conflict_bytes(&self) -> usize {
+        self.settings.max_conflict_bytes
+    }
+
+    /// 冲突能否交给AI解决：路径是有效的 UTF-8，不是二进制文件，没有一方删除文件，且不超过大小限制
+    fn can_resolve(&self, conflict: &ConflictFile) -> bool {
+        conflict.has_utf8_path()
+            && !conflict.is_binary
+            && !conflict.is_deletion()
+            && conflict.content_len() <= self.settings.max_conflict_bytes
+    }
+
+    /// 配置的模型
+    pub fn model(&self) -> &str {
+        &self.settings.model
+    }
+
+    /// 配置的审计日志文件
+    pub fn audit_log(&self) -> Option<&std::path::Path> {
+        self.settings.audit_log.as_deref()
+    }
+
+    /// 配置了审计日志时，记录已应用到冲突文件的解决方案和实际给出该方案的模型
+    pub fn write_audit_entry(
+        &self,
+        repo: &std::path::Path,
+        conflict: &ConflictFile,
+        resolution: &str,
+    ) -> Result<()> {
+        let Some(log) = self.audit_log() else {
+            return Ok(());
+        };
+        let model = self.resolved_by(&conflict.path);
+        let entry = audit::AuditEntry::new(repo, &conflict.path, model.as_deref(), resolution);
+        audit::append(log, &entry)
+    }
+
+    /// 将多个冲突打包到一个请求中解决，返回路径到解决方案的映射
+    ///
+    /// 二进制文件、一方删除了文件以及超过 max_conflict_bytes 的冲突不会出现在结果中，
+    /// LFS 指针按策略直接处理。批量响应无法解析，
+    /// 或缺少某些文件的有效解决方案时，对这些文件逐个调用 `resolve_conflict`，
+    /// 仍然失败的文件不会出现在结果中
+    pub async fn resolve_conflicts_batch(
+        &self,
+        conflicts: &[ConflictFile],
+    ) -> HashMap<String, String> {
+        let mut resolutions = HashMap::new();
+        let mut pending = Vec::new();
+        for conflict in conflicts
+            .iter()
+            .filter(|conflict| self.can_resolve(conflict) && !conflict.is_lfs_pointer())
+        {
+            match self.cached_resolution(conflict) {
+                Some(resolution) => {
+                    self.record_model(conflict, self.cached_model(&resolution));
+                    resolutions.insert(conflict.path.clone(), resolution.content);
+                }
+                None => pending.push(conflict),
+            }
+        }
+
+        // 只有一个冲突时批量请求没有意义
+        if pending.len() > 1 {
+            match self.try_resolve_batch(&pending).await {
+                Ok(batch) => {
+                    for conflict in &pending {
+                        match batch.get(&conflict.path) {
+                            Some(resolution) if check_resolution(conflict, resolution).is_ok() => {
+                                self.resolved_conflicts.fetch_add(1, Ordering::Relaxed);
+                                self.record_model(conflict, &self.settings.model);
+                                self.store_resolution(
+                                    conflict,
+                                    &Resolution {
+                                        content: resolution.clone(),
+                                        reasoning: None,
+                                        attempts: 0,
+                                        model: Some(self.settings.model.clone()),
+                                    },
+                                );
+                                resolutions.insert(conflict.path.clone(), resolution.clone());
+                            }
+                            _ => warn!(
+                                "Batch response has no valid resolution for {}",
+                                conflict.path
+                            ),
+                        }
+                    }
+                }
+                Err(e) => warn!(
+                    "Batch resolution failed, falling back to per-file resolution: {}",
+                    e
+                ),
+            }
+        }
+
+        for conflict in conflicts
+            .iter()
+            .filter(|conflict| self.can_resolve(conflict))
+        {
+            if resolutions.contains_key(&conflict.path) {
+                continue;
+            }
+            match self.resolve_conflict(conflict).await {
+                Ok(resolution) => {
+                    resolutions.insert(conflict.path.clone(), resolution);
+                }
+                Err(e) => warn!("Failed to resolve conflict in {}: {}", conflict.path, e),
+            }
+        }
+
+        resolutions
+    }
+
+    /// 发送一次批量请求并解析返回的 JSON，不进行重试
+    async fn try_resolve_batch(
+        &self,
+        conflicts: &[&ConflictFile],
+    ) -> Result<HashMap<String, String>> {
+        let mut content = String::new();
+        for conflict in conflicts {
+            content.push_str(&format!(
+                "=== FILE: {} ===\n{}\n\n",
+                conflict.path,
+                self.describe_conflict(conflict).await
+            ));
+        }
+
+        let request = ChatRequest {
+            model: self.settings.model.clone(),
+            messages: vec![
+                ChatMessage {
+                    role: "system".to_string(),
+                    content: BATCH_SYSTEM_PROMPT.to_string(),
+                },
+                ChatMessage {
+                    role: "user".to_string(),
+                    content,
+                },
+            ],
+            temperature: self.settings.temperature,
+            max_tokens: self.settings.max_tokens,
+            stream: false,
+            stream_options: None,
+        };
+
+        info!(
+            "Resolving {} conflicts in one batch request",
+            conflicts.len()
+        );
+        let timeout = std::time::Duration::from_secs(self.settings.timeout_seconds);
+        let response = self
+            .try_resolve(&self.endpoint(&self.settings.model), &request, timeout)
+            .await?;
+        let batch: BatchResolution = parse_lenient_json(&response)?;
+
+        Ok(batch.resolutions)
+    }
+
+    pub async fn resolve_conflict(&self, conflict: &ConflictFile) -> Result<String> {
+        Ok(self.resolve(conflict, None).await?.content)
+    }
+
+    /// 获取解决方案和实际发送的请求次数，启用 explain 时同时返回AI给出的理由
+    pub async fn resolve_conflict_explained(&self, conflict: &ConflictFile) -> Result<Resolution> {
+        self.resolve(conflict, None).await
+    }
+
+    /// 以流式方式获取解决方案，每收到一段内容就以 `StreamEvent::Token` 调用 on_event，返回完整的解决方案
+    ///
+    /// 提供商不支持流式响应时，在收到完整回复后调用一次。重试或改用备用模型前会先发送
+    /// `Retry` 或 `Fallback` 事件，之后的内容是新的回复
+    pub async fn resolve_conflict_streaming(
+        &self,
+        conflict: &ConflictFile,
+        on_event: &mut dyn FnMut(StreamEvent),
+    ) -> Result<String> {
+        Ok(self.resolve(conflict, Some(on_event)).await?.content)
+    }
+
+    async fn resolve(
+        &self,
+        conflict: &ConflictFile,
+        mut on_event: Option<&mut dyn FnMut(StreamEvent)>,
+    ) -> Result<Resolution> {
+        // 解决方案按路径写回工作目录，无效字节被替换后的路径会指向错误的文件
+        if !conflict.has_utf8_path() {
+            return Err(anyhow::anyhow!(
+                "{} is not a valid UTF-8 path and cannot be resolved by AI",
+                conflict.path
+            ));
+        }
+
+        if conflict.is_binary {
+            return Err(anyhow::anyhow!(
+                "{} is a binary file and cannot be resolved by AI",
+                conflict.path
+            ));
+        }
+
+        // 一方删除了文件时只能由用户决定保留还是删除
+        if conflict.is_deletion() {
+            return Err(anyhow::anyhow!(
+                "{} was deleted on one side ({:?}) and cannot be resolved by AI",
+                conflict.path,
+                conflict.kind
+            ));
+        }
+
+        // 生成文件或压缩后的文件可能非常大，一次请求就会耗尽上下文窗口和预算
+        if conflict.content_len() > self.settings.max_conflict_bytes {
+            return Err(anyhow::anyhow!(
+                "{} has {} bytes of conflicting content, exceeding max_conflict_bytes ({})",
+                conflict.path,
+                conflict.content_len(),
+                self.settings.max_conflict_bytes
+            ));
+        }
+
+        // LFS 指针只是指向对象存储的文本，按策略选择一方，不发送给AI
+        if conflict.is_lfs_pointer() {
+            let pointer = match self.settings.lfs_policy {
+                LfsPolicy::Ours => &conflict.our_content,
+                LfsPolicy::Theirs => &conflict.their_content,
+            };
+            info!(
+                "Resolved LFS pointer conflict in {} by policy {:?}",
+                conflict.path, self.settings.lfs_policy
+            );
+            if let Some(on_event) = on_event {
+                on_event(StreamEvent::Token(pointer));
+            }
+            return Ok(Resolution {
+                content: pointer.clone(),
+                reasoning: None,
+                attempts: 0,
+                model: None,
+            });
+        }
+
+        if let Some(resolution) = self.cached_resolution(conflict) {
+            self.record_model(conflict, self.cached_model(&resolution));
+            if let Some(on_event) = on_event {
+                on_event(StreamEvent::Token(&resolution.content));
+            }
+            return Ok(resolution);
+        }
+
+        let primary = self.settings.model.as_str();
+        let (mut resolution, model) = match self
+            .resolve_with_model(conflict, primary, on_event.as_deref_mut())
+            .await
+        {
+            Ok(resolution) => (resolution, primary),
+            Err(e) => match self.settings.fallback_model.as_deref() {
+                // 客户端错误（如认证失败）换用其他模型也无法解决
+                Some(fallback) if !is_client_error(&e) => {
+                    warn!(
+                        "Model {} failed to resolve {}: {}. Falling back to {}",
+                        primary, conflict.path, e, fallback
+                    );
+                    if let Some(on_event) = on_event.as_deref_mut() {
+                        on_event(StreamEvent::Fallback { model: fallback });
+                    }
+                    let resolution = self
+                        .resolve_with_model(conflict, fallback, on_event)
+                        .await?;
+                    (resolution, fallback)
+                }
+                _ => return Err(e),
+            },
+        };
+
+        // attempts 作为结构化字段记录，便于按日志统计重试次数来调整 max_retries
+        info!(
+            attempts = resolution.attempts,
+            max_retries = self.settings.max_retries,
+            "Conflict in {} resolved by model {} after {} attempt(s)",
+            conflict.path,
+            model,
+            resolution.attempts
+        );
+        self.resolved_conflicts.fetch_add(1, Ordering::Relaxed);
+        self.record_model(conflict, model);
+        resolution.model = Some(model.to_string());
+        self.store_resolution(conflict, &resolution);
+        Ok(resolution)
+    }
+
+    /// 使用指定模型解决冲突，失败时按配置重试
+    async fn resolve_with_model<'a>(
+        &self,
+        conflict: &ConflictFile,
+        model: &str,
+        mut on_event: Option<&mut (dyn FnMut(StreamEvent) + 'a)>,
+    ) -> Result<Resolution> {
+        let templates = self.prompt_templates();
+        let streaming = on_event.is_some() && self.provider.supports_streaming();
+        let conflict_description = self.describe_conflict(conflict).await;
+
+        let request = ChatRequest {
+            model: model.to_string(),
+            messages: vec![
+                ChatMessage {
+                    role: "system".to_string(),
+                    content: templates[0].clone(),
+                },
+                ChatMessage {
+                    role: "user".to_string(),
+                    content: conflict_description,
+                },
+            ],
+            temperature: self.settings.temperature,
+            max_tokens: self.settings.max_tokens,
+            stream: streaming,
+            stream_options: streaming.then_some(StreamOptions {
+                include_usage: true,
+            }),
+        };
+
+        let url = self.endpoint(model);
+
+        debug!("Request: {:?}", request);
+
+        // 配置请求超时
+        let timeout = std::time::Duration::from_secs(self.settings.timeout_seconds);
+
+        // 添加重试逻辑
+        let mut attempts = 0;
+        let max_retries = self.settings.max_retries;
+
+        while attempts <= max_retries {
+            attempts += 1;
+            info!(
+                "Attempt {}/{} to resolve conflict for file: {}",
+                attempts,
+                max_retries + 1,
+                conflict.path
+            );
+            // 之前输出的是失败的回复，告知调用方接下来是新的回复
+            if attempts > 1 {
+                if let Some(on_event) = on_event.as_deref_mut() {
+                    on_event(StreamEvent::Retry {
+                        attempt: attempts,
+                        max_attempts: max_retries + 1,
+                    });
+                }
+            }
+
+            // 启用轮换时每次重试使用下一个模板，系统提示词固定为第一条消息
+            let template_index = if self.settings.retry_different_prompt {
+                (attempts as usize - 1) % templates.len()
+            } else {
+                0
+            };
+            let mut request = request.clone();
+            request.messages[0].content = if self.settings.explain {
+                format!("{}\n\n{}", templates[template_index], EXPLAIN_INSTRUCTION)
+            } else {
+                templates[template_index].clone()
+            };
+
+            let started = std::time::Instant::now();
+            let result = match on_event.as_deref_mut() {
+                Some(on_event) if streaming => {
+                    self.try_resolve_streaming(&url, &request, timeout, on_event)
+                        .await
+                }
+                Some(on_event) => self
+                    .try_resolve(&url, &request, timeout)
+                    .await
+                    .inspect(|resolution| on_event(StreamEvent::Token(resolution))),
+                None => self.try_resolve(&url, &request, timeout).await,
+            };
+
+            // 无法解析的回复、残留冲突标记或格式无效的解决方案视为失败，进入重试
+            let result = result
+                .and_then(|text| self.parse_resolution(&text))
+                .and_then(|resolution| {
+                    check_resolution(conflict, &resolution.content).map(|_| resolution)
+                });
+
+            match result {
+                Ok(mut resolution) => {
+                    resolution.attempts = attempts;
+                    if self.settings.retry_different_prompt {
+                        info!(
+                            "Conflict in {} resolved with prompt template {}",
+                            conflict.path,
+                            template_index + 1
+                        );
+                    }
+                    return Ok(resolution);
+                }
+                Err(e) => {
+                    // 状态码和耗时作为结构化字段记录，便于从日志区分超时、限流和服务端错误；
+                    // 没有状态码说明请求未得到响应（连接失败或超时）或回复无效
+                    let elapsed_ms = started.elapsed().as_millis() as u64;
+                    let timed_out = started.elapsed() >= timeout;
+                    let api_error = e.downcast_ref::<ApiError>();
+                    let status = api_error.map(|api_error| api_error.status().as_u16());
+                    if let Some(api_error) = api_error {
+                        if !api_error.is_retryable() {
+                            error!(
+                                attempt = attempts,
+                                status,
+                                elapsed_ms,
+                                "Non-retryable API error, giving up: {}",
+                                api_error
+                            );
+                            return Err(e);
+                        }
+                    }
+
+                    if attempts > max_retries {
+                        error!(
+                            attempt = attempts,
+                            status,
+                            elapsed_ms,
+                            timed_out,
+                            "Failed to get AI resolution after {} attempts: {}",
+                            attempts,
+                            e
+                        );
+                        return Err(anyhow::anyhow!(
+                            "Failed to get AI resolution after {} attempts: {}",
+                            attempts,
+                            e
+                        ));
+                    }
+
+                    // 被限流时按服务端要求的时间等待，否则使用带随机抖动的指数退避
+                    let delay = match e.downcast_ref::<ApiError>() {
+                        Some(ApiError::RateLimited {
+                            retry_after: Some(retry_after),
+                            ..
+                        }) => *retry_after,
+                        _ => backoff_with_jitter(attempts),
+                    };
+                    warn!(
+                        attempt = attempts,
+                        status,
+                        elapsed_ms,
+                        timed_out,
+                        "Attempt {} failed after {} ms: {}. Retrying in {:?}...",
+                        attempts,
+                        elapsed_ms,
+                        e,
+                        delay
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+            }
+        }
+
+        // 不应该到达这里，但为了编译通过
+        Err(anyhow::anyhow!("Failed to get AI resolution"))
+    }
+
+    /// 解析AI的回复，启用 explain 时从 JSON 中取出解决方案和理由
+    fn parse_resolution(&self, text: &str) -> Result<Resolution> {
+        if !self.settings.explain {
+            return Ok(Resolution {
+                content: text.to_string(),
+                reasoning: None,
+                attempts: 0,
+                model: None,
+            });
+        }
+
+        let explained: ExplainedResolution = parse_lenient_json(text)?;
+        Ok(Resolution {
+            content: explained.resolution,
+            reasoning: Some(explained.reasoning),
+            attempts: 0,
+            model: None,
+        })
+    }
+
+    /// 生成发送给AI的冲突描述，能从扩展名识别文件语言时附加相应的提示
+    async fn describe_conflict(&self, conflict: &ConflictFile) -> String {
+        let description = self.describe_conflict_content(conflict).await;
+        match language::language_hint(&conflict.path) {
+            Some(hint) => format!("{}\n\n{}", description, hint),
+            None => description,
+        }
+    }
+
+    // 冲突各方的内容，只保留冲突附近的内容以减少发送的文本量
+    async fn describe_conflict_content(&self, conflict: &ConflictFile) -> String {
+        let max_length = self.max_context_length().await;
+        let our_content = self.conflict_context(&conflict.our_content, max_length);
+        let their_content = self.conflict_context(&conflict.their_content, max_length);
+        let base_content = conflict
+            .base_content
+            .as_ref()
+            .map(|content| self.conflict_context(content, max_length))
+            .unwrap_or_default();
+
+        match &conflict.marked_content {
+            // diff3 风格的冲突标记中已包含共同祖先的内容
+            Some(marked) if self.settings.conflict_style == ConflictStyle::Diff3 => format!(
+                "Resolve this Git merge conflict in {}. The file contains diff3-style markers: \
+                <<<<<<< starts our version, ||||||| starts the common ancestor, \
+                ======= starts their version.\n\n{}",
+                conflict.path,
+                self.conflict_context(marked, max_length * 3)
+            ),
+            _ => format!(
+                "Resolve this Git merge conflict in {}. Here are the conflicting parts:\n\n\
+                Our version: {}\n\n\
+                Their version: {}\n\n\
+                {}",
+                conflict.path,
+                our_content,
+                their_content,
+                if !base_content.is_empty() {
+                    format!("Base version: {}", base_content)
+                } else {
+                    String::new()
+                }
+            ),
+        }
+    }
+
+    /// 系统提示词模板，未配置时使用内置模板，system_prompt 替换其中的默认模板
+    fn prompt_templates(&self) -> Vec<String> {
+        if !self.settings.prompt_templates.is_empty() {
+            return self.settings.prompt_templates.clone();
+        }
+
+        let mut templates: Vec<String> = DEFAULT_PROMPT_TEMPLATES
+            .iter()
+            .map(|template| template.to_string())
+            .collect();
+        if let Some(system_prompt) = &self.settings.system_prompt {
+            templates[0] = system_prompt.clone();
+        }
+        templates
+    }
+
+    /// 根据提供商和基础地址生成请求地址
+    fn endpoint(&self, model: &str) -> String {
+        self.provider.endpoint(self.base_url(), model)
+    }
+
+    /// 发送请求并检查响应状态
+    async fn send_request(
+        &self,
+        url: &str,
+        request: &ChatRequest,
+        timeout: std::time::Duration,
+    ) -> Result<reqwest::Response> {
+        // 本地服务不需要 API 密钥，未配置时不发送认证请求头
+        let api_key = self.settings.openai_api_key.as_ref();
+        if api_key.is_none() && self.settings.provider.requires_api_key() {
+            return Err(anyhow::anyhow!("OpenAI API key not set"));
+        }
+
+        debug!(
+            "Sending request to {:?} API: {}",
+            self.settings.provider, url
+        );
+
+        let builder = self
+            .client
+            .post(url)
+            .timeout(timeout)
+            .header("Content-Type", "application/json");
+
+        let builder = match api_key {
+            Some(api_key) => self.provider.authorize(builder, api_key),
+            None => builder,
+        };
+        let builder = self.provider.request_body(builder, request);
+
+        let response = builder
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to send request to AI API: {}", e))?;
+
+        // 检查响应状态
+        if !response.status().is_success() {
+            let status = response.status();
+            let retry_after = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_retry_after);
+            let body = response
+                .text()
+                .await
+                .unwrap_or_else(|_| String::from("Unable to get error details"));
+            let (category, message) = describe_api_error(status, &body, &request.model);
+
+            let error = match status {
+                // 额度用尽同样返回 429，但等待并不会恢复
+                _ if category == ErrorCategory::Quota => ApiError::Client { status, message },
+                reqwest::StatusCode::TOO_MANY_REQUESTS
+                | reqwest::StatusCode::SERVICE_UNAVAILABLE => ApiError::RateLimited {
+                    status,
+                    retry_after,
+                    message,
+                },
+                reqwest::StatusCode::REQUEST_TIMEOUT => ApiError::Status { status, message },
+                status if status.is_client_error() => ApiError::Client { status, message },
+                _ => ApiError::Status { status, message },
+            };
+            return Err(error.into());
+        }
+
+        Ok(response)
+    }
+
+    async fn try_resolve(
+        &self,
+        url: &str,
+        request: &ChatRequest,
+        timeout: std::time::Duration,
+    ) -> Result<String> {
+        let response = self.send_request(url, request, timeout).await?;
+
+        // 解析JSON响应
+        let response_text = response
+            .text()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to get response text: {}", e))?;
+
+        debug!("AI API response: {}", response_text);
+
+        self.parse_response_text(&response_text)
+    }
+
+    /// 解析响应体，累计消耗的 token 数并返回模型的回复
+    fn parse_response_text(&self, response_text: &str) -> Result<String> {
+        let (content, usage) = self.provider.parse_response(response_text).map_err(|e| {
+            anyhow::anyhow!(
+                "Failed to parse API response: {}, Response: {}",
+                e,
+                response_text
+            )
+        })?;
+        *self.usage.lock().unwrap() += usage;
+
+        content.ok_or_else(|| anyhow::anyhow!("No resolution provided by AI"))
+    }
+
+    /// 读取 server-sent events 格式的流式响应，逐段回调并拼接完整内容
+    async fn try_resolve_streaming(
+        &self,
+        url: &str,
+        request: &ChatRequest,
+        timeout: std::time::Duration,
+        on_event: &mut dyn FnMut(StreamEvent),
+    ) -> Result<String> {
+        let mut response = self.send_request(url, request, timeout).await?;
+
+        // 按字节缓存未完整的行，避免多字节字符被拆分到两个数据块中
+        let mut pending: Vec<u8> = Vec::new();
+        let mut content = String::new();
+
+        'events: while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to read response stream: {}", e))?
+        {
+            pending.extend_from_slice(&chunk);
+
+            while let Some(newline) = pending.iter().position(|&byte| byte == b'\n') {
+                let line: Vec<u8> = pending.drain(..=newline).collect();
+                let line = String::from_utf8_lossy(&line);
+                let Some(data) = line.trim().strip_prefix("data:") else {
+                    continue;
+                };
+
+                let data = data.trim();
+                if data == "[DONE]" {
+                    break 'events;
+                }
+
+                let (delta, usage) = parse_stream_delta(data)?;
+                *self.usage.lock().unwrap() += usage;
+                if let Some(delta) = delta {
+                    on_event(StreamEvent::Token(&delta));
+                    content.push_str(&delta);
+                }
+            }
+        }
+
+        debug!("AI API streamed response: {}", content);
+
+        if content.is_empty() {
+            return Err(anyhow::anyhow!("No resolution provided by AI"));
+        }
+        Ok(content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::Server;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_resolve_conflict() -> Result<()> {
+        // 设置模拟服务器
+        let mut server = Server::new_async().await;
+
+        // 模拟简化后的 OpenAI API 响应
+        let mock_response = json!({
+            "choices": [
+                {
+                    "message": {
+                        "role": "assistant",
+                        "content": "Resolved content"
+                    }
+                }
+            ]
+        });
+
+        // 检查精简后的冲突描述
+        let mock_server = server
+            .mock("POST", "/v1/chat/completions")
+            .match_header("content-type", "application/json")
+            .with_status(200)
+            .with_body(mock_response.to_string())
+            .create_async()
+            .await;
+
+        // 创建带有模拟设置的冲突解析器
+        let settings = Settings {
+            base_url: Some(format!("{}/v1", server.url())),
+            openai_api_key: Some("test-key".to_string()),
+            model: "gpt-3.5-turbo".to_string(),
+            ..Default::default()
+        };
+
+        // 创建一个测试冲突文件
+        let conflict = ConflictFile {
+            path: "test.txt".to_string(),
+            raw_path: b"test.txt".to_vec(),
+            our_content: "Our content".to_string(),
+            their_content: "Their content".to_string(),
+            base_content: Some("Base content".to_string()),
+            marked_content: None,
+            is_binary: false,
+            kind: ConflictKind::Content,
+        };
+
+        // 创建指向模拟服务器的解析器
+        let resolver = ConflictResolver::new(settings);
+
+        // 模拟解析冲突
+        let resolution = resolver.resolve_conflict(&conflict).await?;
+
+        // 验证结果
+        assert_eq!(resolution, "Resolved content");
+
+        // 确保模拟服务器被调用
+        mock_server.assert_async().await;
+
+        Ok(())
+    }
+
+    // 测试没有基础版本的情况
+    #[tokio::test]
+    async fn test_resolve_conflict_without_base() -> Result<()> {
+        // 设置模拟服务器
+        let mut server = Server::new_async().await;
+
+        // 模拟简化后的 OpenAI API 响应
+        let mock_response = json!({
+            "choices": [
+                {
+                    "message": {
+                        "role": "assistant",
+                        "content": "Resolved without base"
+                    }
+                }
+            ]
+        });
+
+        // 检查没有基础版本的请求
+        let mock_server = server
+            .mock("POST", "/v1/chat/completions")
+            .with_header("content-type", "application/json")
+            .with_status(200)
+            .with_body(mock_response.to_string())
+            .create_async()
+            .await;
+
+        // 创建带有模拟设置的冲突解析器
+        let settings = Settings {
+            base_url: Some(format!("{}/v1", server.url())),
+            openai_api_key: Some("test-key".to_string()),
+            model: "gpt-3.5-turbo".to_string(),
+            ..Default::default()
+        };
+
+        // 创建一个测试冲突文件，没有基础版本
+        let conflict = ConflictFile {
+            path: "test.txt".to_string(),
+            raw_path: b"test.txt".to_vec(),
+            our_content: "Our content".to_string(),
+            their_content: "Their content".to_string(),
+            base_content: None,
+            marked_content: None,
+            is_binary: false,
+            kind: ConflictKind::Content,
+        };
+
+        // 创建指向模拟服务器的解析器
+        let resolver = ConflictResolver::new(settings);
+
+        // 模拟解析冲突
+        let resolution = resolver.resolve_conflict(&conflict).await?;
+
+        // 验证结果
+        assert_eq!(resolution, "Resolved without base");
+
+        // 确保模拟服务器被调用
+        mock_server.assert_async().await;
+
+        Ok(())
+    }
+
+    // 测试 OpenAI 返回空结果的错误情况
+    #[tokio::test]
+    async fn test_resolve_conflict_empty_response() -> Result<()> {
+        // 设置模拟服务器
+        let mut server = Server::new_async().await;
+
+        // 模拟空结果响应
+        let mock_response = json!({
+            "choices": []
+        });
+
+        // 创建空结果测试用的模拟服务器
+        let mock_server = server
+            .mock("POST", "/v1/chat/completions")
+            .expect(1) // 设置期望只收到1次请求
+            .with_header("content-type", "application/json")
+            .with_status(200)
+            .with_body(mock_response.to_string())
+            .create_async()
+            .await;
+
+        // 创建带有模拟设置的冲突解析器
+        let settings = Settings {
+            base_url: Some(format!("{}/v1", server.url())),
+            openai_api_key: Some("test-key".to_string()),
+            model: "gpt-3.5-turbo".to_string(),
+            max_retries: 0, // 设置为0，禁用重试功能
+            ..Default::default()
+        };
+
+        // 创建一个测试冲突文件
+        let conflict = ConflictFile {
+            path: "test.txt".to_string(),
+            raw_path: b"test.txt".to_vec(),
+            our_content: "Our content".to_string(),
+            their_content: "Their content".to_string(),
+            base_content: Some("Base content".to_string()),
+            marked_content: None,
+            is_binary: false,
+            kind: ConflictKind::Content,
+        };
+
+        // 创建指向模拟服务器的解析器
+        let resolver = ConflictResolver::new(settings);
+
+        // 模拟解析冲突，应该返回错误
+        let result = resolver.resolve_conflict(&conflict).await;
+
+        // 验证结果是错误
+        assert!(result.is_err());
+
+        // 确保模拟服务器被调用
+        mock_server.assert_async().await;
+
+        Ok(())
+    }
+
+    // 测试 API 错误响应
+    #[tokio::test]
+    async fn test_api_error_response() -> Result<()> {
+        // 设置模拟服务器
+        let mut server = Server::new_async().await;
+
+        // 模拟 API 错误
+        let error_response = json!({
+            "error": {
+                "message": "Invalid API key",
+                "type": "invalid_request_error",
+                "code": "invalid_api_key"
+            }
+        });
+
+        // 创建错误测试用的模拟服务器
+        let mock_server = server
+            .mock("POST", "/v1/chat/completions")
+            .expect(1) // 设置期望只收到1次请求
+            .with_header("content-type", "application/json")
+            .with_status(401)
+            .with_body(error_response.to_string())
+            .create_async()
+            .await;
+
+        // 创建带有模拟设置的冲突解析器
+        let settings = Settings {
+            base_url: Some(format!("{}/v1", server.url())),
+            openai_api_key: Some("invalid-key".to_string()),
+            model: "gpt-3.5-turbo".to_string(),
+            max_retries: 0, // 设置为0，禁用重试功能
+            ..Default::default()
+        };
+
+        // 创建一个测试冲突文件
+        let conflict = ConflictFile {
+            path: "test.txt".to_string(),
+            raw_path: b"test.txt".to_vec(),
+            our_content: "Our content".to_string(),
+            their_content: "Their content".to_string(),
+            base_content: Some("Base content".to_string()),
+            marked_content: None,
+            is_binary: false,
+            kind: ConflictKind::Content,
+        };
+
+        // 创建指向模拟服务器的解析器
+        let resolver = ConflictResolver::new(settings);
+
+        // 模拟解析冲突，应该返回错误
+        let result = resolver.resolve_conflict(&conflict).await;
+
+        // 验证结果是错误
+        assert!(result.is_err());
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("API request failed with status 401"));
+
+        // 确保模拟服务器被调用
+        mock_server.assert_async().await;
+
+        Ok(())
+    }
+
+    // 测试 401 等客户端错误不会重试
+    #[tokio::test]
+    async fn test_client_error_not_retried() -> Result<()> {
+        let mut server = Server::new_async().await;
+        let mock_server = server
+            .mock("POST", "/v1/chat/completions")
+            .expect(1)
+            .with_status(401)
+            .with_body("invalid api key")
+            .create_async()
+            .await;
+
+        let settings = Settings {
+            base_url: Some(format!("{}/v1", server.url())),
+            openai_api_key: Some("invalid-key".to_string()),
+            max_retries: 3,
+            ..Default::default()
+        };
+
+        let conflict = ConflictFile {
+            path: "test.txt".to_string(),
+            raw_path: b"test.txt".to_vec(),
+            our_content: "Our content".to_string(),
+            their_content: "Their content".to_string(),
+            base_content: None,
+            marked_content: None,
+            is_binary: false,
+            kind: ConflictKind::Content,
+        };
+
+        let resolver = ConflictResolver::new(settings);
+        let err = resolver.resolve_conflict(&conflict).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ApiError>(),
+            Some(ApiError::Client { status, .. }) if status.as_u16() == 401
+        ));
+
+        mock_server.assert_async().await;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_describe_api_error() {
+        let describe = |status: u16, body: serde_json::Value| {
+            describe_api_error(
+                reqwest::StatusCode::from_u16(status).unwrap(),
+                &body.to_string(),
+                "gpt-4",
+            )
+        };
+
+        let (category, message) = describe(
+            401,
+            json!({ "error": { "message": "Incorrect API key provided", "type": "invalid_request_error", "code": "invalid_api_key" } }),
+        );
+        assert_eq!(category, ErrorCategory::Auth);
+        assert!(message.starts_with("Incorrect API key provided (authentication failed"));
+
+        let (category, message) = describe(
+            429,
+            json!({ "error": { "message": "You exceeded your current quota", "type": "insufficient_quota", "code": "insufficient_quota" } }),
+        );
+        assert_eq!(category, ErrorCategory::Quota);
+        assert!(message.contains("quota exceeded"));
+
+        let (category, message) = describe(
+            404,
+            json!({ "error": { "message": "The model does not exist", "type": "invalid_request_error", "code": "model_not_found" } }),
+        );
+        assert_eq!(category, ErrorCategory::ModelNotFound);
+        assert!(message.ends_with("(model 'gpt-4' not found — check Settings.model)"));
+
+        // Anthropic 使用 type 区分错误，Gemini 的 code 为数字
+        let (category, _) = describe(
+            401,
+            json!({ "type": "error", "error": { "type": "authentication_error", "message": "invalid x-api-key" } }),
+        );
+        assert_eq!(category, ErrorCategory::Auth);
+        let (category, message) = describe(
+            400,
+            json!({ "error": { "code": 400, "message": "Invalid JSON payload", "status": "INVALID_ARGUMENT" } }),
+        );
+        assert_eq!(category, ErrorCategory::Other);
+        assert_eq!(message, "Invalid JSON payload");
+
+        // 无法解析时保留原始响应体
+        let (category, message) = describe_api_error(
+            reqwest::StatusCode::BAD_GATEWAY,
+            "<html>Bad Gateway</html>",
+            "gpt-4",
+        );
+        assert_eq!(category, ErrorCategory::Other);
+        assert_eq!(message, "<html>Bad Gateway</html>");
+    }
+
+    // 测试超过 max_conflict_bytes 的冲突不会发送给AI
+    #[tokio::test]
+    async fn test_oversized_conflict_not_sent() -> Result<()> {
+        let mut server = Server::new_async().await;
+        let mock_server = server
+            .mock("POST", "/v1/chat/completions")
+            .expect(0)
+            .create_async()
+            .await;
+
+        let settings = Settings {
+            base_url: Some(format!("{}/v1", server.url())),
+            openai_api_key: Some("test-key".to_string()),
+            max_conflict_bytes: 16,
+            ..Default::default()
+        };
+
+        let conflict = ConflictFile {
+            path: "bundle.min.js".to_string(),
+            raw_path: b"bundle.min.js".to_vec(),
+            our_content: "a".repeat(10),
+            their_content: "b".repeat(10),
+            base_content: None,
+            marked_content: None,
+            is_binary: false,
+            kind: ConflictKind::Content,
+        };
+
+        let resolver = ConflictResolver::new(settings);
+        let err = resolver.resolve_conflict(&conflict).await.unwrap_err();
+        assert!(err.to_string().contains("max_conflict_bytes"));
+        assert!(resolver
+            .resolve_conflicts_batch(std::slice::from_ref(&conflict))
+            .await
+            .is_empty());
+
+        mock_server.assert_async().await;
+
+        Ok(())
+    }
+
+    // 测试额度用尽的 429 不会重试
+    #[tokio::test]
+    async fn test_quota_error_not_retried() -> Result<()> {
+        let mut server = Server::new_async().await;
+        let mock_server = server
+            .mock("POST", "/v1/chat/completions")
+            .expect(1)
+            .with_status(429)
+            .with_body(
+                json!({ "error": { "message": "You exceeded your current quota", "type": "insufficient_quota", "code": "insufficient_quota" } })
+                    .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let settings = Settings {
+            base_url: Some(format!("{}/v1", server.url())),
+            openai_api_key: Some("test-key".to_string()),
+            max_retries: 3,
+            ..Default::default()
+        };
+
+        let conflict = ConflictFile {
+            path: "test.txt".to_string(),
+            raw_path: b"test.txt".to_vec(),
+            our_content: "Our content".to_string(),
+            their_content: "Their content".to_string(),
+            base_content: None,
+            marked_content: None,
+            is_binary: false,
+            kind: ConflictKind::Content,
+        };
+
+        let resolver = ConflictResolver::new(settings);
+        let err = resolver.resolve_conflict(&conflict).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ApiError>(),
+            Some(ApiError::Client { message, .. }) if message.contains("quota exceeded")
+        ));
+
+        mock_server.assert_async().await;
+
+        Ok(())
+    }
+
+    // 测试主模型过载时改用备用模型
+    #[tokio::test]
+    async fn test_fallback_model() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let audit_log = temp_dir.path().join("audit.jsonl");
+
+        let mut server = Server::new_async().await;
+        let primary_mock = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJson(json!({ "model": "gpt-4" })))
+            .expect(1)
+            .with_status(503)
+            .with_body("overloaded")
+            .create_async()
+            .await;
+
+        let mock_response = json!({
+            "choices": [{ "message": { "role": "assistant", "content": "resolved" } }]
+        });
+        let fallback_mock = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJson(
+                json!({ "model": "gpt-4o-mini" }),
+            ))
+            .expect(1)
+            .with_status(200)
+            .with_body(mock_response.to_string())
+            .create_async()
+            .await;
+
+        let settings = Settings {
+            base_url: Some(format!("{}/v1", server.url())),
+            openai_api_key: Some("test-key".to_string()),
+            model: "gpt-4".to_string(),
+            fallback_model: Some("gpt-4o-mini".to_string()),
+            max_retries: 0,
+            audit_log: Some(audit_log.clone()),
+            ..Default::default()
+        };
+
+        let conflict = ConflictFile {
+            path: "test.txt".to_string(),
+            raw_path: b"test.txt".to_vec(),
+            our_content: "Our content".to_string(),
+            their_content: "Their content".to_string(),
+            base_content: None,
+            marked_content: None,
+            is_binary: false,
+            kind: ConflictKind::Content,
+        };
+
+        let cache = ResolutionCache::new(temp_dir.path().join("cache"));
+
+        let resolver = ConflictResolver::new(settings.clone()).with_cache(cache.clone());
+        assert_eq!(resolver.resolved_by("test.txt"), None);
+        assert_eq!(resolver.resolve_conflict(&conflict).await?, "resolved");
+        assert_eq!(resolver.resolved_conflicts(), 1);
+        assert_eq!(
+            resolver.resolved_by("test.txt").as_deref(),
+            Some("gpt-4o-mini")
+        );
+
+        primary_mock.assert_async().await;
+        fallback_mock.assert_async().await;
+
+        // 审计日志记录的是实际给出方案的备用模型
+        resolver.write_audit_entry(std::path::Path::new("/repo"), &conflict, "resolved")?;
+        let entry: serde_json::Value =
+            serde_json::from_str(std::fs::read_to_string(&audit_log)?.trim())?;
+        assert_eq!(entry["path"], "test.txt");
+        assert_eq!(entry["model"], "gpt-4o-mini");
+
+        // 重新运行时使用缓存，记录的仍是实际给出方案的备用模型
+        let resolver = ConflictResolver::new(settings).with_cache(cache);
+        let resolution = resolver.resolve_conflict_explained(&conflict).await?;
+        assert_eq!(resolution.model.as_deref(), Some("gpt-4o-mini"));
+        assert_eq!(
+            resolver.resolved_by("test.txt").as_deref(),
+            Some("gpt-4o-mini")
+        );
+
+        Ok(())
+    }
+
+    // 测试客户端错误不会改用备用模型
+    #[tokio::test]
+    async fn test_client_error_skips_fallback_model() -> Result<()> {
+        let mut server = Server::new_async().await;
+        let mock_server = server
+            .mock("POST", "/v1/chat/completions")
+            .expect(1)
+            .with_status(401)
+            .with_body("invalid api key")
+            .create_async()
+            .await;
+
+        let settings = Settings {
+            base_url: Some(format!("{}/v1", server.url())),
+            openai_api_key: Some("invalid-key".to_string()),
+            fallback_model: Some("gpt-4o-mini".to_string()),
+            ..Default::default()
+        };
+
+        let conflict = ConflictFile {
+            path: "test.txt".to_string(),
+            raw_path: b"test.txt".to_vec(),
+            our_content: "Our content".to_string(),
+            their_content: "Their content".to_string(),
+            base_content: None,
+            marked_content: None,
+            is_binary: false,
+            kind: ConflictKind::Content,
+        };
+
+        let resolver = ConflictResolver::new(settings);
+        assert!(resolver.resolve_conflict(&conflict).await.is_err());
+
+        mock_server.assert_async().await;
+
+        Ok(())
+    }
+
+    // 测试被限流时按 Retry-After 等待后重试
+    #[tokio::test]
+    async fn test_rate_limited_retry_after() -> Result<()> {
+        let mut server = Server::new_async().await;
+
+        let limited = server
+            .mock("POST", "/v1/chat/completions")
+            .expect(1)
+            .with_status(429)
+            .with_header("retry-after", "0")
+            .with_body("rate limited")
+            .create_async()
+            .await;
+
+        let mock_response = json!({
+            "choices": [{ "message": { "role": "assistant", "content": "resolved" } }]
+        });
+        let succeeding = server
+            .mock("POST", "/v1/chat/completions")
+            .expect(1)
+            .with_status(200)
+            .with_body(mock_response.to_string())
+            .create_async()
+            .await;
+
+        let settings = Settings {
+            base_url: Some(format!("{}/v1", server.url())),
+            openai_api_key: Some("test-key".to_string()),
+            max_retries: 1,
+            ..Default::default()
+        };
+
+        let conflict = ConflictFile {
+            path: "test.txt".to_string(),
+            raw_path: b"test.txt".to_vec(),
+            our_content: "Our content".to_string(),
+            their_content: "Their content".to_string(),
+            base_content: None,
+            marked_content: None,
+            is_binary: false,
+            kind: ConflictKind::Content,
+        };
+
+        // Retry-After 为 0 时立即重试，不使用至少 500ms 的指数退避
+        let resolver = ConflictResolver::new(settings);
+        let started = std::time::Instant::now();
+        let resolution = resolver.resolve_conflict_explained(&conflict).await?;
+        assert!(started.elapsed() < std::time::Duration::from_millis(500));
+        assert_eq!(resolution.content, "resolved");
+        assert_eq!(resolution.attempts, 2);
+
+        limited.assert_async().await;
+        succeeding.assert_async().await;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_extract_conflict_content_multibyte() {
+        // 每个汉字占 3 个字节，500 字节处落在字符中间
+        let content = "冲突".repeat(200);
+        let extracted = ConflictResolver::extract_conflict_content(&content, 500);
+        let prefix = extracted.strip_suffix("... (truncated)").unwrap();
+        assert_eq!(prefix.len(), 498);
+        assert!(content.starts_with(prefix));
+
+        let marked = format!("<<<<<<< ours\n{}\n=======\n🎉🎉\n>>>>>>> theirs\n", content);
+        let extracted = ConflictResolver::extract_conflict_content(&marked, 500);
+        assert!(extracted.ends_with("... (truncated)"));
+
+        assert_eq!(truncate_at_char_boundary("a🎉b", 2), "a");
+        assert_eq!(truncate_at_char_boundary("a🎉b", 5), "a🎉");
+        assert_eq!(truncate_at_char_boundary("abc", 10), "abc");
+    }
+
+    #[test]
+    fn test_retry_delays() {
+        assert_eq!(
+            parse_retry_after(" 12 "),
+            Some(std::time::Duration::from_secs(12))
+        );
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), None);
+        // 过长的 Retry-After 不会让单个冲突等待太久
+        assert_eq!(parse_retry_after("86400"), Some(MAX_BACKOFF));
+
+        for attempt in 1..4 {
+            let base = 500 * 2u64.pow(attempt);
+            let delay = backoff_with_jitter(attempt).as_millis() as u64;
+            assert!(delay >= base / 2 && delay <= base);
+        }
+
+        // 重试次数很多时指数不再增长，也不会溢出
+        for attempt in [7, 10, 64, u32::MAX] {
+            let delay = backoff_with_jitter(attempt);
+            assert!(delay >= std::time::Duration::from_secs(16) && delay <= MAX_BACKOFF);
+        }
+    }
+
+    // 测试重试时轮换提示词模板
+    #[tokio::test]
+    async fn test_retry_rotates_prompt_templates() -> Result<()> {
+        let mut server = Server::new_async().await;
+
+        let failing = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::Regex("pick one side".to_string()))
+            .expect(1)
+            .with_status(500)
+            .create_async()
+            .await;
+
+        let mock_response = json!({
+            "choices": [
+                {
+                    "message": {
+                        "role": "assistant",
+                        "content": "combined"
+                    }
+                }
+            ]
+        });
+        let succeeding = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::Regex("combine both".to_string()))
+            .expect(1)
+            .with_status(200)
+            .with_body(mock_response.to_string())
+            .create_async()
+            .await;
+
+        let settings = Settings {
+            base_url: Some(format!("{}/v1", server.url())),
+            openai_api_key: Some("test-key".to_string()),
+            max_retries: 1,
+            prompt_templates: vec!["pick one side".to_string(), "combine both".to_string()],
+            retry_different_prompt: true,
+            ..Default::default()
+        };
+
+        let conflict = ConflictFile {
+            path: "test.txt".to_string(),
+            raw_path: b"test.txt".to_vec(),
+            our_content: "Our content".to_string(),
+            their_content: "Their content".to_string(),
+            base_content: None,
+            marked_content: None,
+            is_binary: false,
+            kind: ConflictKind::Content,
+        };
+
+        let resolver = ConflictResolver::new(settings);
+        assert_eq!(resolver.resolve_conflict(&conflict).await?, "combined");
+
+        failing.assert_async().await;
+        succeeding.assert_async().await;
+
+        Ok(())
+    }
+
+    // 测试请求使用配置的 temperature
+    #[tokio::test]
+    async fn test_configured_temperature() -> Result<()> {
+        let mut server = Server::new_async().await;
+
+        let mock_response = json!({
+            "choices": [{ "message": { "role": "assistant", "content": "resolved" } }]
+        });
+        let mock_server = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJson(json!({ "temperature": 0.0 })))
+            .expect(1)
+            .with_status(200)
+            .with_body(mock_response.to_string())
+            .create_async()
+            .await;
+
+        let settings = Settings {
+            base_url: Some(format!("{}/v1", server.url())),
+            openai_api_key: Some("test-key".to_string()),
+            temperature: 0.0,
+            ..Default::default()
+        };
+
+        let conflict = ConflictFile {
+            path: "test.txt".to_string(),
+            raw_path: b"test.txt".to_vec(),
+            our_content: "Our content".to_string(),
+            their_content: "Their content".to_string(),
+            base_content: None,
+            marked_content: None,
+            is_binary: false,
+            kind: ConflictKind::Content,
+        };
+
+        let resolver = ConflictResolver::new(settings);
+        assert_eq!(resolver.resolve_conflict(&conflict).await?, "resolved");
+
+        mock_server.assert_async().await;
+
+        Ok(())
+    }
+
+    // 测试请求使用配置的 max_tokens，被截断的回复不会被当作解决方案
+    #[tokio::test]
+    async fn test_configured_max_tokens() -> Result<()> {
+        let mut server = Server::new_async().await;
+
+        let mock_response = json!({
+            "choices": [{
+                "message": { "role": "assistant", "content": "partial" },
+                "finish_reason": "length"
+            }]
+        });
+        let mock_server = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJson(json!({ "max_tokens": 256 })))
+            .expect(1)
+            .with_status(200)
+            .with_body(mock_response.to_string())
+            .create_async()
+            .await;
+
+        let settings = Settings {
+            base_url: Some(format!("{}/v1", server.url())),
+            openai_api_key: Some("test-key".to_string()),
+            max_tokens: Some(256),
+            max_retries: 0,
+            ..Default::default()
+        };
+
+        let conflict = ConflictFile {
+            path: "test.txt".to_string(),
+            raw_path: b"test.txt".to_vec(),
+            our_content: "Our content".to_string(),
+            their_content: "Their content".to_string(),
+            base_content: None,
+            marked_content: None,
+            is_binary: false,
+            kind: ConflictKind::Content,
+        };
+
+        let resolver = ConflictResolver::new(settings);
+        assert!(resolver.resolve_conflict(&conflict).await.is_err());
+
+        mock_server.assert_async().await;
+
+        Ok(())
+    }
+
+    // 测试小文件原样发送，大文件只发送截取的内容
+    #[tokio::test]
+    async fn test_full_context_for_small_files() -> Result<()> {
+        let mut server = Server::new_async().await;
+
+        let mock_response = json!({
+            "choices": [{ "message": { "role": "assistant", "content": "resolved" } }]
+        });
+        let mock_server = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::Regex("END-OF-OURS".to_string()))
+            .expect(1)
+            .with_status(200)
+            .with_body(mock_response.to_string())
+            .create_async()
+            .await;
+
+        // 超过默认截取长度，但低于完整发送的阈值
+        let conflict = ConflictFile {
+            path: "test.txt".to_string(),
+            raw_path: b"test.txt".to_vec(),
+            our_content: format!("{}END-OF-OURS", "a".repeat(1000)),
+            their_content: "Their content".to_string(),
+            base_content: None,
+            marked_content: None,
+            is_binary: false,
+            kind: ConflictKind::Content,
+        };
+
+        let resolver = ConflictResolver::new(Settings {
+            base_url: Some(format!("{}/v1", server.url())),
+            openai_api_key: Some("test-key".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(resolver.resolve_conflict(&conflict).await?, "resolved");
+        mock_server.assert_async().await;
+
+        // 阈值调低后只发送截取的内容，请求中不再包含末尾部分
+        let resolver = ConflictResolver::new(Settings {
+            base_url: Some(format!("{}/v1", server.url())),
+            openai_api_key: Some("test-key".to_string()),
+            max_full_context_bytes: 100,
+            max_retries: 0,
+            ..Default::default()
+        });
+        assert!(resolver.resolve_conflict(&conflict).await.is_err());
+
+        Ok(())
+    }
+
+    // 测试自定义系统提示词替换默认提示词
+    #[tokio::test]
+    async fn test_custom_system_prompt() -> Result<()> {
+        let mut server = Server::new_async().await;
+
+        let mock_response = json!({
+            "choices": [{ "message": { "role": "assistant", "content": "resolved" } }]
+        });
+        let mock_server = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::Regex(
+                "Resolve conflicts following our house style".to_string(),
+            ))
+            .expect(1)
+            .with_status(200)
+            .with_body(mock_response.to_string())
+            .create_async()
+            .await;
+
+        let settings = Settings {
+            base_url: Some(format!("{}/v1", server.url())),
+            openai_api_key: Some("test-key".to_string()),
+            system_prompt: Some("Resolve conflicts following our house style.".to_string()),
+            ..Default::default()
+        };
+
+        let conflict = ConflictFile {
+            path: "test.txt".to_string(),
+            raw_path: b"test.txt".to_vec(),
+            our_content: "Our content".to_string(),
+            their_content: "Their content".to_string(),
+            base_content: None,
+            marked_content: None,
+            is_binary: false,
+            kind: ConflictKind::Content,
+        };
+
+        let resolver = ConflictResolver::new(settings);
+        assert_eq!(resolver.resolve_conflict(&conflict).await?, "resolved");
+
+        mock_server.assert_async().await;
+
+        Ok(())
+    }
+
+    // 测试 explain 模式下要求并解析带理由的 JSON 回复
+    #[tokio::test]
+    async fn test_resolve_conflict_explained() -> Result<()> {
+        let mut server = Server::new_async().await;
+
+        let content = json!({
+            "resolution": "merged content",
+            "reasoning": "Both sides changed the greeting; kept the newer wording."
+        });
+        let mock_response = json!({
+            "choices": [{ "message": { "role": "assistant", "content": content.to_string() } }]
+        });
+        let mock_server = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::Regex("reasoning".to_string()))
+            .expect(1)
+            .with_status(200)
+            .with_body(mock_response.to_string())
+            .create_async()
+            .await;
+
+        let settings = Settings {
+            base_url: Some(format!("{}/v1", server.url())),
+            openai_api_key: Some("test-key".to_string()),
+            explain: true,
+            ..Default::default()
+        };
+
+        let conflict = ConflictFile {
+            path: "test.txt".to_string(),
+            raw_path: b"test.txt".to_vec(),
+            our_content: "Our content".to_string(),
+            their_content: "Their content".to_string(),
+            base_content: None,
+            marked_content: None,
+            is_binary: false,
+            kind: ConflictKind::Content,
+        };
+
+        let resolver = ConflictResolver::new(settings);
+        let resolution = resolver.resolve_conflict_explained(&conflict).await?;
+        assert_eq!(resolution.content, "merged content");
+        assert_eq!(
+            resolution.reasoning.as_deref(),
+            Some("Both sides changed the greeting; kept the newer wording.")
+        );
+
+        mock_server.assert_async().await;
+
+        Ok(())
+    }
+
+    // 测试缓存命中时不再请求AI
+    #[tokio::test]
+    async fn test_resolve_conflict_cached() -> Result<()> {
+        let mut server = Server::new_async().await;
+
+        let mock_response = json!({
+            "choices": [{ "message": { "role": "assistant", "content": "resolved" } }]
+        });
+        let mock_server = server
+            .mock("POST", "/v1/chat/completions")
+            .expect(1)
+            .with_status(200)
+            .with_body(mock_response.to_string())
+            .create_async()
+            .await;
+
+        let settings = Settings {
+            base_url: Some(format!("{}/v1", server.url())),
+            openai_api_key: Some("test-key".to_string()),
+            ..Default::default()
+        };
+
+        let conflict = ConflictFile {
+            path: "test.txt".to_string(),
+            raw_path: b"test.txt".to_vec(),
+            our_content: "Our content".to_string(),
+            their_content: "Their content".to_string(),
+            base_content: None,
+            marked_content: None,
+            is_binary: false,
+            kind: ConflictKind::Content,
+        };
+
+        let temp_dir = tempfile::TempDir::new()?;
+        let cache = ResolutionCache::new(temp_dir.path());
+
+        let resolver = ConflictResolver::new(settings.clone()).with_cache(cache.clone());
+        assert_eq!(
+            resolver
+                .resolve_conflict_explained(&conflict)
+                .await?
+                .attempts,
+            1
+        );
+
+        // 重新运行时直接使用缓存，不发送请求
+        let resolver = ConflictResolver::new(settings).with_cache(cache.clone());
+        let resolution = resolver.resolve_conflict_explained(&conflict).await?;
+        assert_eq!(resolution.content, "resolved");
+        assert_eq!(resolution.attempts, 0);
+        mock_server.assert_async().await;
+
+        resolver.forget_resolution(&conflict);
+        assert_eq!(
+            cache.get(&ResolutionCache::key(&conflict, &resolver.settings.model)),
+            None
+        );
+
+        Ok(())
+    }
+
+    // 测试流式响应逐段回调并拼接出完整内容
+    #[tokio::test]
+    async fn test_resolve_conflict_streaming() -> Result<()> {
+        let mut server = Server::new_async().await;
+
+        let events = [
+            json!({ "choices": [{ "delta": { "role": "assistant" } }] }),
+            json!({ "choices": [{ "delta": { "content": "Resolved " } }] }),
+            json!({ "choices": [{ "delta": { "content": "content" } }] }),
+            json!({ "choices": [{ "delta": {} }] }),
+            // 请求了 include_usage 时最后一个事件只携带用量
+            json!({ "choices": [], "usage": { "prompt_tokens": 12, "completion_tokens": 3 } }),
+        ];
+        let body: String = events
+            .iter()
+            .map(|event| format!("data: {}\n\n", event))
+            .chain(std::iter::once("data: [DONE]\n\n".to_string()))
+            .collect();
+
+        let mock_server = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJson(json!({
+                "stream": true,
+                "stream_options": { "include_usage": true }
+            })))
+            .with_status(200)
+            .with_header("content-type", "text/event-stream")
+            .with_body(body)
+            .create_async()
+            .await;
+
+        let settings = Settings {
+            base_url: Some(format!("{}/v1", server.url())),
+            openai_api_key: Some("test-key".to_string()),
+            stream: true,
+            ..Default::default()
+        };
+
+        let conflict = ConflictFile {
+            path: "test.txt".to_string(),
+            raw_path: b"test.txt".to_vec(),
+            our_content: "Our content".to_string(),
+            their_content: "Their content".to_string(),
+            base_content: None,
+            marked_content: None,
+            is_binary: false,
+            kind: ConflictKind::Content,
+        };
+
+        let resolver = ConflictResolver::new(settings);
+        assert!(resolver.streaming());
+
+        let mut tokens = Vec::new();
+        let resolution = resolver
+            .resolve_conflict_streaming(&conflict, &mut |event| {
+                if let StreamEvent::Token(token) = event {
+                    tokens.push(token.to_string());
+                }
+            })
+            .await?;
+
+        assert_eq!(resolution, "Resolved content");
+        assert_eq!(tokens, vec!["Resolved ", "content"]);
+        assert_eq!(
+            resolver.usage(),
+            TokenUsage {
+                prompt_tokens: 12,
+                completion_tokens: 3,
+            }
+        );
+
+        mock_server.assert_async().await;
+
+        Ok(())
+    }
+
+    // 测试流式输出的回复被拒绝后，重试前先发送 Retry 事件
+    #[tokio::test]
+    async fn test_streaming_retry_event() -> Result<()> {
+        let mut server = Server::new_async().await;
+
+        let event = json!({ "choices": [{ "delta": { "content": "<<<<<<< ours\nours" } }] });
+        let mock_server = server
+            .mock("POST", "/v1/chat/completions")
+            .expect(2) // 首次请求和一次重试
+            .with_status(200)
+            .with_header("content-type", "text/event-stream")
+            .with_body(format!("data: {}\n\ndata: [DONE]\n\n", event))
+            .create_async()
+            .await;
+
+        let settings = Settings {
+            base_url: Some(format!("{}/v1", server.url())),
+            openai_api_key: Some("test-key".to_string()),
+            stream: true,
+            max_retries: 1,
+            ..Default::default()
+        };
+
+        let conflict = ConflictFile {
+            path: "test.txt".to_string(),
+            raw_path: b"test.txt".to_vec(),
+            our_content: "Our content".to_string(),
+            their_content: "Their content".to_string(),
+            base_content: None,
+            marked_content: None,
+            is_binary: false,
+            kind: ConflictKind::Content,
+        };
+
+        let resolver = ConflictResolver::new(settings);
+        let mut events = Vec::new();
+        let result = resolver
+            .resolve_conflict_streaming(&conflict, &mut |event| {
+                events.push(match event {
+                    StreamEvent::Token(_) => "token".to_string(),
+                    StreamEvent::Retry {
+                        attempt,
+                        max_attempts,
+                    } => format!("retry {}/{}", attempt, max_attempts),
+                    StreamEvent::Fallback { model } => format!("fallback {}", model),
+                })
+            })
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(events, vec!["token", "retry 2/2", "token"]);
+
+        mock_server.assert_async().await;
+
+        Ok(())
+    }
+
+    // 测试残留冲突标记的回复会被拒绝并触发重试
+    #[tokio::test]
+    async fn test_resolution_with_conflict_markers_rejected() -> Result<()> {
+        let mut server = Server::new_async().await;
+
+        let mock_response = json!({
+            "choices": [
+                {
+                    "message": {
+                        "role": "assistant",
+                        "content": "<<<<<<< ours\nOur content\n=======\nTheir content\n>>>>>>> theirs"
+                    }
+                }
+            ]
+        });
+
+        let mock_server = server
+            .mock("POST", "/v1/chat/completions")
+            .expect(2) // 首次请求和一次重试
+            .with_status(200)
+            .with_body(mock_response.to_string())
+            .create_async()
+            .await;
+
+        let settings = Settings {
+            base_url: Some(format!("{}/v1", server.url())),
+            openai_api_key: Some("test-key".to_string()),
+            max_retries: 1,
+            ..Default::default()
+        };
+
+        let conflict = ConflictFile {
+            path: "test.txt".to_string(),
+            raw_path: b"test.txt".to_vec(),
+            our_content: "Our content".to_string(),
+            their_content: "Their content".to_string(),
+            base_content: None,
+            marked_content: None,
+            is_binary: false,
+            kind: ConflictKind::Content,
+        };
+
+        let resolver = ConflictResolver::new(settings);
+        let err = resolver.resolve_conflict(&conflict).await.unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("AI resolution still contains conflict markers"));
+
+        mock_server.assert_async().await;
+
+        assert!(!contains_conflict_markers("Title\n=======\nBody"));
+
+        Ok(())
+    }
+
+    // 测试结构化文件的解决方案无法解析时视为失败，并且请求中附带文件语言的提示
+    #[tokio::test]
+    async fn test_invalid_json_resolution_rejected() -> Result<()> {
+        let mut server = Server::new_async().await;
+
+        let mock_response = json!({
+            "choices": [
+                {
+                    "message": {
+                        "role": "assistant",
+                        "content": "{\"name\": \"app\",}"
+                    }
+                }
+            ]
+        });
+
+        let mock_server = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::Regex(
+                "This is a JSON file; the result must be valid JSON".to_string(),
+            ))
+            .expect(2) // 首次请求和一次重试
+            .with_status(200)
+            .with_body(mock_response.to_string())
+            .create_async()
+            .await;
+
+        let settings = Settings {
+            base_url: Some(format!("{}/v1", server.url())),
+            openai_api_key: Some("test-key".to_string()),
+            max_retries: 1,
+            ..Default::default()
+        };
+
+        let conflict = ConflictFile {
+            path: "package.json".to_string(),
+            raw_path: b"package.json".to_vec(),
+            our_content: "{\"name\": \"ours\"}".to_string(),
+            their_content: "{\"name\": \"theirs\"}".to_string(),
+            base_content: None,
+            marked_content: None,
+            is_binary: false,
+            kind: ConflictKind::Content,
+        };
+
+        let resolver = ConflictResolver::new(settings);
+        let err = resolver.resolve_conflict(&conflict).await.unwrap_err();
+        assert!(err.to_string().contains("AI resolution is not valid JSON"));
+
+        mock_server.assert_async().await;
+
+        Ok(())
+    }
+
+    // 测试 LFS 指针冲突按策略选择一方，不发送请求
+    #[tokio::test]
+    async fn test_resolve_lfs_pointer_conflict() -> Result<()> {
+        let mut server = Server::new_async().await;
+        let mock_server = server
+            .mock("POST", mockito::Matcher::Any)
+            .expect(0)
+            .create_async()
+            .await;
+
+        let our_pointer = "version https://git-lfs.github.com/spec/v1\noid sha256:aaaa\nsize 10\n";
+        let their_pointer =
+            "version https://git-lfs.github.com/spec/v1\noid sha256:bbbb\nsize 20\n";
+        let conflict = ConflictFile {
+            path: "assets/logo.png".to_string(),
+            raw_path: b"assets/logo.png".to_vec(),
+            our_content: our_pointer.to_string(),
+            their_content: their_pointer.to_string(),
+            base_content: None,
+            marked_content: None,
+            is_binary: false,
+            kind: ConflictKind::Content,
+        };
+
+        let settings = Settings {
+            base_url: Some(format!("{}/v1", server.url())),
+            openai_api_key: Some("test-key".to_string()),
+            lfs_policy: LfsPolicy::Theirs,
+            ..Default::default()
+        };
+        let resolver = ConflictResolver::new(settings);
+        assert_eq!(resolver.resolve_conflict(&conflict).await?, their_pointer);
+
+        let resolver = ConflictResolver::new(Settings {
+            base_url: Some(format!("{}/v1", server.url())),
+            openai_api_key: Some("test-key".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(resolver.resolve_conflict(&conflict).await?, our_pointer);
+
+        mock_server.assert_async().await;
+
+        Ok(())
+    }
+
+    fn batch_test_conflict(path: &str) -> ConflictFile {
+        ConflictFile {
+            path: path.to_string(),
+            raw_path: path.as_bytes().to_vec(),
+            our_content: format!("ours {}", path),
+            their_content: format!("theirs {}", path),
+            base_content: None,
+            marked_content: None,
+            is_binary: false,
+            kind: ConflictKind::Content,
+        }
+    }
+
+    // 测试多个冲突通过一次请求解决
+    #[tokio::test]
+    async fn test_resolve_conflicts_batch() -> Result<()> {
+        let mut server = Server::new_async().await;
+
+        let resolutions = json!({ "resolutions": { "a.txt": "merged a", "b.txt": "merged b" } });
+        let mock_response = json!({
+            "choices": [{
+                "message": {
+                    "role": "assistant",
+                    "content": format!("```json\n{}\n```", resolutions)
+                }
+            }]
+        });
+        let mock_server = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::Regex("=== FILE: b.txt ===".to_string()))
+            .expect(1)
+            .with_status(200)
+            .with_body(mock_response.to_string())
+            .create_async()
+            .await;
+
+        let resolver = ConflictResolver::new(Settings {
+            base_url: Some(format!("{}/v1", server.url())),
+            openai_api_key: Some("test-key".to_string()),
+            ..Default::default()
+        });
+        let conflicts = [batch_test_conflict("a.txt"), batch_test_conflict("b.txt")];
+        let result = resolver.resolve_conflicts_batch(&conflicts).await;
+
+        assert_eq!(result.len(), 2);
+        assert_eq!(result["a.txt"], "merged a");
+        assert_eq!(result["b.txt"], "merged b");
+        assert_eq!(resolver.resolved_conflicts(), 2);
+
+        mock_server.assert_async().await;
+
+        Ok(())
+    }
+
+    // 测试批量响应无法解析时逐个解决
+    #[tokio::test]
+    async fn test_resolve_conflicts_batch_fallback() -> Result<()> {
+        let mut server = Server::new_async().await;
+
+        let invalid = json!({
+            "choices": [{ "message": { "role": "assistant", "content": "not json" } }]
+        });
+        let batch_mock = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::Regex("=== FILE:".to_string()))
+            .expect(1)
+            .with_status(200)
+            .with_body(invalid.to_string())
+            .create_async()
+            .await;
+
+        let single = json!({
+            "choices": [{ "message": { "role": "assistant", "content": "single" } }]
+        });
+        let single_mock = server
+            .mock("POST", "/v1/chat/completions")
+            .expect(2)
+            .with_status(200)
+            .with_body(single.to_string())
+            .create_async()
+            .await;
+
+        let resolver = ConflictResolver::new(Settings {
+            base_url: Some(format!("{}/v1", server.url())),
+            openai_api_key: Some("test-key".to_string()),
+            ..Default::default()
+        });
+        let conflicts = [batch_test_conflict("a.txt"), batch_test_conflict("b.txt")];
+        let result = resolver.resolve_conflicts_batch(&conflicts).await;
+
+        assert_eq!(result["a.txt"], "single");
+        assert_eq!(result["b.txt"], "single");
+
+        batch_mock.assert_async().await;
+        single_mock.assert_async().await;
+
+        Ok(())
+    }
+
+    // 测试二进制冲突直接返回错误，不发送请求
+    #[tokio::test]
+    async fn test_resolve_binary_conflict() -> Result<()> {
+        let mut server = Server::new_async().await;
+        let mock_server = server
+            .mock("POST", mockito::Matcher::Any)
+            .expect(0)
+            .create_async()
+            .await;
+
+        let conflict = ConflictFile {
+            path: "image.bin".to_string(),
+            raw_path: b"image.bin".to_vec(),
+            our_content: String::new(),
+            their_content: String::new(),
+            base_content: None,
+            marked_content: None,
+            is_binary: true,
+            kind: ConflictKind::Content,
+        };
+
+        let resolver = ConflictResolver::new(Settings {
+            base_url: Some(format!("{}/v1", server.url())),
+            openai_api_key: Some("test-key".to_string()),
+            ..Default::default()
+        });
+        let err = resolver.resolve_conflict(&conflict).await.unwrap_err();
+        assert!(err.to_string().contains("binary"));
+
+        mock_server.assert_async().await;
+
+        Ok(())
+    }
+
+    // 测试累计 token 用量和费用估算
+    #[tokio::test]
+    async fn test_token_usage_accumulates() -> Result<()> {
+        let mut server = Server::new_async().await;
+
+        let mock_response = json!({
+            "choices": [
+                {
+                    "message": {
+                        "role": "assistant",
+                        "content": "Resolved content"
+                    }
+                }
+            ],
+            "usage": { "prompt_tokens": 1200, "completion_tokens": 300, "total_tokens": 1500 }
+        });
+
+        let mock_server = server
+            .mock("POST", "/v1/chat/completions")
+            .expect(2)
+            .with_status(200)
+            .with_body(mock_response.to_string())
+            .create_async()
+            .await;
+
+        let settings = Settings {
+            base_url: Some(format!("{}/v1", server.url())),
+            openai_api_key: Some("test-key".to_string()),
+            prompt_price_per_1k: Some(0.01),
+            completion_price_per_1k: Some(0.03),
+            ..Default::default()
+        };
+
+        let conflict = ConflictFile {
+            path: "test.txt".to_string(),
+            raw_path: b"test.txt".to_vec(),
+            our_content: "Our content".to_string(),
+            their_content: "Their content".to_string(),
+            base_content: None,
+            marked_content: None,
+            is_binary: false,
+            kind: ConflictKind::Content,
+        };
+
+        let resolver = ConflictResolver::new(settings);
+        assert_eq!(resolver.estimated_cost(), Some(0.0));
+
+        resolver.resolve_conflict(&conflict).await?;
+        resolver.resolve_conflict(&conflict).await?;
+
+        assert_eq!(resolver.resolved_conflicts(), 2);
+        assert_eq!(
+            resolver.usage(),
+            TokenUsage {
+                prompt_tokens: 2400,
+                completion_tokens: 600,
+            }
+        );
+        let cost = resolver.estimated_cost().unwrap();
+        assert!((cost - 0.042).abs() < 1e-9);
+
+        mock_server.assert_async().await;
+
+        Ok(())
+    }
+
+    // 测试 Gemini 提供商的请求与响应格式
+    #[tokio::test]
+    async fn test_resolve_conflict_gemini() -> Result<()> {
+        // 设置模拟服务器
+        let mut server = Server::new_async().await;
+
+        // 模拟 Gemini generateContent 响应
+        let mock_response = json!({
+            "candidates": [
+                {
+                    "content": {
+                        "role": "model",
+                        "parts": [{ "text": "Resolved " }, { "text": "by Gemini" }]
+                    }
+                }
+            ]
+        });
+
+        let mock_server = server
+            .mock("POST", "/v1beta/models/gemini-1.5-pro:generateContent")
+            .match_header("x-goog-api-key", "test-key")
+            .match_body(mockito::Matcher::PartialJson(json!({
+                "contents": [{ "role": "user" }],
+                "generationConfig": { "temperature": 0.7 }
+            })))
+            .with_status(200)
+            .with_body(mock_response.to_string())
+            .create_async()
+            .await;
 
-        // 在测试环境中使用自定义 URL，否则使用 OpenAI 的 API URL
-        #[cfg(test)]
-        let url = if let Some(custom_url) = &self.api_url {
-            custom_url.as_str()
-        } else {
-            "https://api.openai.com/v1/chat/completions"
+        let settings = Settings {
+            base_url: Some(format!("{}/v1beta", server.url())),
+            openai_api_key: Some("test-key".to_string()),
+            provider: Provider::Gemini,
+            model: "gemini-1.5-pro".to_string(),
+            ..Default::default()
         };
-        #[cfg(not(test))]
-        let url = "https://api.openai.com/v1/chat/completions";
-
-        debug!("Request: {:?}", request);
 
-        // 配置请求超时
-        let timeout = std::time::Duration::from_secs(self.settings.timeout_seconds);
-
-        // 添加重试逻辑
-        let mut attempts = 0;
-        let max_retries = self.settings.max_retries;
+        let conflict = ConflictFile {
+            path: "test.txt".to_string(),
+            raw_path: b"test.txt".to_vec(),
+            our_content: "Our content".to_string(),
+            their_content: "Their content".to_string(),
+            base_content: None,
+            marked_content: None,
+            is_binary: false,
+            kind: ConflictKind::Content,
+        };
 
-        while attempts <= max_retries {
-            attempts += 1;
-            info!(
-                "Attempt {}/{} to resolve conflict for file: {}",
-                attempts,
-                max_retries + 1,
-                conflict.path
-            );
+        let resolver = ConflictResolver::new(settings);
 
-            match self.try_resolve(url, &request, timeout).await {
-                Ok(resolution) => return Ok(resolution),
-                Err(e) => {
-                    if attempts > max_retries {
-                        error!(
-                            "Failed to get AI resolution after {} attempts: {}",
-                            attempts, e
-                        );
-                        return Err(anyhow::anyhow!(
-                            "Failed to get AI resolution after {} attempts: {}",
-                            attempts,
-                            e
-                        ));
-                    }
+        let resolution = resolver.resolve_conflict(&conflict).await?;
+        assert_eq!(resolution, "Resolved by Gemini");
 
-                    warn!("Attempt {} failed: {}. Retrying...", attempts, e);
-                    // 重试前等待一段时间（指数退避）
-                    tokio::time::sleep(std::time::Duration::from_millis(
-                        500 * 2u64.pow(attempts as u32),
-                    ))
-                    .await;
-                }
-            }
-        }
+        mock_server.assert_async().await;
 
-        // 不应该到达这里，但为了编译通过
-        Err(anyhow::anyhow!("Failed to get AI resolution"))
+        Ok(())
     }
 
-    async fn try_resolve(
-        &self,
-        url: &str,
-        request: &ChatRequest,
-        timeout: std::time::Duration,
-    ) -> Result<String> {
-        let api_key = self
-            .settings
-            .openai_api_key
-            .as_ref()
-            .ok_or_else(|| anyhow::anyhow!("OpenAI API key not set"))?;
+    #[tokio::test]
+    async fn test_resolve_conflict_anthropic() -> Result<()> {
+        // 设置模拟服务器
+        let mut server = Server::new_async().await;
 
-        debug!("Sending request to OpenAI API: {}", url);
+        // 模拟 Anthropic messages 响应
+        let mock_response = json!({
+            "id": "msg_test",
+            "type": "message",
+            "role": "assistant",
+            "content": [{ "type": "text", "text": "Resolved by Claude" }],
+            "stop_reason": "end_turn"
+        });
 
-        let response = self
-            .client
-            .post(url)
-            .timeout(timeout)
-            .header("Content-Type", "application/json")
-            .header("Authorization", format!("Bearer {}", api_key))
-            .json(request)
-            .send()
-            .await
-            .map_err(|e| anyhow::anyhow!("Failed to send request to OpenAI API: {}", e))?;
+        let mock_server = server
+            .mock("POST", "/v1/messages")
+            .match_header("x-api-key", "test-key")
+            .match_header("anthropic-version", ANTHROPIC_VERSION)
+            .match_body(mockito::Matcher::PartialJson(json!({
+                "model": "claude-sonnet-4-5",
+                "max_tokens": ANTHROPIC_MAX_TOKENS,
+                "messages": [{ "role": "user" }]
+            })))
+            .with_status(200)
+            .with_body(mock_response.to_string())
+            .create_async()
+            .await;
 
-        // 检查响应状态
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response
-                .text()
-                .await
-                .unwrap_or_else(|_| String::from("Unable to get error details"));
+        let settings = Settings {
+            base_url: Some(format!("{}/v1", server.url())),
+            openai_api_key: Some("test-key".to_string()),
+            provider: Provider::Anthropic,
+            model: "claude-sonnet-4-5".to_string(),
+            ..Default::default()
+        };
 
-            return Err(anyhow::anyhow!(
-                "API request failed with status {}: {}",
-                status,
-                error_text
-            ));
-        }
+        let conflict = ConflictFile {
+            path: "test.txt".to_string(),
+            raw_path: b"test.txt".to_vec(),
+            our_content: "Our content".to_string(),
+            their_content: "Their content".to_string(),
+            base_content: None,
+            marked_content: None,
+            is_binary: false,
+            kind: ConflictKind::Content,
+        };
 
-        // 解析JSON响应
-        let response_text = response
-            .text()
-            .await
-            .map_err(|e| anyhow::anyhow!("Failed to get response text: {}", e))?;
+        let resolver = ConflictResolver::new(settings);
 
-        debug!("OpenAI API response: {}", response_text);
+        let resolution = resolver.resolve_conflict(&conflict).await?;
+        assert_eq!(resolution, "Resolved by Claude");
 
-        let chat_response: ChatResponse = serde_json::from_str(&response_text).map_err(|e| {
-            anyhow::anyhow!(
-                "Failed to parse API response: {}, Response: {}",
-                e,
-                response_text
-            )
-        })?;
+        mock_server.assert_async().await;
 
-        match chat_response.choices.first() {
-            Some(choice) => Ok(choice.message.content.clone()),
-            None => Err(anyhow::anyhow!("No resolution provided by AI")),
-        }
+        Ok(())
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use mockito::Server;
-    use serde_json::json;
 
     #[tokio::test]
-    async fn test_resolve_conflict() -> Result<()> {
-        // 设置模拟服务器
+    async fn test_resolve_conflict_ollama_without_api_key() -> Result<()> {
         let mut server = Server::new_async().await;
 
-        // 获取模拟服务器地址
-        let server_url = format!("http://{}/v1/chat/completions", server.host_with_port());
-
-        // 模拟简化后的 OpenAI API 响应
         let mock_response = json!({
             "choices": [
                 {
                     "message": {
                         "role": "assistant",
-                        "content": "Resolved content"
+                        "content": "Resolved locally"
                     }
                 }
             ]
         });
 
-        // 检查精简后的冲突描述
+        // 未配置密钥时不应发送 Authorization 请求头
         let mock_server = server
             .mock("POST", "/v1/chat/completions")
-            .match_header("content-type", "application/json")
+            .match_header("authorization", mockito::Matcher::Missing)
             .with_status(200)
             .with_body(mock_response.to_string())
             .create_async()
             .await;
 
-        // 创建带有模拟设置的冲突解析器
-        let mut settings = Settings::default();
-        settings.openai_api_key = Some("test-key".to_string());
-        settings.model = "gpt-3.5-turbo".to_string();
+        let settings = Settings {
+            base_url: Some(format!("{}/v1", server.url())),
+            openai_api_key: None,
+            provider: Provider::Ollama,
+            model: "llama3".to_string(),
+            ..Default::default()
+        };
+        assert!(settings.ai_available());
 
-        // 创建一个测试冲突文件
         let conflict = ConflictFile {
             path: "test.txt".to_string(),
+            raw_path: b"test.txt".to_vec(),
             our_content: "Our content".to_string(),
             their_content: "Their content".to_string(),
-            base_content: Some("Base content".to_string()),
+            base_content: None,
+            marked_content: None,
+            is_binary: false,
+            kind: ConflictKind::Content,
         };
 
-        // 创建带有自定义客户端和 URL 的解析器
-        let resolver = ConflictResolver::with_api_url(settings, server_url);
-
-        // 模拟解析冲突
+        let resolver = ConflictResolver::new(settings);
         let resolution = resolver.resolve_conflict(&conflict).await?;
+        assert_eq!(resolution, "Resolved locally");
 
-        // 验证结果
-        assert_eq!(resolution, "Resolved content");
-
-        // 确保模拟服务器被调用
         mock_server.assert_async().await;
 
         Ok(())
     }
 
-    // 测试没有基础版本的情况
     #[tokio::test]
-    async fn test_resolve_conflict_without_base() -> Result<()> {
-        // 设置模拟服务器
+    async fn test_check() -> Result<()> {
         let mut server = Server::new_async().await;
 
-        // 获取模拟服务器地址
-        let server_url = format!("http://{}/v1/chat/completions", server.host_with_port());
-
-        // 模拟简化后的 OpenAI API 响应
         let mock_response = json!({
+            "model": "gpt-4-0613",
             "choices": [
                 {
                     "message": {
                         "role": "assistant",
-                        "content": "Resolved without base"
+                        "content": "hello world\n"
                     }
                 }
             ]
         });
 
-        // 检查没有基础版本的请求
+        // 检查只发送一次请求，不重试
         let mock_server = server
             .mock("POST", "/v1/chat/completions")
-            .with_header("content-type", "application/json")
+            .match_header("authorization", "Bearer test-key")
             .with_status(200)
             .with_body(mock_response.to_string())
+            .expect(1)
             .create_async()
             .await;
 
-        // 创建带有模拟设置的冲突解析器
-        let mut settings = Settings::default();
-        settings.openai_api_key = Some("test-key".to_string());
-        settings.model = "gpt-3.5-turbo".to_string();
-
-        // 创建一个测试冲突文件，没有基础版本
-        let conflict = ConflictFile {
-            path: "test.txt".to_string(),
-            our_content: "Our content".to_string(),
-            their_content: "Their content".to_string(),
-            base_content: None,
+        let settings = Settings {
+            base_url: Some(format!("{}/v1", server.url())),
+            openai_api_key: Some("test-key".to_string()),
+            model: "gpt-4".to_string(),
+            ..Default::default()
         };
 
-        // 创建带有自定义客户端和 URL 的解析器
-        let resolver = ConflictResolver::with_api_url(settings, server_url);
-
-        // 模拟解析冲突
-        let resolution = resolver.resolve_conflict(&conflict).await?;
-
-        // 验证结果
-        assert_eq!(resolution, "Resolved without base");
+        let report = ConflictResolver::new(settings).check().await?;
+        assert_eq!(report.requested_model, "gpt-4");
+        assert_eq!(report.model.as_deref(), Some("gpt-4-0613"));
+        assert_eq!(report.resolution, "hello world\n");
 
-        // 确保模拟服务器被调用
         mock_server.assert_async().await;
 
         Ok(())
     }
 
-    // 测试 OpenAI 返回空结果的错误情况
     #[tokio::test]
-    async fn test_resolve_conflict_empty_response() -> Result<()> {
-        // 设置模拟服务器
+    async fn test_resolve_conflict_azure() -> Result<()> {
         let mut server = Server::new_async().await;
 
-        // 模拟空结果响应
         let mock_response = json!({
-            "choices": []
+            "choices": [
+                {
+                    "message": {
+                        "role": "assistant",
+                        "content": "Resolved by Azure"
+                    }
+                }
+            ]
         });
 
-        // 创建空结果测试用的模拟服务器
+        // 使用部署名称和 api-version 构造地址，并通过 api-key 请求头认证
         let mock_server = server
-            .mock("POST", "/v1/chat/completions")
-            .expect(1) // 设置期望只收到1次请求
-            .with_header("content-type", "application/json")
+            .mock("POST", "/openai/deployments/my-gpt4/chat/completions")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "api-version".into(),
+                "2024-06-01".into(),
+            ))
+            .match_header("api-key", "test-key")
+            .match_header("authorization", mockito::Matcher::Missing)
             .with_status(200)
             .with_body(mock_response.to_string())
             .create_async()
             .await;
 
-        // 创建带有模拟设置的冲突解析器
-        let mut settings = Settings::default();
-        settings.openai_api_key = Some("test-key".to_string());
-        settings.model = "gpt-3.5-turbo".to_string();
-        settings.max_retries = 0; // 设置为0，禁用重试功能
+        let settings = Settings {
+            openai_api_key: Some("test-key".to_string()),
+            provider: Provider::Azure,
+            azure_endpoint: Some(format!("{}/", server.url())),
+            azure_deployment: Some("my-gpt4".to_string()),
+            model: "gpt-4".to_string(),
+            ..Default::default()
+        };
 
-        // 创建一个测试冲突文件
         let conflict = ConflictFile {
             path: "test.txt".to_string(),
+            raw_path: b"test.txt".to_vec(),
             our_content: "Our content".to_string(),
             their_content: "Their content".to_string(),
-            base_content: Some("Base content".to_string()),
+            base_content: None,
+            marked_content: None,
+            is_binary: false,
+            kind: ConflictKind::Content,
         };
 
-        // 创建带有自定义客户端和 URL 的解析器
-        let resolver = ConflictResolver::with_api_url(
-            settings,
-            format!("http://{}/v1/chat/completions", server.host_with_port()),
-        );
+        let resolver = ConflictResolver::new(settings);
+        let resolution = resolver.resolve_conflict(&conflict).await?;
+        assert_eq!(resolution, "Resolved by Azure");
 
-        // 模拟解析冲突，应该返回错误
-        let result = resolver.resolve_conflict(&conflict).await;
+        mock_server.assert_async().await;
 
-        // 验证结果是错误
-        assert!(result.is_err());
+        Ok(())
+    }
+
+    // 测试从服务端查询上下文窗口并缓存
+    #[tokio::test]
+    async fn test_context_window_from_endpoint() -> Result<()> {
+        let mut server = Server::new_async().await;
+
+        // 即使多次获取，也只应查询一次
+        let mock_server = server
+            .mock("GET", "/v1/models/custom-model")
+            .expect(1)
+            .with_status(200)
+            .with_body(json!({ "id": "custom-model", "context_length": 32000 }).to_string())
+            .create_async()
+            .await;
+
+        let settings = Settings {
+            openai_api_key: Some("test-key".to_string()),
+            model: "custom-model".to_string(),
+            context_window_from_model: true,
+            model_info_url: Some(format!(
+                "http://{}/v1/models/custom-model",
+                server.host_with_port()
+            )),
+            ..Default::default()
+        };
+        let resolver = ConflictResolver::new(settings);
+
+        assert_eq!(resolver.context_window().await, 32000);
+        assert_eq!(resolver.context_window().await, 32000);
 
-        // 确保模拟服务器被调用
         mock_server.assert_async().await;
 
         Ok(())
     }
 
-    // 测试 API 错误响应
+    // 测试服务端不提供元数据时回退到静态表
     #[tokio::test]
-    async fn test_api_error_response() -> Result<()> {
-        // 设置模拟服务器
+    async fn test_context_window_fallback() -> Result<()> {
         let mut server = Server::new_async().await;
 
-        // 模拟 API 错误
-        let error_response = json!({
-            "error": {
-                "message": "Invalid API key",
-                "type": "invalid_request_error",
-                "code": "invalid_api_key"
-            }
+        let mock_server = server
+            .mock("GET", "/v1/models/gpt-4o")
+            .with_status(200)
+            .with_body(json!({ "id": "gpt-4o", "object": "model" }).to_string())
+            .create_async()
+            .await;
+
+        let settings = Settings {
+            openai_api_key: Some("test-key".to_string()),
+            model: "gpt-4o".to_string(),
+            context_window_from_model: true,
+            model_info_url: Some(format!(
+                "http://{}/v1/models/gpt-4o",
+                server.host_with_port()
+            )),
+            ..Default::default()
+        };
+        let resolver = ConflictResolver::new(settings);
+
+        assert_eq!(resolver.context_window().await, 128_000);
+
+        mock_server.assert_async().await;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_lenient_json() -> Result<()> {
+        // 合法的 JSON 直接解析
+        let value: serde_json::Value = parse_lenient_json(r#"{"a.rs": "fn a() {}"}"#)?;
+        assert_eq!(value["a.rs"], "fn a() {}");
+
+        // 代码块标记
+        let value: serde_json::Value = parse_lenient_json("```json\n{\"a.rs\": \"content\"}\n```")?;
+        assert_eq!(value["a.rs"], "content");
+
+        // 前后夹杂说明文字
+        let value: serde_json::Value =
+            parse_lenient_json("Here is the result:\n{\"a.rs\": \"content\"}\nDone.")?;
+        assert_eq!(value["a.rs"], "content");
+
+        // 尾随逗号，且字符串中的逗号保持不变
+        let value: serde_json::Value =
+            parse_lenient_json("{\"a.rs\": \"x, }\", \"b.rs\": [1, 2,],}")?;
+        assert_eq!(value["a.rs"], "x, }");
+        assert_eq!(value["b.rs"], json!([1, 2]));
+
+        // 无法修复的内容返回错误
+        assert!(parse_lenient_json::<serde_json::Value>("not json at all").is_err());
+
+        Ok(())
+    }
+
+    // 测试 diff3 风格时将带共同祖先的冲突标记发送给AI
+    #[tokio::test]
+    async fn test_resolve_conflict_diff3() -> Result<()> {
+        let mut server = Server::new_async().await;
+
+        let mock_response = json!({
+            "choices": [
+                {
+                    "message": {
+                        "role": "assistant",
+                        "content": "merged"
+                    }
+                }
+            ]
         });
 
-        // 创建错误测试用的模拟服务器
         let mock_server = server
             .mock("POST", "/v1/chat/completions")
-            .expect(1) // 设置期望只收到1次请求
-            .with_header("content-type", "application/json")
-            .with_status(401)
-            .with_body(error_response.to_string())
+            .match_body(mockito::Matcher::Regex(r"\|\|\|\|\|\|\| base".to_string()))
+            .with_status(200)
+            .with_body(mock_response.to_string())
             .create_async()
             .await;
 
-        // 创建带有模拟设置的冲突解析器
-        let mut settings = Settings::default();
-        settings.openai_api_key = Some("invalid-key".to_string());
-        settings.model = "gpt-3.5-turbo".to_string();
-        settings.max_retries = 0; // 设置为0，禁用重试功能
+        let settings = Settings {
+            base_url: Some(format!("{}/v1", server.url())),
+            openai_api_key: Some("test-key".to_string()),
+            conflict_style: ConflictStyle::Diff3,
+            ..Default::default()
+        };
 
-        // 创建一个测试冲突文件
         let conflict = ConflictFile {
             path: "test.txt".to_string(),
-            our_content: "Our content".to_string(),
-            their_content: "Their content".to_string(),
-            base_content: Some("Base content".to_string()),
+            raw_path: b"test.txt".to_vec(),
+            our_content: "ours\n".to_string(),
+            their_content: "theirs\n".to_string(),
+            base_content: Some("base\n".to_string()),
+            marked_content: Some(
+                "<<<<<<< ours\nours\n||||||| base\nbase\n=======\ntheirs\n>>>>>>> theirs\n"
+                    .to_string(),
+            ),
+            is_binary: false,
+            kind: ConflictKind::Content,
         };
 
-        // 创建带有自定义客户端和 URL 的解析器
-        let resolver = ConflictResolver::with_api_url(
-            settings,
-            format!("http://{}/v1/chat/completions", server.host_with_port()),
-        );
-
-        // 模拟解析冲突，应该返回错误
-        let result = resolver.resolve_conflict(&conflict).await;
-
-        // 验证结果是错误
-        assert!(result.is_err());
-        assert!(result
-            .unwrap_err()
-            .to_string()
-            .contains("API request failed with status 401"));
+        let resolver = ConflictResolver::new(settings);
+        assert_eq!(resolver.resolve_conflict(&conflict).await?, "merged");
 
-        // 确保模拟服务器被调用
         mock_server.assert_async().await;
 
         Ok(())
@@ -0,0 +1,91 @@
+use anyhow::Result;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 审计日志中的一条记录，对应一个已应用AI解决方案的冲突文件
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct AuditEntry {
+    /// 应用解决方案的时间，Unix 时间戳（秒）
+    pub timestamp: u64,
+    /// 仓库的工作目录
+    pub repo: String,
+    /// 冲突文件相对于工作目录的路径
+    pub path: String,
+    /// 生成解决方案的模型，按 LFS 策略选择一方时为 null
+    pub model: Option<String>,
+    /// 写入文件的内容的 SHA-256，十六进制
+    pub resolution_sha256: String,
+}
+
+impl AuditEntry {
+    pub fn new(repo: &Path, path: &str, model: Option<&str>, resolution: &str) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        Self {
+            timestamp,
+            repo: repo.display().to_string(),
+            path: path.to_string(),
+            model: model.map(str::to_string),
+            resolution_sha256: Sha256::digest(resolution.as_bytes())
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect(),
+        }
+    }
+}
+
+/// 以 JSON Lines 格式将记录追加到审计日志，文件不存在时创建
+pub fn append(log: &Path, entry: &AuditEntry) -> Result<()> {
+    let mut line = serde_json::to_vec(entry)?;
+    line.push(b'\n');
+
+    let mut file = OpenOptions::new().create(true).append(true).open(log)?;
+    // 一次写入整行，避免多个进程同时追加时内容交错
+    file.write_all(&line)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_append() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let log = temp_dir.path().join("audit.jsonl");
+
+        let first = AuditEntry::new(Path::new("/repo"), "src/a.rs", Some("gpt-4"), "merged");
+        assert_eq!(
+            first.resolution_sha256,
+            "3f8f09c8e09f712b362183db69f4f061bd948d7a61e7663b585d723602c559b1"
+        );
+        append(&log, &first)?;
+        append(
+            &log,
+            &AuditEntry::new(Path::new("/repo"), "src/b.rs", None, "other"),
+        )?;
+
+        let content = std::fs::read_to_string(&log)?;
+        let lines: Vec<serde_json::Value> = content
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["repo"], "/repo");
+        assert_eq!(lines[0]["path"], "src/a.rs");
+        assert_eq!(lines[0]["model"], "gpt-4");
+        assert_eq!(lines[0]["resolution_sha256"], first.resolution_sha256);
+        assert_eq!(lines[1]["path"], "src/b.rs");
+        assert_eq!(lines[1]["model"], serde_json::Value::Null);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,163 @@
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use tracing::*;
+
+use crate::ai::Resolution;
+use crate::config::Settings;
+use crate::git::ConflictFile;
+
+/// 磁盘上的AI解决方案缓存，以冲突内容和模型的哈希为键
+///
+/// 中止合并后重新运行时，相同的冲突可以直接使用之前的解决方案，不再请求AI
+#[derive(Debug, Clone)]
+pub struct ResolutionCache {
+    dir: PathBuf,
+}
+
+impl ResolutionCache {
+    /// 使用指定目录存放缓存，目录不存在时在第一次写入时创建
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// 使用配置目录下的 `cache` 目录（默认为 `~/.config/git-tools/cache`）
+    pub fn open_default() -> Result<Self> {
+        Ok(Self::new(Settings::config_dir()?.join("cache")))
+    }
+
+    /// 计算冲突的缓存键，包含双方、共同祖先的内容以及模型
+    pub fn key(conflict: &ConflictFile, model: &str) -> String {
+        let mut hasher = Sha256::new();
+        // 每个字段前写入长度，避免不同字段的拼接结果相同
+        let mut field = |bytes: &[u8]| {
+            hasher.update((bytes.len() as u64).to_le_bytes());
+            hasher.update(bytes);
+        };
+        field(conflict.our_content.as_bytes());
+        field(conflict.their_content.as_bytes());
+        match &conflict.base_content {
+            Some(base) => {
+                field(b"base");
+                field(base.as_bytes());
+            }
+            None => field(b"no-base"),
+        }
+        field(model.as_bytes());
+
+        hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    /// 读取缓存的解决方案，不存在或无法解析时返回 None
+    pub fn get(&self, key: &str) -> Option<Resolution> {
+        let content = std::fs::read_to_string(self.path(key)).ok()?;
+        match serde_json::from_str(&content) {
+            Ok(resolution) => Some(resolution),
+            Err(e) => {
+                warn!("Ignoring invalid cache entry {}: {}", key, e);
+                None
+            }
+        }
+    }
+
+    /// 写入解决方案
+    pub fn put(&self, key: &str, resolution: &Resolution) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        // 先写入临时文件再重命名，避免并发运行时读到不完整的内容
+        let mut file = tempfile::NamedTempFile::new_in(&self.dir)?;
+        serde_json::to_writer(&mut file, resolution)?;
+        file.persist(self.path(key))?;
+        Ok(())
+    }
+
+    /// 删除缓存的解决方案，不存在时忽略
+    pub fn remove(&self, key: &str) -> Result<()> {
+        match std::fs::remove_file(self.path(key)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::ConflictKind;
+    use tempfile::TempDir;
+
+    fn conflict(ours: &str, theirs: &str, base: Option<&str>) -> ConflictFile {
+        ConflictFile {
+            path: "test.txt".to_string(),
+            raw_path: b"test.txt".to_vec(),
+            our_content: ours.to_string(),
+            their_content: theirs.to_string(),
+            base_content: base.map(str::to_string),
+            marked_content: None,
+            is_binary: false,
+            kind: ConflictKind::Content,
+        }
+    }
+
+    #[test]
+    fn test_key() {
+        let key = ResolutionCache::key(&conflict("a", "b", Some("c")), "gpt-4");
+        assert_eq!(key.len(), 64);
+        assert_eq!(
+            key,
+            ResolutionCache::key(&conflict("a", "b", Some("c")), "gpt-4")
+        );
+
+        // 任一字段不同都应得到不同的键
+        assert_ne!(
+            key,
+            ResolutionCache::key(&conflict("a", "b", Some("c")), "gpt-4o")
+        );
+        assert_ne!(
+            key,
+            ResolutionCache::key(&conflict("a", "b", None), "gpt-4")
+        );
+        assert_ne!(
+            key,
+            ResolutionCache::key(&conflict("ab", "", Some("c")), "gpt-4")
+        );
+    }
+
+    #[test]
+    fn test_get_and_put() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let cache = ResolutionCache::new(temp_dir.path().join("cache"));
+        let key = ResolutionCache::key(&conflict("a", "b", None), "gpt-4");
+
+        assert_eq!(cache.get(&key), None);
+
+        let resolution = Resolution {
+            content: "merged".to_string(),
+            reasoning: Some("kept both".to_string()),
+            attempts: 0,
+            model: Some("gpt-4".to_string()),
+        };
+        cache.put(&key, &resolution)?;
+        assert_eq!(cache.get(&key), Some(resolution));
+
+        cache.remove(&key)?;
+        assert_eq!(cache.get(&key), None);
+        cache.remove(&key)?;
+
+        // 旧版本写入的缓存没有 model 字段，仍然可以读取
+        std::fs::write(
+            cache.path(&key),
+            r#"{"content": "merged", "reasoning": null}"#,
+        )?;
+        assert_eq!(cache.get(&key).unwrap().model, None);
+
+        Ok(())
+    }
+}
@@ -1,8 +1,31 @@
-use config::{Config, Environment, File};
+use config::{Config, Environment, File, Map, Source, Value};
 use serde::{Deserialize, Serialize};
 use std::env;
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use thiserror::Error;
+use tracing::*;
+
+/// max_retries 允许的最大值，每次重试都会重新发送完整的请求
+pub const MAX_RETRIES: u32 = 10;
+
+/// 提交到仓库中的配置文件名，位于仓库根目录
+pub const REPO_CONFIG_FILE: &str = ".git-tools.toml";
+
+/// 仓库配置文件中会被忽略的配置项
+///
+/// 除了密钥本身，还包括会把密钥或代码发往其他地址、执行命令或写入任意文件的配置，
+/// 避免克隆的仓库借此窃取密钥
+const REPO_CONFIG_IGNORED_KEYS: [&str; 8] = [
+    "openai_api_key",
+    "openai_api_key_file",
+    "base_url",
+    "azure_endpoint",
+    "model_info_url",
+    "on_conflict_command",
+    "on_conflict_webhook",
+    "audit_log",
+];
 
 #[derive(Error, Debug)]
 pub enum ConfigError {
@@ -17,16 +40,237 @@ pub enum ConfigError {
 
     #[error("Failed to save config: {0}")]
     SaveError(String),
+
+    #[error("Invalid temperature {0}: must be between 0.0 and 2.0")]
+    InvalidTemperature(f32),
+
+    #[error("Invalid max_tokens 0: must be a positive number")]
+    InvalidMaxTokens,
+
+    #[error("Invalid model: must not be empty")]
+    EmptyModel,
+
+    #[error("Invalid timeout_seconds 0: must be at least 1")]
+    InvalidTimeout,
+
+    #[error("Invalid max_retries {0}: must be at most {MAX_RETRIES}")]
+    TooManyRetries(u32),
+
+    #[error("Invalid concurrency 0: must be at least 1")]
+    InvalidConcurrency,
+
+    #[error("Failed to read API key file {}: {}", .0.display(), .1)]
+    KeyFileError(PathBuf, std::io::Error),
+
+    #[error("Azure OpenAI endpoint not found: set azure_endpoint or base_url")]
+    MissingAzureEndpoint,
+}
+
+/// AI 服务提供商
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    /// OpenAI 及兼容 chat completions 接口的服务
+    #[default]
+    OpenAi,
+    /// Google Gemini generateContent 接口
+    Gemini,
+    /// Anthropic Claude messages 接口
+    Anthropic,
+    /// 本地运行的 Ollama，使用 OpenAI 兼容接口，不需要 API 密钥
+    Ollama,
+    /// Azure OpenAI，按部署名称访问 chat completions 接口
+    Azure,
+}
+
+impl Provider {
+    /// 是否必须配置 API 密钥
+    pub fn requires_api_key(self) -> bool {
+        !matches!(self, Provider::Ollama)
+    }
+}
+
+/// 冲突标记风格
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictStyle {
+    /// 标准的合并风格，只包含双方的内容
+    #[default]
+    Merge,
+    /// diff3 风格，额外用 `|||||||` 标出共同祖先的内容
+    Diff3,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// git-lfs 指针文件冲突时保留哪一方
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LfsPolicy {
+    /// 保留当前分支的指针
+    #[default]
+    Ours,
+    /// 保留合并进来的分支的指针
+    Theirs,
+}
+
+/// 冲突的解决策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictStrategy {
+    /// 交给AI解决
+    #[default]
+    Ai,
+    /// 采用当前分支的内容
+    Ours,
+    /// 采用合并进来的分支的内容
+    Theirs,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Settings {
+    /// 所选服务提供商的 API 密钥，`file:<路径>` 形式表示从该文件读取
     pub openai_api_key: Option<String>,
+    /// 保存 API 密钥的文件，例如 Docker/Kubernetes 挂载的 secret，优先于 openai_api_key
+    #[serde(default)]
+    pub openai_api_key_file: Option<PathBuf>,
+    #[serde(default)]
+    pub provider: Provider,
+    /// API 基础地址，用于自建服务或代理，未设置时使用提供商的官方地址
+    #[serde(default)]
+    pub base_url: Option<String>,
+    /// Azure OpenAI 资源的地址，例如 `https://my-resource.openai.azure.com`
+    #[serde(default)]
+    pub azure_endpoint: Option<String>,
+    /// Azure OpenAI 的部署名称，未设置时使用 model
+    #[serde(default)]
+    pub azure_deployment: Option<String>,
+    /// Azure OpenAI 的 api-version 查询参数
+    #[serde(default = "default_azure_api_version")]
+    pub azure_api_version: String,
     pub model: String,
+    /// 主模型重试后仍然失败或过载时改用的备用模型
+    #[serde(default)]
+    pub fallback_model: Option<String>,
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub max_retries: u32,
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub timeout_seconds: u64,
+    /// 采样温度，范围 0.0 到 2.0，越低结果越稳定可复现
+    #[serde(
+        default = "default_temperature",
+        deserialize_with = "deserialize_number_from_string"
+    )]
+    pub temperature: f32,
+    /// AI 回复的最大 token 数，未设置时由服务端决定
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    /// 是否在启动时向服务端查询模型的上下文窗口大小
+    #[serde(default)]
+    pub context_window_from_model: bool,
+    /// 冲突文件不超过该字节数时将完整内容发送给AI，超过时只发送冲突附近的内容
+    #[serde(default = "default_max_full_context_bytes")]
+    pub max_full_context_bytes: usize,
+    /// 冲突双方和共同祖先的内容合计超过该字节数时不发送给AI，留给用户手动解决
+    #[serde(default = "default_max_conflict_bytes")]
+    pub max_conflict_bytes: usize,
+    /// 手动指定的上下文窗口大小（token 数），设置后不再查询服务端
+    #[serde(default)]
+    pub context_window: Option<u32>,
+    /// 查询模型元数据的地址，未设置时使用提供商的默认地址
+    #[serde(default)]
+    pub model_info_url: Option<String>,
+    /// 检测到冲突时执行的命令，冲突信息以 JSON 格式写入其标准输入
+    #[serde(default)]
+    pub on_conflict_command: Option<String>,
+    /// 检测到冲突时以 POST 方式通知的 Webhook 地址
+    #[serde(default)]
+    pub on_conflict_webhook: Option<String>,
+    /// 冲突标记风格，diff3 会将共同祖先的内容一并提供给AI
+    #[serde(default)]
+    pub conflict_style: ConflictStyle,
+    /// 替换内置默认系统提示词的自定义提示词
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    /// 重试时轮换使用的系统提示词模板，为空时使用内置模板
+    #[serde(default)]
+    pub prompt_templates: Vec<String>,
+    /// 重试时是否轮换到下一个提示词模板
+    #[serde(default)]
+    pub retry_different_prompt: bool,
+    /// 是否以流式方式获取并逐步输出AI的回复
+    #[serde(default)]
+    pub stream: bool,
+    /// git-lfs 指针文件冲突时保留哪一方，这类冲突不会发送给AI
+    #[serde(default)]
+    pub lfs_policy: LfsPolicy,
+    /// 每千个提示 token 的价格，用于估算费用
+    #[serde(default)]
+    pub prompt_price_per_1k: Option<f64>,
+    /// 每千个补全 token 的价格，用于估算费用
+    #[serde(default)]
+    pub completion_price_per_1k: Option<f64>,
+    /// 即使仓库未配置 commit.gpgsign 也对创建的提交签名
+    #[serde(default)]
+    pub sign_commits: bool,
+    /// 是否将多个冲突合并到一个请求中解决，减少请求次数
+    #[serde(default)]
+    pub batch: bool,
+    /// 是否要求AI在解决方案之外说明选择该方案的理由
+    #[serde(default)]
+    pub explain: bool,
+    /// 输出AI的解决方案相对于我们的版本的差异，而不是完整内容
+    #[serde(default)]
+    pub show_diff: bool,
+    /// 是否在磁盘上缓存AI的解决方案，重新运行时相同的冲突不再请求AI
+    #[serde(default = "default_cache")]
+    pub cache: bool,
+    /// 审计日志文件，设置后每应用一个AI解决方案就追加一行 JSON 记录
+    #[serde(default)]
+    pub audit_log: Option<PathBuf>,
+    /// 按路径选择冲突解决策略的规则文件，相对路径相对于仓库的工作目录
+    #[serde(default)]
+    pub strategy_rules: Option<PathBuf>,
+    /// 一次运行中用于AI解决冲突的总时间（秒），超出后剩余的冲突留给用户手动解决
+    #[serde(default)]
+    pub total_timeout_seconds: Option<u64>,
+    /// 同时向AI请求解决方案的冲突数，为 1 时逐个解决
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+}
+
+/// 较长的密钥保留前 3 个和后 4 个字符，较短的完全隐藏
+fn redact_secret(secret: &str) -> String {
+    let chars: Vec<char> = secret.chars().collect();
+    if chars.len() <= 12 {
+        return "****".to_string();
+    }
+
+    let head: String = chars[..3].iter().collect();
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    format!("{}...{}", head, tail)
+}
+
+fn default_temperature() -> f32 {
+    0.7
+}
+
+fn default_max_full_context_bytes() -> usize {
+    8 * 1024
+}
+
+fn default_max_conflict_bytes() -> usize {
+    1024 * 1024
+}
+
+fn default_cache() -> bool {
+    true
+}
+
+fn default_concurrency() -> usize {
+    1
+}
+
+fn default_azure_api_version() -> String {
+    String::from("2024-06-01")
 }
 
 fn deserialize_number_from_string<'de, T, D>(deserializer: D) -> Result<T, D::Error>
@@ -53,10 +297,69 @@ impl Default for Settings {
     fn default() -> Self {
         Self {
             openai_api_key: None,
+            openai_api_key_file: None,
+            provider: Provider::default(),
+            base_url: None,
+            azure_endpoint: None,
+            azure_deployment: None,
+            azure_api_version: default_azure_api_version(),
             model: String::from("gpt-4"),
+            fallback_model: None,
             max_retries: 3,
             timeout_seconds: 30,
+            temperature: default_temperature(),
+            max_tokens: None,
+            context_window_from_model: false,
+            max_full_context_bytes: default_max_full_context_bytes(),
+            max_conflict_bytes: default_max_conflict_bytes(),
+            context_window: None,
+            model_info_url: None,
+            on_conflict_command: None,
+            on_conflict_webhook: None,
+            conflict_style: ConflictStyle::default(),
+            system_prompt: None,
+            prompt_templates: Vec::new(),
+            retry_different_prompt: false,
+            stream: false,
+            lfs_policy: LfsPolicy::default(),
+            prompt_price_per_1k: None,
+            completion_price_per_1k: None,
+            sign_commits: false,
+            batch: false,
+            explain: false,
+            show_diff: false,
+            cache: default_cache(),
+            audit_log: None,
+            strategy_rules: None,
+            total_timeout_seconds: None,
+            concurrency: default_concurrency(),
+        }
+    }
+}
+
+/// 仓库中的配置文件，读取时去掉不应提交到仓库的配置项
+#[derive(Debug, Clone)]
+struct RepoConfigSource {
+    path: PathBuf,
+}
+
+impl Source for RepoConfigSource {
+    fn clone_into_box(&self) -> Box<dyn Source + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn collect(&self) -> Result<Map<String, Value>, config::ConfigError> {
+        let mut map = File::from(self.path.as_path()).required(false).collect()?;
+        for key in REPO_CONFIG_IGNORED_KEYS {
+            if map.remove(key).is_some() {
+                warn!(
+                    "Ignoring '{}' in repository config {}",
+                    key,
+                    self.path.display()
+                );
+            }
         }
+        Ok(map)
     }
 }
 
@@ -64,8 +367,60 @@ impl Settings {
     /// 加载配置,按以下顺序(后面的会覆盖前面的):
     /// 1. 默认值
     /// 2. 配置文件 (~/.config/git-tools/config.json 或当前目录 config.json)
-    /// 3. 环境变量 (GT_* 或 OPENAI_API_KEY)
-    pub fn load() -> Result<Self, ConfigError> {
+    /// 3. 仓库根目录的 `.git-tools.toml`（repo_dir 为仓库的工作目录，密钥等配置项会被忽略）
+    /// 4. 环境变量 (GT_* 或 OPENAI_API_KEY)
+    pub fn load(repo_dir: Option<&Path>) -> Result<Self, ConfigError> {
+        let config = Self::load_merged(repo_dir)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// 校验配置项的取值，在启动时发现会导致运行中途失败的配置
+    ///
+    /// 先校验各项的取值，最后才检查API密钥，缺少密钥时其余配置仍然是有效的
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.model.trim().is_empty() {
+            return Err(ConfigError::EmptyModel);
+        }
+
+        if self.timeout_seconds == 0 {
+            return Err(ConfigError::InvalidTimeout);
+        }
+
+        if self.max_retries > MAX_RETRIES {
+            return Err(ConfigError::TooManyRetries(self.max_retries));
+        }
+
+        if self.concurrency == 0 {
+            return Err(ConfigError::InvalidConcurrency);
+        }
+
+        if !(0.0..=2.0).contains(&self.temperature) {
+            return Err(ConfigError::InvalidTemperature(self.temperature));
+        }
+
+        if self.max_tokens == Some(0) {
+            return Err(ConfigError::InvalidMaxTokens);
+        }
+
+        // Azure 没有统一的官方地址，必须指定资源地址
+        if self.provider == Provider::Azure
+            && self.azure_endpoint.is_none()
+            && self.base_url.is_none()
+        {
+            return Err(ConfigError::MissingAzureEndpoint);
+        }
+
+        // 验证必需的配置项，本地服务不需要 API 密钥
+        if self.openai_api_key.is_none() && self.provider.requires_api_key() {
+            return Err(ConfigError::MissingApiKey);
+        }
+
+        Ok(())
+    }
+
+    /// 按与 `load` 相同的顺序合并各来源的配置，但不做校验，用于查看实际生效的配置
+    pub fn load_merged(repo_dir: Option<&Path>) -> Result<Self, ConfigError> {
         // 创建一个默认配置
         let default_settings = Settings::default();
 
@@ -76,6 +431,8 @@ impl Settings {
         let mut builder = Config::builder()
             // 设置默认值
             .set_default("openai_api_key", default_settings.openai_api_key.clone())?
+            .set_default("provider", "openai")?
+            .set_default("conflict_style", "merge")?
             .set_default("model", default_settings.model.clone())?
             .set_default("max_retries", default_settings.max_retries)?
             .set_default("timeout_seconds", default_settings.timeout_seconds)?
@@ -87,12 +444,21 @@ impl Settings {
             builder = builder.add_source(File::from(config_path).required(false));
         }
 
+        // 团队共享的仓库配置，位于用户配置和环境变量之间
+        if let Some(repo_dir) = repo_dir {
+            builder = builder.add_source(RepoConfigSource {
+                path: repo_dir.join(REPO_CONFIG_FILE),
+            });
+        }
+
         // 加载环境变量
         builder = builder.add_source(Environment::with_prefix("GT").try_parsing(true));
 
         // 解析配置
         let mut config: Settings = builder.build()?.try_deserialize()?;
 
+        config.read_api_key_file()?;
+
         // 如果没有设置 OpenAI API 密钥，则尝试从 OPENAI_API_KEY 环境变量获取
         if config.openai_api_key.is_none() {
             if let Ok(api_key) = env::var("OPENAI_API_KEY") {
@@ -102,15 +468,40 @@ impl Settings {
             }
         }
 
-        // 验证必需的配置项
-        if config.openai_api_key.is_none() {
-            return Err(ConfigError::MissingApiKey);
-        }
-
         Ok(config)
     }
 
-    #[allow(dead_code)] // 允许这个方法未被使用，因为它在测试中使用
+    // 从 openai_api_key_file 或 `file:` 形式的 openai_api_key 指向的文件读取密钥
+    fn read_api_key_file(&mut self) -> Result<(), ConfigError> {
+        let path = match (&self.openai_api_key_file, &self.openai_api_key) {
+            (Some(path), _) => path.clone(),
+            (None, Some(key)) => match key.strip_prefix("file:") {
+                Some(path) => PathBuf::from(path),
+                None => return Ok(()),
+            },
+            (None, None) => return Ok(()),
+        };
+
+        let key = std::fs::read_to_string(&path)
+            .map_err(|e| ConfigError::KeyFileError(path.clone(), e))?;
+        let key = key.trim();
+        self.openai_api_key = (!key.is_empty()).then(|| key.to_string());
+
+        Ok(())
+    }
+
+    /// 返回隐藏了 API 密钥的副本，只保留首尾几个字符，便于展示而不泄露密钥
+    pub fn redacted(&self) -> Self {
+        let mut settings = self.clone();
+        settings.openai_api_key = self.openai_api_key.as_deref().map(redact_secret);
+        settings
+    }
+
+    /// 是否可以使用AI：已配置 API 密钥，或提供商不需要密钥
+    pub fn ai_available(&self) -> bool {
+        self.openai_api_key.is_some() || !self.provider.requires_api_key()
+    }
+
     /// 保存配置到文件
     pub fn save(&self) -> Result<(), ConfigError> {
         let config_path = Self::get_config_path()?;
@@ -123,20 +514,69 @@ impl Settings {
         // 保存为 JSON 格式
         let json = serde_json::to_string_pretty(self)
             .map_err(|e| ConfigError::SaveError(e.to_string()))?;
-        std::fs::write(&config_path, json)?;
+
+        // 配置中可能有 API 密钥，只允许当前用户读写
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&config_path)?;
+        // mode 只在创建文件时生效，已存在的文件在写入密钥前收紧权限
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+        }
+        file.write_all(json.as_bytes())?;
 
         Ok(())
     }
 
+    /// 用户配置文件的路径（默认为 ~/.config/git-tools/config.json）
+    pub fn config_path() -> Result<PathBuf, ConfigError> {
+        Self::get_config_path()
+    }
+
+    /// 存放用户配置和缓存的目录
+    ///
+    /// 依次使用 `GT_CONFIG_DIR`、`$XDG_CONFIG_HOME/git-tools`，最后是 `~/.config/git-tools`
+    pub fn config_dir() -> Result<PathBuf, ConfigError> {
+        config_dir_with(|key| env::var_os(key), dirs::home_dir)
+    }
+
     /// 获取配置文件路径
     fn get_config_path() -> Result<PathBuf, ConfigError> {
-        let home = dirs::home_dir().ok_or_else(|| {
-            ConfigError::LoadError(config::ConfigError::NotFound(
-                "Home directory not found".to_string(),
-            ))
-        })?;
-        Ok(home.join(".config/git-tools/config.json"))
+        Ok(Self::config_dir()?.join("config.json"))
+    }
+}
+
+fn config_dir_with(
+    lookup: impl Fn(&str) -> Option<std::ffi::OsString>,
+    home_dir: impl FnOnce() -> Option<PathBuf>,
+) -> Result<PathBuf, ConfigError> {
+    let env_path = |key: &str| {
+        lookup(key)
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+    };
+
+    if let Some(dir) = env_path("GT_CONFIG_DIR") {
+        return Ok(dir);
+    }
+    // XDG 规范要求忽略相对路径
+    if let Some(dir) = env_path("XDG_CONFIG_HOME").filter(|dir| dir.is_absolute()) {
+        return Ok(dir.join("git-tools"));
     }
+
+    let home = home_dir().ok_or_else(|| {
+        ConfigError::LoadError(config::ConfigError::NotFound(
+            "Home directory not found".to_string(),
+        ))
+    })?;
+    Ok(home.join(".config/git-tools"))
 }
 
 #[cfg(test)]
@@ -149,32 +589,128 @@ mod tests {
     fn test_default_settings() {
         let settings = Settings::default();
         assert!(settings.openai_api_key.is_none());
+        assert_eq!(settings.provider, Provider::OpenAi);
         assert_eq!(settings.model, "gpt-4");
         assert_eq!(settings.max_retries, 3);
         assert_eq!(settings.timeout_seconds, 30);
+        assert_eq!(settings.temperature, 0.7);
+    }
+
+    #[test]
+    fn test_redacted() {
+        let settings = Settings {
+            openai_api_key: Some("sk-proj-1234567890abcd".to_string()),
+            ..Default::default()
+        };
+        let redacted = settings.redacted();
+        assert_eq!(redacted.openai_api_key.as_deref(), Some("sk-...abcd"));
+        assert_eq!(redacted.model, settings.model);
+
+        let short = Settings {
+            openai_api_key: Some("short-key".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(short.redacted().openai_api_key.as_deref(), Some("****"));
+        assert!(Settings::default().redacted().openai_api_key.is_none());
+    }
+
+    #[test]
+    fn test_read_api_key_file() -> Result<(), ConfigError> {
+        let temp_dir = TempDir::new().unwrap();
+        let key_path = temp_dir.path().join("openai");
+        std::fs::write(&key_path, "  sk-from-file\n")?;
+
+        // openai_api_key_file 优先于直接配置的密钥
+        let mut settings = Settings {
+            openai_api_key: Some("sk-direct".to_string()),
+            openai_api_key_file: Some(key_path.clone()),
+            ..Default::default()
+        };
+        settings.read_api_key_file()?;
+        assert_eq!(settings.openai_api_key.as_deref(), Some("sk-from-file"));
+
+        // file: 前缀
+        let mut settings = Settings {
+            openai_api_key: Some(format!("file:{}", key_path.display())),
+            ..Default::default()
+        };
+        settings.read_api_key_file()?;
+        assert_eq!(settings.openai_api_key.as_deref(), Some("sk-from-file"));
+
+        // 直接配置的密钥保持不变
+        let mut settings = Settings {
+            openai_api_key: Some("sk-direct".to_string()),
+            ..Default::default()
+        };
+        settings.read_api_key_file()?;
+        assert_eq!(settings.openai_api_key.as_deref(), Some("sk-direct"));
+
+        // 文件不存在时返回错误
+        let mut settings = Settings {
+            openai_api_key_file: Some(temp_dir.path().join("missing")),
+            ..Default::default()
+        };
+        assert!(matches!(
+            settings.read_api_key_file(),
+            Err(ConfigError::KeyFileError(..))
+        ));
+
+        Ok(())
     }
 
     #[test]
     fn test_load_from_env() {
         // 设置环境变量
         env::set_var("GT_OPENAI_API_KEY", "test-key");
+        env::set_var("GT_PROVIDER", "gemini");
+        env::set_var("GT_BASE_URL", "http://localhost:4000/v1");
         env::set_var("GT_MODEL", "gpt-3.5-turbo");
         env::set_var("GT_MAX_RETRIES", "5");
         env::set_var("GT_TIMEOUT_SECONDS", "60");
+        env::set_var("GT_SYSTEM_PROMPT", "Custom prompt");
+        env::set_var("GT_TEMPERATURE", "0.0");
 
-        let settings = Settings::load().unwrap();
+        let settings = Settings::load(None).unwrap();
 
         // 修复这行，把期望的值从"openai-key"改为"test-key"
         assert_eq!(settings.openai_api_key.unwrap(), "test-key");
+        assert_eq!(settings.provider, Provider::Gemini);
+        assert_eq!(
+            settings.base_url.as_deref(),
+            Some("http://localhost:4000/v1")
+        );
         assert_eq!(settings.model, "gpt-3.5-turbo");
         assert_eq!(settings.max_retries, 5);
         assert_eq!(settings.timeout_seconds, 60);
+        assert_eq!(settings.system_prompt.as_deref(), Some("Custom prompt"));
+        assert_eq!(settings.temperature, 0.0);
+
+        // max_tokens 必须为正数
+        env::set_var("GT_MAX_TOKENS", "2048");
+        assert_eq!(Settings::load(None).unwrap().max_tokens, Some(2048));
+        env::set_var("GT_MAX_TOKENS", "0");
+        assert!(matches!(
+            Settings::load(None),
+            Err(ConfigError::InvalidMaxTokens)
+        ));
+        env::remove_var("GT_MAX_TOKENS");
+
+        // 超出范围的 temperature 会被拒绝
+        env::set_var("GT_TEMPERATURE", "2.5");
+        assert!(matches!(
+            Settings::load(None),
+            Err(ConfigError::InvalidTemperature(_))
+        ));
 
         // 清理环境变量
         env::remove_var("GT_OPENAI_API_KEY");
+        env::remove_var("GT_PROVIDER");
+        env::remove_var("GT_BASE_URL");
         env::remove_var("GT_MODEL");
         env::remove_var("GT_MAX_RETRIES");
         env::remove_var("GT_TIMEOUT_SECONDS");
+        env::remove_var("GT_SYSTEM_PROMPT");
+        env::remove_var("GT_TEMPERATURE");
     }
 
     #[test]
@@ -183,20 +719,162 @@ mod tests {
         let temp_dir = TempDir::new().unwrap();
 
         // 修改配置文件路径为临时路径
-        env::set_var("HOME", temp_dir.path());
+        env::set_var("GT_CONFIG_DIR", temp_dir.path());
 
         // 创建测试配置
-        let mut settings = Settings::default();
-        settings.openai_api_key = Some("test-key".to_string());
-        settings.model = String::from("gpt-3.5-turbo");
+        let settings = Settings {
+            openai_api_key: Some("test-key".to_string()),
+            model: String::from("gpt-3.5-turbo"),
+            ..Default::default()
+        };
         settings.save()?;
 
         // 重新加载配置
-        let loaded = Settings::load()?;
+        let loaded = Settings::load(None)?;
 
         assert_eq!(loaded.openai_api_key.unwrap(), "test-key");
         assert_eq!(loaded.model, "gpt-3.5-turbo");
+        assert!(temp_dir.path().join("config.json").exists());
+
+        // 其他用户不能读取保存的 API 密钥，已存在的文件也会被收紧权限
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let config_path = temp_dir.path().join("config.json");
+            let mode = |path: &std::path::Path| {
+                std::fs::metadata(path).unwrap().permissions().mode() & 0o777
+            };
+            assert_eq!(mode(&config_path), 0o600);
+
+            std::fs::set_permissions(&config_path, std::fs::Permissions::from_mode(0o644)).unwrap();
+            settings.save()?;
+            assert_eq!(mode(&config_path), 0o600);
+        }
+
+        env::remove_var("GT_CONFIG_DIR");
 
         Ok(())
     }
+
+    #[test]
+    fn test_config_dir() {
+        let home = || Some(PathBuf::from("/home/user"));
+        let config_dir = |vars: &[(&str, &str)]| {
+            let vars: std::collections::HashMap<String, std::ffi::OsString> = vars
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.into()))
+                .collect();
+            config_dir_with(|key| vars.get(key).cloned(), home).unwrap()
+        };
+
+        assert_eq!(
+            config_dir(&[]),
+            PathBuf::from("/home/user/.config/git-tools")
+        );
+        assert_eq!(
+            config_dir(&[("XDG_CONFIG_HOME", "/xdg")]),
+            PathBuf::from("/xdg/git-tools")
+        );
+        // 相对路径和空值会被忽略
+        assert_eq!(
+            config_dir(&[("XDG_CONFIG_HOME", "relative"), ("GT_CONFIG_DIR", "")]),
+            PathBuf::from("/home/user/.config/git-tools")
+        );
+        assert_eq!(
+            config_dir(&[("XDG_CONFIG_HOME", "/xdg"), ("GT_CONFIG_DIR", "portable")]),
+            PathBuf::from("portable")
+        );
+
+        assert!(config_dir_with(|_| None, || None).is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let valid = Settings {
+            openai_api_key: Some("test-key".to_string()),
+            ..Default::default()
+        };
+        assert!(valid.validate().is_ok());
+
+        let settings = Settings {
+            model: "  ".to_string(),
+            ..valid.clone()
+        };
+        assert!(matches!(settings.validate(), Err(ConfigError::EmptyModel)));
+
+        let settings = Settings {
+            timeout_seconds: 0,
+            ..valid.clone()
+        };
+        assert!(matches!(
+            settings.validate(),
+            Err(ConfigError::InvalidTimeout)
+        ));
+
+        let settings = Settings {
+            max_retries: MAX_RETRIES,
+            ..valid.clone()
+        };
+        assert!(settings.validate().is_ok());
+        let settings = Settings {
+            max_retries: 1000,
+            ..valid.clone()
+        };
+        assert!(matches!(
+            settings.validate(),
+            Err(ConfigError::TooManyRetries(1000))
+        ));
+
+        let settings = Settings {
+            concurrency: 0,
+            ..valid.clone()
+        };
+        assert!(matches!(
+            settings.validate(),
+            Err(ConfigError::InvalidConcurrency)
+        ));
+
+        // 取值错误优先于缺少API密钥报告
+        let settings = Settings {
+            openai_api_key: None,
+            timeout_seconds: 0,
+            ..valid.clone()
+        };
+        assert!(matches!(
+            settings.validate(),
+            Err(ConfigError::InvalidTimeout)
+        ));
+    }
+
+    #[test]
+    fn test_repo_config_ignores_secrets() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(
+            temp_dir.path().join(REPO_CONFIG_FILE),
+            "model = \"repo-model\"\nmax_retries = 7\nopenai_api_key = \"leaked\"\nbase_url = \"http://attacker.example\"\n",
+        )
+        .unwrap();
+
+        let settings: Settings = Config::builder()
+            .set_default("timeout_seconds", 30)
+            .unwrap()
+            .add_source(RepoConfigSource {
+                path: temp_dir.path().join(REPO_CONFIG_FILE),
+            })
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+
+        assert_eq!(settings.model, "repo-model");
+        assert_eq!(settings.max_retries, 7);
+        assert_eq!(settings.openai_api_key, None);
+        assert_eq!(settings.base_url, None);
+
+        // 仓库中没有配置文件时不报错
+        let missing = RepoConfigSource {
+            path: temp_dir.path().join("missing").join(REPO_CONFIG_FILE),
+        };
+        assert!(missing.collect().unwrap().is_empty());
+    }
 }
@@ -1,38 +1,452 @@
 use anyhow::{anyhow, Result};
 use git2::{BranchType, MergeAnalysis, Oid, Repository};
+use std::collections::HashMap;
+use std::io::Write;
+use std::process::{Command, Stdio};
 use tracing::*;
 
+use crate::config::ConflictStyle;
+
+/// git-lfs 指针文件的首行
+const LFS_POINTER_HEADER: &str = "version https://git-lfs.github.com/spec/v1";
+
+/// 索引中普通文件的模式
+const FILE_MODE_REGULAR: u32 = 0o100644;
+
+/// 索引中可执行文件的模式
+const FILE_MODE_EXECUTABLE: u32 = 0o100755;
+
+/// 合并提交中列出由AI解决的文件的尾注
+pub const AI_RESOLVED_TRAILER: &str = "AI-Resolved";
+
+/// 合并提交中记录解决冲突所用模型的尾注
+pub const AI_MODEL_TRAILER: &str = "AI-Model";
+
+/// 冲突的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictKind {
+    /// 双方都修改（或都添加）了文件的内容
+    #[default]
+    Content,
+    /// 我们删除了文件，对方修改了它，只有 `their_content`
+    DeletedByUs,
+    /// 对方删除了文件，我们修改了它，只有 `our_content`
+    DeletedByThem,
+}
+
 #[derive(Debug)]
 pub struct ConflictFile {
+    /// 用于显示的路径，路径不是有效的 UTF-8 时无效字节会被替换
     pub path: String,
+    /// 索引中记录的原始路径字节
+    pub raw_path: Vec<u8>,
     pub our_content: String,
     pub their_content: String,
     pub base_content: Option<String>,
+    /// 带冲突标记的文件内容，标记风格由 `set_conflict_style` 决定
+    pub marked_content: Option<String>,
+    /// 任意一方是二进制文件或不是有效的 UTF-8 文本（例如 Latin-1、Shift-JIS 编码的文件），
+    /// 此时各版本的内容均为空，需要手动解决
+    pub is_binary: bool,
+    /// 冲突的类型，一方删除了文件时AI无法解决，需要手动决定保留还是删除
+    pub kind: ConflictKind,
+}
+
+impl ConflictFile {
+    /// 双方和共同祖先的内容合计的字节数
+    pub fn content_len(&self) -> usize {
+        self.our_content.len()
+            + self.their_content.len()
+            + self.base_content.as_ref().map_or(0, String::len)
+    }
+
+    /// 路径是否是有效的 UTF-8，否则只能报告冲突，需要手动解决
+    pub fn has_utf8_path(&self) -> bool {
+        std::str::from_utf8(&self.raw_path).is_ok()
+    }
+
+    /// 是否有一方删除了文件
+    pub fn is_deletion(&self) -> bool {
+        self.kind != ConflictKind::Content
+    }
+
+    /// 冲突中是否有一方是 git-lfs 指针文件，这类冲突应按策略选择一方而不是交给AI合并
+    pub fn is_lfs_pointer(&self) -> bool {
+        self.our_content.starts_with(LFS_POINTER_HEADER)
+            || self.their_content.starts_with(LFS_POINTER_HEADER)
+    }
+}
+
+/// 提交的元数据
+#[derive(Debug, Clone)]
+pub struct CommitInfo {
+    pub id: Oid,
+    /// 提交信息的第一段
+    pub summary: String,
+    /// 提交信息中第一段之后的内容，没有时为空
+    pub body: String,
+    pub author_name: String,
+    pub author_email: String,
+    /// 作者时间，包含提交时记录的时区
+    pub time: git2::Time,
+}
+
+impl CommitInfo {
+    fn from_commit(commit: &git2::Commit) -> Self {
+        let author = commit.author();
+        Self {
+            id: commit.id(),
+            summary: commit.summary().unwrap_or("[无效的提交信息]").to_string(),
+            body: commit.body().unwrap_or_default().trim().to_string(),
+            author_name: author.name().unwrap_or_default().to_string(),
+            author_email: author.email().unwrap_or_default().to_string(),
+            time: author.when(),
+        }
+    }
+}
+
+/// 将时间格式化为 `YYYY-MM-DD HH:MM +HHMM`，使用时间中记录的时区
+pub fn format_time(time: git2::Time) -> String {
+    let offset = time.offset_minutes() as i64;
+    let local = time.seconds() + offset * 60;
+    let (year, month, day) = civil_from_days(local.div_euclid(86400));
+    let seconds = local.rem_euclid(86400);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02} {}{:02}{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        if offset < 0 { '-' } else { '+' },
+        offset.abs() / 60,
+        offset.abs() % 60
+    )
+}
+
+/// 自 1970-01-01 起的天数到公历日期的换算，parse_ymd 的逆运算
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = if days >= 0 { days } else { days - 146096 } / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+/// 列出独有提交时的过滤条件
+#[derive(Debug, Default, Clone)]
+pub struct CommitFilter {
+    /// 作者名称或邮箱中包含的文本，不区分大小写
+    pub author: Option<String>,
+    /// 只保留不早于该时间的提交（Unix 时间戳，秒）
+    pub since: Option<i64>,
+    /// 只保留不晚于该时间的提交（Unix 时间戳，秒）
+    pub until: Option<i64>,
+    /// 最多返回的提交数，达到后停止遍历
+    pub limit: Option<usize>,
+}
+
+impl CommitFilter {
+    /// 提交是否满足所有过滤条件
+    fn matches(&self, commit: &git2::Commit) -> bool {
+        if let Some(pattern) = &self.author {
+            let pattern = pattern.to_lowercase();
+            let author = commit.author();
+            let name = author.name().unwrap_or_default().to_lowercase();
+            let email = author.email().unwrap_or_default().to_lowercase();
+            if !name.contains(&pattern) && !email.contains(&pattern) {
+                return false;
+            }
+        }
+
+        let time = commit.time().seconds();
+        if self.since.is_some_and(|since| time < since) {
+            return false;
+        }
+        if self.until.is_some_and(|until| time > until) {
+            return false;
+        }
+
+        true
+    }
+}
+
+/// 解析日期为 Unix 时间戳，支持 RFC3339（如 `2024-05-01T12:00:00+08:00`）和 `YYYY-MM-DD`（按 UTC 计算）
+///
+/// end_of_day 为 true 时 `YYYY-MM-DD` 解析为当天的最后一秒，用于包含结束日期当天的提交
+pub fn parse_date(input: &str, end_of_day: bool) -> Result<i64> {
+    let input = input.trim();
+    let invalid = || anyhow!("Invalid date '{}', expected RFC3339 or YYYY-MM-DD", input);
+
+    let (date, time) = match input.split_once(['T', 't', ' ']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (input, None),
+    };
+
+    let days = parse_ymd(date).ok_or_else(invalid)?;
+    let Some(time) = time else {
+        return Ok(days * 86400 + if end_of_day { 86399 } else { 0 });
+    };
+
+    // 时间部分为 HH:MM:SS[.小数秒]，后接 Z 或 ±HH:MM 时区
+    let (clock, offset) = match time.strip_suffix(['Z', 'z']) {
+        Some(clock) => (clock, 0),
+        None => {
+            let pos = time.rfind(['+', '-']).ok_or_else(invalid)?;
+            let (clock, zone) = time.split_at(pos);
+            let sign = if zone.starts_with('-') { -1 } else { 1 };
+            let (hours, minutes) = zone[1..].split_once(':').ok_or_else(invalid)?;
+            let hours: i64 = hours.parse().map_err(|_| invalid())?;
+            let minutes: i64 = minutes.parse().map_err(|_| invalid())?;
+            (clock, sign * (hours * 3600 + minutes * 60))
+        }
+    };
+
+    let clock = clock.split('.').next().unwrap_or(clock);
+    let fields: Vec<i64> = clock
+        .split(':')
+        .map(|field| field.parse().map_err(|_| invalid()))
+        .collect::<Result<_>>()?;
+    match fields[..] {
+        [hour, minute, second] if hour < 24 && minute < 60 && second <= 60 => {
+            Ok(days * 86400 + hour * 3600 + minute * 60 + second - offset)
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// 解析 `YYYY-MM-DD`，返回自 1970-01-01 起的天数
+fn parse_ymd(date: &str) -> Option<i64> {
+    let mut fields = date.splitn(3, '-').map(|field| field.parse::<i64>().ok());
+    let (year, month, day) = (fields.next()??, fields.next()??, fields.next()??);
+
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return None,
+    };
+    if !(1..=days_in_month).contains(&day) {
+        return None;
+    }
+
+    // 公历日期到天数的换算，参见 Howard Hinnant 的 days_from_civil
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    Some(era * 146097 + day_of_era - 719468)
+}
+
+/// 进行中的合并或拣选的冲突状态
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ConflictStatus {
+    /// 仍有冲突的路径
+    pub conflicted: Vec<String>,
+    /// 原本有冲突、现已解决并暂存的路径
+    pub resolved: Vec<String>,
+}
+
+/// 生成记录由AI解决的文件和所用模型的提交尾注
+pub fn ai_trailer(paths: &[String], models: &[String]) -> String {
+    let mut trailer = format!("{}: {}", AI_RESOLVED_TRAILER, paths.join(", "));
+    if !models.is_empty() {
+        trailer.push_str(&format!("\n{}: {}", AI_MODEL_TRAILER, models.join(", ")));
+    }
+    trailer
+}
+
+// 在提交说明的正文之后以空行分隔追加尾注，MERGE_MSG 中以 '#' 开头的冲突列表保留在最后
+fn append_trailer(message: &str, trailer: &str) -> String {
+    let lines: Vec<&str> = message.lines().collect();
+    let split = lines
+        .iter()
+        .position(|line| line.starts_with('#'))
+        .unwrap_or(lines.len());
+    let (body, comments) = lines.split_at(split);
+
+    let mut result = format!("{}\n\n{}\n", body.join("\n").trim_end(), trailer);
+    if !comments.is_empty() {
+        result.push('\n');
+        result.push_str(&comments.join("\n"));
+        result.push('\n');
+    }
+    result
+}
+
+// 索引中所有冲突条目的路径
+fn conflict_paths(index: &git2::Index) -> Result<Vec<String>> {
+    let mut paths = Vec::new();
+    for conflict in index.conflicts()? {
+        let conflict = conflict?;
+        if let Some(entry) = conflict.our.or(conflict.their).or(conflict.ancestor) {
+            paths.push(String::from_utf8_lossy(&entry.path).into_owned());
+        }
+    }
+    Ok(paths)
+}
+
+/// 合并的结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeOutcome {
+    /// 目标分支已包含源分支的所有提交，没有任何修改
+    UpToDate,
+    /// 目标分支快进到源分支，包含快进后的提交
+    FastForwarded(Oid),
+    /// 创建了合并提交，包含新提交的 ID
+    Merged(Oid),
+    /// 合并产生冲突，合并保持进行中，包含冲突文件的路径
+    Conflicted(Vec<String>),
+}
+
+/// 撤销提交的结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RevertOutcome {
+    /// 提交的修改已不在当前分支中（已被撤销或提交本身没有修改），没有创建提交
+    AlreadyReverted,
+    /// 创建了撤销提交，包含新提交的 ID
+    Reverted(Oid),
+    /// 撤销产生冲突，撤销保持进行中，包含冲突文件的路径
+    Conflicted(Vec<String>),
+}
+
+/// 合并选项
+#[derive(Debug, Default, Clone)]
+pub struct MergeOptions {
+    /// 即使可以快进也创建合并提交，等同于 `git merge --no-ff`
+    pub no_ff: bool,
+    /// 只允许快进合并，需要合并提交时返回错误，等同于 `git merge --ff-only`
+    pub ff_only: bool,
+    /// 合并提交的说明，未设置时使用 `default_merge_message` 生成的默认说明
+    pub message: Option<String>,
 }
 
 pub struct GitHandler {
     repo: Repository,
+    /// 覆盖 git 配置的提交身份 (name, email)
+    identity: Option<(String, String)>,
+    /// 写入工作目录和提供给AI的冲突标记风格
+    conflict_style: ConflictStyle,
+    /// 即使未配置 commit.gpgsign 也对创建的提交签名
+    sign_commits: bool,
 }
 
 impl GitHandler {
+    /// 从 path 开始向上查找并打开所在的仓库，与 git 命令一样可以在子目录中使用
     pub fn new(path: &str) -> Result<Self> {
-        let repo = Repository::open(path)?;
-        Ok(Self { repo })
+        Ok(Self::from_repository(Repository::discover(path)?))
+    }
+
+    /// 只打开 path 指定的仓库，不向上查找
+    pub fn open(path: &str) -> Result<Self> {
+        Ok(Self::from_repository(Repository::open(path)?))
+    }
+
+    fn from_repository(repo: Repository) -> Self {
+        Self {
+            repo,
+            identity: None,
+            conflict_style: ConflictStyle::default(),
+            sign_commits: false,
+        }
+    }
+
+    /// 仓库的工作目录，在链接的工作树中打开时为该工作树的目录
+    ///
+    /// 裸仓库没有工作目录，需要修改工作目录的操作返回错误
+    pub fn workdir(&self) -> Result<&std::path::Path> {
+        self.repo.workdir().ok_or_else(|| {
+            anyhow!("Operation requires a working directory, but the repository is bare")
+        })
+    }
+
+    /// 设置是否对创建的提交签名，仓库配置了 commit.gpgsign 时总是签名
+    pub fn set_sign_commits(&mut self, sign: bool) {
+        self.sign_commits = sign;
+    }
+
+    /// 设置冲突标记风格
+    pub fn set_conflict_style(&mut self, style: ConflictStyle) {
+        self.conflict_style = style;
+    }
+
+    /// 设置创建提交时使用的身份，覆盖仓库配置中的 user.name / user.email，
+    /// 但不修改仓库配置
+    pub fn set_identity(&mut self, name: &str, email: &str) {
+        self.identity = Some((name.replace('\0', ""), email.replace('\0', "")));
+    }
+
+    /// 查找分支，优先查找本地分支，找不到时回退到远程跟踪分支（如 origin/main）
+    pub fn resolve_branch(&self, name: &str) -> Result<git2::Branch<'_>> {
+        match self.repo.find_branch(name, BranchType::Local) {
+            Ok(branch) => Ok(branch),
+            Err(_) => Ok(self.repo.find_branch(name, BranchType::Remote)?),
+        }
+    }
+
+    /// 本地分支配置的上游分支（branch.<name>.remote 和 branch.<name>.merge），
+    /// 返回可直接用于合并的分支名，例如 `origin/main`
+    pub fn upstream_of(&self, branch: &str) -> Result<String> {
+        let local = self
+            .repo
+            .find_branch(branch, BranchType::Local)
+            .map_err(|_| anyhow!("Local branch '{}' does not exist", branch))?;
+        let upstream = local.upstream().map_err(|e| match e.code() {
+            git2::ErrorCode::NotFound => anyhow!("Branch '{}' has no upstream configured", branch),
+            _ => anyhow!(
+                "Failed to resolve upstream of '{}': {}",
+                branch,
+                e.message()
+            ),
+        })?;
+        let name = upstream
+            .name()?
+            .ok_or_else(|| anyhow!("Upstream of '{}' is not a valid UTF-8 name", branch))?;
+        Ok(name.to_string())
     }
 
     /// 检查分支是否存在
     pub fn branch_exists(&self, branch_name: &str) -> Result<bool> {
-        let branch = self.repo.find_branch(branch_name, BranchType::Local);
+        let branch = self.resolve_branch(branch_name);
         Ok(branch.is_ok())
     }
 
     /// 获取分支的最新提交
     pub fn get_branch_commit(&self, branch_name: &str) -> Result<Oid> {
-        let branch = self.repo.find_branch(branch_name, BranchType::Local)?;
+        let branch = self.resolve_branch(branch_name)?;
         let commit = branch.get().peel_to_commit()?;
         Ok(commit.id())
     }
 
+    /// 解析提交，优先按本地分支解析，也支持标签、提交哈希、`HEAD~3` 等任意修订，
+    /// 以及 `main@{1}`、`HEAD@{yesterday}` 等引用日志写法
+    pub fn resolve_revision(&self, rev: &str) -> Result<Oid> {
+        if let Ok(commit) = self.get_branch_commit(rev) {
+            return Ok(commit);
+        }
+
+        if rev.contains("@{") {
+            return self
+                .repo
+                .revparse_single(rev)
+                .and_then(|object| object.peel_to_commit())
+                .map(|commit| commit.id())
+                .map_err(|e| anyhow!("Reflog entry '{}' does not exist: {}", rev, e.message()));
+        }
+
+        Ok(self.find_commit_by_rev(rev)?.id())
+    }
+
     /// 切换到指定分支
     pub fn checkout_branch(&self, branch_name: &str) -> Result<()> {
         let branch = self.repo.find_branch(branch_name, BranchType::Local)?;
@@ -48,7 +462,8 @@ impl GitHandler {
         checkout_opts
             .force() // 强制检出
             .allow_conflicts(true) // 允许冲突
-            .conflict_style_merge(true) // 使用合并风格的冲突标记
+            .conflict_style_merge(self.conflict_style == ConflictStyle::Merge) // 使用合并风格的冲突标记
+            .conflict_style_diff3(self.conflict_style == ConflictStyle::Diff3) // 或 diff3 风格
             .remove_untracked(false) // 不移除未跟踪的文件
             .remove_ignored(false) // 不移除被忽略的文件
             .recreate_missing(true) // 重新创建丢失的文件
@@ -65,19 +480,66 @@ impl GitHandler {
         Ok(())
     }
 
-    /// 尝试合并分支，返回是否有冲突
-    pub fn merge_branches(&self, target: &str, source: &str) -> Result<bool> {
+    /// 列出已暂存或未暂存的修改所在的路径，不包括未跟踪的文件和子模块
+    pub fn uncommitted_changes(&self) -> Result<Vec<String>> {
+        self.workdir()?;
+
+        let mut opts = git2::StatusOptions::new();
+        opts.include_untracked(false)
+            .include_ignored(false)
+            .exclude_submodules(true);
+
+        let statuses = self.repo.statuses(Some(&mut opts))?;
+        Ok(statuses
+            .iter()
+            .filter(|entry| entry.status() != git2::Status::CURRENT)
+            .filter_map(|entry| entry.path().map(str::to_string))
+            .collect())
+    }
+
+    /// 列出存在未提交修改的子模块：子模块内有未提交的修改，子模块检出的提交与记录的不同，
+    /// 或者父仓库的索引中暂存了子模块的新提交
+    pub fn dirty_submodules(&self) -> Result<Vec<String>> {
+        let mut dirty = Vec::new();
+
+        for submodule in self.repo.submodules()? {
+            let name = match submodule.name() {
+                Some(name) => name.to_string(),
+                None => continue, // 跳过无效的 UTF-8 名称
+            };
+
+            let status = self
+                .repo
+                .submodule_status(&name, git2::SubmoduleIgnore::Untracked)?;
+            if status.intersects(
+                git2::SubmoduleStatus::WD_INDEX_MODIFIED
+                    | git2::SubmoduleStatus::WD_WD_MODIFIED
+                    | git2::SubmoduleStatus::WD_MODIFIED
+                    | git2::SubmoduleStatus::INDEX_MODIFIED,
+            ) {
+                dirty.push(name);
+            }
+        }
+
+        Ok(dirty)
+    }
+
+    /// 尝试合并分支，返回合并的结果
+    pub fn merge_branches(
+        &self,
+        target: &str,
+        source: &str,
+        options: &MergeOptions,
+    ) -> Result<MergeOutcome> {
         info!("Attempting to merge {} into {}", source, target);
+        self.workdir()?;
 
         // 确保字符串安全
         let safe_target = target.replace('\0', "");
         let safe_source = source.replace('\0', "");
 
-        // 确保我们在目标分支上
-        self.checkout_branch(&safe_target)?;
-
         // 获取源分支的提交
-        let source_branch = self.repo.find_branch(&safe_source, BranchType::Local)?;
+        let source_branch = self.resolve_branch(&safe_source)?;
         let source_commit = source_branch.get().peel_to_commit()?;
 
         // 使用 try-catch 方式处理 annotated commit
@@ -89,10 +551,58 @@ impl GitHandler {
             }
         };
 
+        // 在切换分支前针对目标分支分析合并，避免修改工作目录
+        let target_ref = self
+            .repo
+            .find_branch(&safe_target, BranchType::Local)?
+            .into_reference();
+        let (analysis, _) = self
+            .repo
+            .merge_analysis_for_ref(&target_ref, &[&annotated_commit])?;
+
+        if options.ff_only
+            && !analysis.intersects(
+                MergeAnalysis::ANALYSIS_UP_TO_DATE | MergeAnalysis::ANALYSIS_FASTFORWARD,
+            )
+        {
+            return Err(anyhow!("Not possible to fast-forward, aborting"));
+        }
+
+        // 需要合并提交时先在内存中合并，没有冲突则直接写入提交，无需强制检出目标分支
+        let fast_forward = analysis.contains(MergeAnalysis::ANALYSIS_FASTFORWARD) && !options.no_ff;
+        if !analysis.contains(MergeAnalysis::ANALYSIS_UP_TO_DATE) && !fast_forward {
+            let index = self.merge_in_memory(&safe_target, &safe_source)?;
+            if !index.has_conflicts() {
+                info!("Merge is clean, committing merged tree directly");
+                let commit_id = self.commit_clean_merge(
+                    &safe_target,
+                    &safe_source,
+                    index,
+                    options.message.as_deref(),
+                )?;
+                return Ok(MergeOutcome::Merged(commit_id));
+            }
+        }
+
+        // 确保我们在目标分支上
+        self.checkout_branch(&safe_target)?;
+
         // 分析合并结果
         let (analysis, _) = self.repo.merge_analysis(&[&annotated_commit])?;
 
+        // 可以快进时 libgit2 会同时设置 NORMAL 和 FASTFORWARD，因此需先检查快进
         match analysis {
+            analysis if analysis.contains(MergeAnalysis::ANALYSIS_UP_TO_DATE) => {
+                info!("Branches are already up-to-date");
+                Ok(MergeOutcome::UpToDate)
+            }
+            analysis
+                if analysis.contains(MergeAnalysis::ANALYSIS_FASTFORWARD) && !options.no_ff =>
+            {
+                info!("Fast-forward merge possible");
+                self.fast_forward_merge(source_commit.id())?;
+                Ok(MergeOutcome::FastForwarded(source_commit.id()))
+            }
             analysis if analysis.contains(MergeAnalysis::ANALYSIS_NORMAL) => {
                 // 配置合并选项，使用更保守的合并策略，确保冲突被正确检测
                 let mut merge_opts = git2::MergeOptions::new();
@@ -104,7 +614,8 @@ impl GitHandler {
                 let mut checkout_opts = git2::build::CheckoutBuilder::new();
                 checkout_opts
                     .allow_conflicts(true) // 允许存在冲突
-                    .conflict_style_merge(true) // 使用标准的合并冲突标记
+                    .conflict_style_merge(self.conflict_style == ConflictStyle::Merge) // 使用标准的合并冲突标记
+                    .conflict_style_diff3(self.conflict_style == ConflictStyle::Diff3) // 或 diff3 风格
                     .use_theirs(false) // 不默认使用他们的更改
                     .update_index(true); // 确保更新索引
 
@@ -120,29 +631,17 @@ impl GitHandler {
                 index.read(true)?; // 强制重新读取索引
 
                 // 检查索引中的冲突项
-                let has_conflicts = index.has_conflicts();
-
-                // 额外检查工作目录中是否有冲突标记
-                let workdir = self
-                    .repo
-                    .workdir()
-                    .ok_or_else(|| anyhow!("Repository has no working directory"))?;
-
-                // 强制将has_conflicts设为true用于测试
-                if let Ok(content) = std::fs::read_to_string(workdir.join("conflict.txt")) {
-                    // 确保内容中有冲突标记
-                    if content.contains("main content") && content.contains("feature content") {
-                        info!("Merge resulted in conflicts");
-                        return Ok(true);
-                    }
-                }
-
-                if has_conflicts {
+                if index.has_conflicts() {
                     info!("Merge resulted in conflicts");
-                    Ok(true)
+                    Ok(MergeOutcome::Conflicted(conflict_paths(&index)?))
                 } else {
                     info!("Merge completed successfully without conflicts");
-                    self.create_merge_commit(&safe_target, &safe_source)?;
+                    let commit_id = self.create_merge_commit(
+                        &safe_target,
+                        &safe_source,
+                        options.message.as_deref(),
+                        None,
+                    )?;
 
                     // 确保更新工作目录
                     let mut checkout_opts = git2::build::CheckoutBuilder::new();
@@ -153,351 +652,2688 @@ impl GitHandler {
                         .recreate_missing(true);
 
                     self.repo.checkout_head(Some(&mut checkout_opts))?;
-                    Ok(false)
+                    Ok(MergeOutcome::Merged(commit_id))
                 }
             }
-            analysis if analysis.contains(MergeAnalysis::ANALYSIS_UP_TO_DATE) => {
-                info!("Branches are already up-to-date");
-                Ok(false)
-            }
-            analysis if analysis.contains(MergeAnalysis::ANALYSIS_FASTFORWARD) => {
-                info!("Fast-forward merge possible");
-                self.fast_forward_merge(source_commit.id())?;
-                Ok(false)
-            }
             _ => Err(anyhow!("Unexpected merge analysis result")),
         }
     }
 
-    /// 获取所有冲突文件的信息
-    pub fn get_conflicts(&self) -> Result<Vec<ConflictFile>> {
-        let index = self.repo.index()?;
-        let mut conflicts = Vec::new();
+    /// 压缩合并，等同于 `git merge --squash`，返回是否有冲突
+    ///
+    /// 源分支的修改被合并到索引和工作目录中，但不记录第二个父提交、
+    /// 不保留 `MERGE_HEAD`，也不自动提交，由用户检查后自行提交。
+    /// 产生的冲突仍然保留在索引中，可以通过 `get_conflicts` 获取
+    pub fn squash_merge(&self, target: &str, source: &str) -> Result<bool> {
+        info!("Attempting to squash {} into {}", source, target);
+        self.workdir()?;
 
-        for conflict in index.conflicts()? {
-            let conflict = conflict?;
+        let safe_target = target.replace('\0', "");
+        let safe_source = source.replace('\0', "");
 
-            if let (Some(our), Some(their)) = (conflict.our, conflict.their) {
-                let path = match std::str::from_utf8(&our.path) {
-                    Ok(s) => s.replace('\0', ""),
-                    Err(_) => continue, // 跳过无效的 UTF-8 路径
-                };
+        let source_commit = self.resolve_branch(&safe_source)?.get().peel_to_commit()?;
+        let annotated_commit = self.repo.find_annotated_commit(source_commit.id())?;
 
-                // 安全地获取 blob 内容
-                let try_get_content = |blob_id: git2::Oid| -> Result<String> {
-                    let blob = self.repo.find_blob(blob_id)?;
-                    let content = blob.content();
+        self.checkout_branch(&safe_target)?;
 
-                    // 尝试检测并去除空字节
-                    let filtered: Vec<u8> = content.iter().filter(|&&b| b != 0).cloned().collect();
+        let (analysis, _) = self.repo.merge_analysis(&[&annotated_commit])?;
+        if analysis.contains(MergeAnalysis::ANALYSIS_UP_TO_DATE) {
+            info!("Branches are already up-to-date");
+            return Ok(false);
+        }
 
-                    String::from_utf8(filtered)
-                        .map_err(|e| anyhow!("Invalid UTF-8 sequence: {}", e))
-                };
+        let mut merge_opts = git2::MergeOptions::new();
+        merge_opts
+            .file_favor(git2::FileFavor::Normal)
+            .fail_on_conflict(false);
 
-                // 尝试获取文件内容
-                let our_content = match try_get_content(our.id) {
-                    Ok(content) => content,
-                    Err(_) => continue,
-                };
+        let mut checkout_opts = git2::build::CheckoutBuilder::new();
+        checkout_opts
+            .allow_conflicts(true)
+            .conflict_style_merge(self.conflict_style == ConflictStyle::Merge)
+            .conflict_style_diff3(self.conflict_style == ConflictStyle::Diff3)
+            .update_index(true);
+
+        self.repo.merge(
+            &[&annotated_commit],
+            Some(&mut merge_opts),
+            Some(&mut checkout_opts),
+        )?;
 
-                let their_content = match try_get_content(their.id) {
-                    Ok(content) => content,
-                    Err(_) => continue,
-                };
+        let mut index = self.repo.index()?;
+        index.read(true)?;
+        let has_conflicts = index.has_conflicts();
 
-                let base_content = if let Some(base) = conflict.ancestor {
-                    try_get_content(base.id).ok()
-                } else {
-                    None
-                };
+        // 与 git merge --squash 一样，不留下 MERGE_HEAD 等合并状态
+        self.repo.cleanup_state()?;
 
-                conflicts.push(ConflictFile {
-                    path,
-                    our_content,
-                    their_content,
-                    base_content,
-                });
-            }
+        if has_conflicts {
+            info!("Squash merge resulted in conflicts");
+        } else {
+            info!("Squash merge staged without conflicts");
         }
 
-        Ok(conflicts)
+        Ok(has_conflicts)
     }
 
-    /// 应用解决的冲突
-    pub fn apply_resolution(&self, path: &str, content: &str) -> Result<()> {
-        let mut index = self.repo.index()?;
+    /// 将 branch 分支变基到 onto 上，返回重新应用的提交数
+    ///
+    /// 逐个应用补丁，遇到冲突时将冲突交给 `resolve` 处理（通常由它调用
+    /// `apply_resolution`），处理后仍有冲突或出现其他错误时中止变基
+    pub async fn rebase_branch<F, Fut>(
+        &self,
+        branch: &str,
+        onto: &str,
+        mut resolve: F,
+    ) -> Result<usize>
+    where
+        F: FnMut(Vec<ConflictFile>) -> Fut,
+        Fut: std::future::Future<Output = Result<()>>,
+    {
+        info!("Rebasing {} onto {}", branch, onto);
+        self.workdir()?;
+
+        let branch_ref = self
+            .repo
+            .find_branch(branch, BranchType::Local)?
+            .into_reference();
+        let branch_commit = self.repo.reference_to_annotated_commit(&branch_ref)?;
+        let onto_commit = self
+            .repo
+            .find_annotated_commit(self.get_branch_commit(onto)?)?;
 
-        // 将解决后的内容写入工作目录
-        std::fs::write(self.repo.workdir().unwrap().join(path), content)?;
+        let mut rebase = self
+            .repo
+            .rebase(Some(&branch_commit), Some(&onto_commit), None, None)?;
+        let signature = self.committer_signature()?;
+        let mut applied = 0;
+
+        while let Some(operation) = rebase.next() {
+            let step = match operation {
+                Ok(operation) => operation.id(),
+                Err(e) => {
+                    rebase.abort()?;
+                    return Err(anyhow!("Failed to apply rebase step: {}", e));
+                }
+            };
 
-        // 将文件添加到索引
-        index.add_path(std::path::Path::new(path))?;
-        index.write()?;
+            if self.repo.index()?.has_conflicts() {
+                info!("Rebase step {} resulted in conflicts", step);
+                let conflicts = self.get_conflicts()?;
 
-        Ok(())
-    }
+                if let Err(e) = resolve(conflicts).await {
+                    rebase.abort()?;
+                    return Err(anyhow!("Failed to resolve conflicts in {}: {}", step, e));
+                }
 
-    /// 列出 target 分支中不存在于 source 分支的所有 commit
-    pub fn list_unique_commits(&self, target: &str, source: &str) -> Result<Vec<(Oid, String)>> {
-        info!(
-            "Listing commits in '{}' that don't exist in '{}'",
-            target, source
-        );
+                if self.repo.index()?.has_conflicts() {
+                    rebase.abort()?;
+                    return Err(anyhow!("Unresolved conflicts remain in {}", step));
+                }
+            }
 
-        // 获取源分支和目标分支的 commit ID
-        let target_commit = self.get_branch_commit(target)?;
-        let source_commit = self.get_branch_commit(source)?;
+            let committed = if self.should_sign() {
+                self.commit_signed_rebase_step(step, &signature)
+            } else {
+                match rebase.commit(None, &signature, None) {
+                    Ok(_) => Ok(true),
+                    Err(e) if e.code() == git2::ErrorCode::Applied => Ok(false),
+                    Err(e) => Err(e.into()),
+                }
+            };
+            match committed {
+                Ok(true) => applied += 1,
+                // 补丁的修改已存在于目标分支中，跳过该提交
+                Ok(false) => info!("Skipping already applied commit {}", step),
+                Err(e) => {
+                    rebase.abort()?;
+                    return Err(anyhow!("Failed to commit rebase step {}: {}", step, e));
+                }
+            }
+        }
 
-        // 创建一个 revwalk 用于遍历 commit
-        let mut revwalk = self.repo.revwalk()?;
+        rebase.finish(Some(&signature))?;
+        info!("Rebase completed, {} commit(s) applied", applied);
 
-        // 配置 revwalk 以获取目标分支的所有 commit
-        revwalk.push(target_commit)?;
+        Ok(applied)
+    }
 
-        // 隐藏源分支中的 commit
-        revwalk.hide(source_commit)?;
+    // Rebase::commit 不支持签名，需要签名时自行在 HEAD 上创建提交，返回 false 表示补丁已存在。
+    // 变基的后续步骤基于 HEAD 应用，finish 时也将分支移动到 HEAD，因此不需要经过 Rebase::commit
+    fn commit_signed_rebase_step(&self, step: Oid, committer: &git2::Signature) -> Result<bool> {
+        let original = self.repo.find_commit(step)?;
+        let head = self.repo.head()?.peel_to_commit()?;
+        let tree = self.repo.find_tree(self.repo.index()?.write_tree()?)?;
+        if tree.id() == head.tree_id() {
+            return Ok(false);
+        }
 
-        // 配置排序方式，从新到旧
-        revwalk.set_sorting(git2::Sort::TIME)?;
+        self.create_commit_on(
+            "HEAD",
+            &original.author(),
+            committer,
+            original.message().unwrap_or_default(),
+            &tree,
+            &[&head],
+        )?;
+        Ok(true)
+    }
 
-        // 收集结果
-        let mut results = Vec::new();
-        for oid in revwalk {
-            let oid = oid?;
-            let commit = self.repo.find_commit(oid)?;
+    /// 将指定提交拣选到当前分支，返回是否有冲突
+    ///
+    /// 没有冲突时直接创建提交，保留原作者，提交者为当前身份；
+    /// 有冲突时保留在索引中，解决后调用 `commit_cherry_pick` 完成提交
+    pub fn cherry_pick(&self, rev: &str) -> Result<bool> {
+        info!("Cherry-picking {}", rev);
+        self.workdir()?;
+
+        let commit = self.find_commit_by_rev(rev)?;
+        if commit.parent_count() > 1 {
+            return Err(anyhow!("Cannot cherry-pick merge commit '{}'", rev));
+        }
+
+        let mut checkout_opts = git2::build::CheckoutBuilder::new();
+        checkout_opts
+            .allow_conflicts(true)
+            .conflict_style_merge(self.conflict_style == ConflictStyle::Merge)
+            .conflict_style_diff3(self.conflict_style == ConflictStyle::Diff3)
+            .update_index(true);
+        let mut cherrypick_opts = git2::CherrypickOptions::new();
+        cherrypick_opts.checkout_builder(checkout_opts);
 
-            // 获取提交信息
-            let message = commit.message().unwrap_or("[无效的提交信息]").to_string();
+        self.repo.cherrypick(&commit, Some(&mut cherrypick_opts))?;
 
-            results.push((oid, message));
+        let mut index = self.repo.index()?;
+        index.read(true)?;
+        if index.has_conflicts() {
+            info!("Cherry-pick resulted in conflicts");
+            return Ok(true);
         }
 
-        Ok(results)
+        self.commit_cherry_pick(rev)?;
+        Ok(false)
     }
 
-    // 创建合并提交
-    fn create_merge_commit(&self, target: &str, source: &str) -> Result<Oid> {
+    /// 冲突解决后创建拣选提交，保留原提交的作者和提交信息
+    pub fn commit_cherry_pick(&self, rev: &str) -> Result<Oid> {
+        let commit = self.find_commit_by_rev(rev)?;
+
         let mut index = self.repo.index()?;
-        let oid = index.write_tree()?;
-        let tree = self.repo.find_tree(oid)?;
+        if index.has_conflicts() {
+            return Err(anyhow!(
+                "Cannot commit cherry-pick with unresolved conflicts"
+            ));
+        }
+        let tree = self.repo.find_tree(index.write_tree()?)?;
+
+        let head = self.repo.head()?.peel_to_commit()?;
+        let committer = self.committer_signature()?;
+        let commit_id = self.create_commit(
+            &commit.author(),
+            &committer,
+            commit.message().unwrap_or_default(),
+            &tree,
+            &[&head],
+        )?;
+        self.repo.cleanup_state()?;
 
-        let target_commit = self.get_branch_commit(target)?;
-        let source_commit = self.get_branch_commit(source)?;
+        Ok(commit_id)
+    }
 
-        let parent_commits = [
-            &self.repo.find_commit(target_commit)?,
-            &self.repo.find_commit(source_commit)?,
-        ];
+    /// 在当前分支上创建撤销指定提交的新提交，等同于 `git revert`
+    ///
+    /// 先在内存中撤销，结果与 HEAD 相同时不修改工作目录，直接返回 `AlreadyReverted`；
+    /// 有冲突时保留在索引中，解决后调用 `commit_revert` 完成提交
+    pub fn revert(&self, rev: &str) -> Result<RevertOutcome> {
+        info!("Reverting {}", rev);
+        self.workdir()?;
+
+        let commit = self.find_commit_by_rev(rev)?;
+        if commit.parent_count() > 1 {
+            return Err(anyhow!("Cannot revert merge commit '{}'", rev));
+        }
 
-        // 使用更安全的方式获取签名
-        let signature = {
-            let config = self.repo.config()?;
-            let name = config.get_string("user.name")?.replace('\0', "");
-            let email = config.get_string("user.email")?.replace('\0', "");
-            git2::Signature::now(&name, &email)?
-        };
+        let head = self.repo.head()?.peel_to_commit()?;
+        let mut index = self.repo.revert_commit(&commit, &head, 0, None)?;
+        if !index.has_conflicts() && index.write_tree_to(&self.repo)? == head.tree_id() {
+            info!("Commit {} is already reverted", rev);
+            return Ok(RevertOutcome::AlreadyReverted);
+        }
 
-        let safe_source = source.replace('\0', "");
-        let safe_target = target.replace('\0', "");
-        let message = format!("Merge branch '{}' into '{}'", safe_source, safe_target);
+        let mut checkout_opts = git2::build::CheckoutBuilder::new();
+        checkout_opts
+            .allow_conflicts(true)
+            .conflict_style_merge(self.conflict_style == ConflictStyle::Merge)
+            .conflict_style_diff3(self.conflict_style == ConflictStyle::Diff3)
+            .update_index(true);
+        let mut revert_opts = git2::RevertOptions::new();
+        revert_opts.checkout_builder(checkout_opts);
 
-        let commit_id = self.repo.commit(
-            Some("HEAD"),
-            &signature,
-            &signature,
+        self.repo.revert(&commit, Some(&mut revert_opts))?;
+
+        let mut index = self.repo.index()?;
+        index.read(true)?;
+        if index.has_conflicts() {
+            info!("Revert resulted in conflicts");
+            return Ok(RevertOutcome::Conflicted(conflict_paths(&index)?));
+        }
+
+        Ok(RevertOutcome::Reverted(self.commit_revert(rev)?))
+    }
+
+    /// 冲突解决后创建撤销提交，提交信息与 `git revert` 的默认信息相同
+    pub fn commit_revert(&self, rev: &str) -> Result<Oid> {
+        let commit = self.find_commit_by_rev(rev)?;
+
+        let mut index = self.repo.index()?;
+        if index.has_conflicts() {
+            return Err(anyhow!("Cannot commit revert with unresolved conflicts"));
+        }
+        let tree = self.repo.find_tree(index.write_tree()?)?;
+
+        let message = format!(
+            "Revert \"{}\"\n\nThis reverts commit {}.\n",
+            commit.summary().unwrap_or_default(),
+            commit.id()
+        );
+        let head = self.repo.head()?.peel_to_commit()?;
+        let commit_id = self.create_commit(
+            &self.author_signature()?,
+            &self.committer_signature()?,
             &message,
             &tree,
-            &parent_commits,
+            &[&head],
         )?;
+        self.repo.cleanup_state()?;
 
         Ok(commit_id)
     }
 
-    // 快速前进合并
-    fn fast_forward_merge(&self, target_commit: Oid) -> Result<()> {
-        let _commit = self.repo.find_commit(target_commit)?;
+    /// 在指定提交上创建标签，未指定时使用 HEAD，返回标签对象（轻量标签为提交）的 id
+    ///
+    /// 指定了 message 时创建附注标签，标签者与创建提交时的提交者相同；否则创建轻量标签。
+    /// 不会覆盖已存在的同名标签
+    pub fn create_tag(&self, name: &str, rev: Option<&str>, message: Option<&str>) -> Result<Oid> {
+        if !git2::Tag::is_valid_name(name) {
+            return Err(anyhow!("Invalid tag name '{}'", name));
+        }
+        let target = self.find_commit_by_rev(rev.unwrap_or("HEAD"))?;
+        info!("Creating tag {} at {}", name, target.id());
+
+        let result = match message {
+            Some(message) => self.repo.tag(
+                name,
+                target.as_object(),
+                &self.committer_signature()?,
+                message,
+                false,
+            ),
+            None => self.repo.tag_lightweight(name, target.as_object(), false),
+        };
+        result.map_err(|e| match e.code() {
+            git2::ErrorCode::Exists => anyhow!("Tag '{}' already exists", name),
+            _ => e.into(),
+        })
+    }
+
+    // 将修订解析为提交
+    fn find_commit_by_rev(&self, rev: &str) -> Result<git2::Commit<'_>> {
+        self.repo
+            .revparse_single(rev)
+            .and_then(|object| object.peel_to_commit())
+            .map_err(|e| anyhow!("'{}' is not a valid commit: {}", rev, e.message()))
+    }
+
+    /// 仓库当前的状态，例如正在合并或变基
+    pub fn repository_state(&self) -> git2::RepositoryState {
+        self.repo.state()
+    }
+
+    /// 列出仍有冲突的路径，以及原本冲突、已暂存为解决的路径
+    ///
+    /// 已解决的路径通过在内存中重新执行合并或拣选得到，其他状态下只列出仍有冲突的路径
+    pub fn conflict_status(&self) -> Result<ConflictStatus> {
+        let conflicted = conflict_paths(&self.repo.index()?)?;
+
+        let original = match self.repo.state() {
+            git2::RepositoryState::Merge => {
+                let head = self.repo.head()?.peel_to_commit()?;
+                let their = self.repo.find_reference("MERGE_HEAD")?.peel_to_commit()?;
+                conflict_paths(&self.repo.merge_commits(&head, &their, None)?)?
+            }
+            git2::RepositoryState::CherryPick => {
+                let head = self.repo.head()?.peel_to_commit()?;
+                let commit = self
+                    .repo
+                    .find_reference("CHERRY_PICK_HEAD")?
+                    .peel_to_commit()?;
+                conflict_paths(&self.repo.cherrypick_commit(&commit, &head, 0, None)?)?
+            }
+            git2::RepositoryState::Revert => {
+                let head = self.repo.head()?.peel_to_commit()?;
+                let commit = self.repo.find_reference("REVERT_HEAD")?.peel_to_commit()?;
+                conflict_paths(&self.repo.revert_commit(&commit, &head, 0, None)?)?
+            }
+            _ => Vec::new(),
+        };
+
+        let mut resolved: Vec<String> = original
+            .into_iter()
+            .filter(|path| !conflicted.contains(path))
+            .collect();
+        resolved.sort();
+        resolved.dedup();
+
+        Ok(ConflictStatus {
+            conflicted,
+            resolved,
+        })
+    }
+
+    /// 获取所有冲突文件的信息
+    pub fn get_conflicts(&self) -> Result<Vec<ConflictFile>> {
+        let index = self.repo.index()?;
+        self.collect_conflicts(&index)
+    }
+
+    /// 在内存中模拟合并，返回将会产生的冲突，不修改 HEAD、索引和工作目录
+    pub fn simulate_conflicts(&self, target: &str, source: &str) -> Result<Vec<ConflictFile>> {
+        let index = self.merge_in_memory(target, source)?;
+        self.collect_conflicts(&index)
+    }
+
+    /// 预览合并，只返回将会产生冲突的文件路径，不修改 HEAD、索引和工作目录，也不会留下 MERGE_HEAD
+    pub fn preview_merge(&self, target: &str, source: &str) -> Result<Vec<String>> {
+        info!("Previewing merge of '{}' into '{}'", source, target);
+        let index = self.merge_in_memory(target, source)?;
+        conflict_paths(&index)
+    }
+
+    // 在内存中合并两个分支，返回合并后的索引
+    fn merge_in_memory(&self, target: &str, source: &str) -> Result<git2::Index> {
+        let target_commit = self.repo.find_commit(self.get_branch_commit(target)?)?;
+        let source_commit = self.repo.find_commit(self.get_branch_commit(source)?)?;
+
+        Ok(self
+            .repo
+            .merge_commits(&target_commit, &source_commit, None)?)
+    }
+
+    /// 从索引中收集冲突文件的信息
+    fn collect_conflicts(&self, index: &git2::Index) -> Result<Vec<ConflictFile>> {
+        let mut conflicts = Vec::new();
+
+        // 安全地获取 blob 内容
+        let try_get_content = |blob_id: git2::Oid| -> Result<String> {
+            let blob = self.repo.find_blob(blob_id)?;
+            let content = blob.content();
+
+            // 尝试检测并去除空字节
+            let filtered: Vec<u8> = content.iter().filter(|&&b| b != 0).cloned().collect();
+
+            String::from_utf8(filtered).map_err(|e| anyhow!("Invalid UTF-8 sequence: {}", e))
+        };
+        let is_binary =
+            |blob_id: git2::Oid| -> Result<bool> { Ok(self.repo.find_blob(blob_id)?.is_binary()) };
+
+        for conflict in index.conflicts()? {
+            let conflict = conflict?;
+
+            let kind = match (&conflict.our, &conflict.their) {
+                (Some(_), Some(_)) => ConflictKind::Content,
+                (None, Some(_)) => ConflictKind::DeletedByUs,
+                (Some(_), None) => ConflictKind::DeletedByThem,
+                (None, None) => continue,
+            };
+            let Some(entry) = conflict.our.as_ref().or(conflict.their.as_ref()) else {
+                continue;
+            };
+            // 路径不是有效的 UTF-8 时仍然报告冲突，显示时替换无效字节
+            let raw_path = entry.path.clone();
+            let path = String::from_utf8_lossy(&raw_path).replace('\0', "");
+
+            // 一方删除了文件时AI无法决定是否保留，只记录剩下一方的内容，交由用户手动决定
+            if kind != ConflictKind::Content {
+                let mut file = ConflictFile {
+                    path,
+                    raw_path,
+                    our_content: String::new(),
+                    their_content: String::new(),
+                    base_content: None,
+                    marked_content: None,
+                    is_binary: is_binary(entry.id)?,
+                    kind,
+                };
+                if !file.is_binary {
+                    match try_get_content(entry.id) {
+                        Ok(content) => {
+                            match kind {
+                                ConflictKind::DeletedByUs => file.their_content = content,
+                                _ => file.our_content = content,
+                            }
+                            file.base_content = conflict
+                                .ancestor
+                                .as_ref()
+                                .and_then(|base| try_get_content(base.id).ok());
+                        }
+                        Err(e) => {
+                            warn!("Treating {} as binary: {}", file.path, e);
+                            file.is_binary = true;
+                        }
+                    }
+                }
+                conflicts.push(file);
+                continue;
+            }
+
+            let (Some(our), Some(their)) = (conflict.our, conflict.their) else {
+                continue;
+            };
+
+            // 二进制文件无法按文本合并，只记录路径，交由用户手动解决
+            let contents = if is_binary(our.id)? || is_binary(their.id)? {
+                None
+            } else {
+                // 不是有效 UTF-8 的文本同样无法交给AI，按二进制文件处理
+                match try_get_content(our.id).and_then(|our| Ok((our, try_get_content(their.id)?)))
+                {
+                    Ok(contents) => Some(contents),
+                    Err(e) => {
+                        warn!("Treating {} as binary: {}", path, e);
+                        None
+                    }
+                }
+            };
+            let Some((our_content, their_content)) = contents else {
+                conflicts.push(ConflictFile {
+                    path,
+                    raw_path,
+                    our_content: String::new(),
+                    their_content: String::new(),
+                    base_content: None,
+                    marked_content: None,
+                    is_binary: true,
+                    kind,
+                });
+                continue;
+            };
+
+            let base_content = conflict
+                .ancestor
+                .as_ref()
+                .and_then(|base| try_get_content(base.id).ok());
+
+            // 生成带冲突标记的内容，没有共同祖先时无法生成
+            let marked_content = conflict
+                .ancestor
+                .as_ref()
+                .and_then(|base| self.marked_content(base, &our, &their).ok());
+
+            conflicts.push(ConflictFile {
+                path,
+                raw_path,
+                our_content,
+                their_content,
+                base_content,
+                marked_content,
+                is_binary: false,
+                kind,
+            });
+        }
+
+        Ok(conflicts)
+    }
+
+    // 按配置的风格生成带冲突标记的文件内容
+    fn marked_content(
+        &self,
+        base: &git2::IndexEntry,
+        our: &git2::IndexEntry,
+        their: &git2::IndexEntry,
+    ) -> Result<String> {
+        let mut opts = git2::MergeFileOptions::new();
+        opts.ancestor_label("base")
+            .our_label("ours")
+            .their_label("theirs")
+            .style_diff3(self.conflict_style == ConflictStyle::Diff3);
+
+        let result = self
+            .repo
+            .merge_file_from_index(base, our, their, Some(&mut opts))?;
+        Ok(String::from_utf8(result.content().to_vec())?)
+    }
+
+    /// 所有冲突解决后创建合并提交，并清理 MERGE_HEAD 等合并状态
+    ///
+    /// message 为 None 时使用默认的合并说明，trailer 会以空行分隔追加在说明之后
+    pub fn commit_merge(
+        &self,
+        target: &str,
+        source: &str,
+        message: Option<&str>,
+        trailer: Option<&str>,
+    ) -> Result<Oid> {
+        if self.repo.index()?.has_conflicts() {
+            return Err(anyhow!("Cannot commit merge with unresolved conflicts"));
+        }
+
+        let commit_id = self.create_merge_commit(target, source, message, trailer)?;
+        self.repo.cleanup_state()?;
+
+        Ok(commit_id)
+    }
+
+    /// 将尾注追加到进行中的合并的 MERGE_MSG，之后用 `git commit` 或 `finalize_merge`
+    /// 完成合并时会保留在提交说明中
+    pub fn append_merge_trailer(&self, trailer: &str) -> Result<()> {
+        let path = self.repo.path().join("MERGE_MSG");
+        let message = std::fs::read_to_string(&path).unwrap_or_default();
+        std::fs::write(&path, append_trailer(&message, trailer))?;
+        Ok(())
+    }
+
+    // MERGE_MSG 中由 append_merge_trailer 追加的AI尾注
+    fn merge_msg_trailer(&self) -> Option<String> {
+        let message = self.repo.message().ok()?;
+        let lines: Vec<&str> = message
+            .lines()
+            .filter(|line| {
+                [AI_RESOLVED_TRAILER, AI_MODEL_TRAILER]
+                    .iter()
+                    .any(|key| line.starts_with(&format!("{}: ", key)))
+            })
+            .collect();
+        (!lines.is_empty()).then(|| lines.join("\n"))
+    }
+
+    /// 手动解决剩余冲突后完成进行中的合并
+    ///
+    /// 读取 MERGE_HEAD，确认没有未解决的冲突后，以 HEAD 和 MERGE_HEAD 为父提交创建合并提交，
+    /// 并清理合并状态。message 为 None 时使用默认的合并说明，MERGE_MSG 中的AI尾注会被保留
+    pub fn finalize_merge(&self, message: Option<&str>) -> Result<Oid> {
+        if self.repo.state() != git2::RepositoryState::Merge {
+            return Err(anyhow!("No merge in progress"));
+        }
+
+        let mut index = self.repo.index()?;
+        if index.has_conflicts() {
+            return Err(anyhow!(
+                "Cannot finalize merge with unresolved conflicts: {}",
+                conflict_paths(&index)?.join(", ")
+            ));
+        }
+
+        let head = self.repo.head()?;
+        let target = head.shorthand().unwrap_or("HEAD").to_string();
+        let our = head.peel_to_commit()?;
+        let their = self.repo.find_reference("MERGE_HEAD")?.peel_to_commit()?;
+
+        let message = match message {
+            Some(message) => message.replace('\0', ""),
+            None => {
+                // 优先使用指向 MERGE_HEAD 的分支名，本地分支优先于远程跟踪分支
+                let mut source = None;
+                for branch_type in [BranchType::Local, BranchType::Remote] {
+                    for branch in self.repo.branches(Some(branch_type))? {
+                        let (branch, _) = branch?;
+                        if branch.get().target() == Some(their.id()) {
+                            source = branch.name()?.map(str::to_string);
+                            break;
+                        }
+                    }
+                    if source.is_some() {
+                        break;
+                    }
+                }
+
+                match source {
+                    Some(source) => self.default_merge_message(&target, &source)?,
+                    None => format!(
+                        "Merge commit '{}' into '{}'",
+                        &their.id().to_string()[..7],
+                        target
+                    ),
+                }
+            }
+        };
+        let message = match self.merge_msg_trailer() {
+            Some(trailer) => append_trailer(&message, &trailer),
+            None => message,
+        };
+
+        let tree = self.repo.find_tree(index.write_tree()?)?;
+        let commit_id = self.create_commit(
+            &self.author_signature()?,
+            &self.committer_signature()?,
+            &message,
+            &tree,
+            &[&our, &their],
+        )?;
+        self.repo.cleanup_state()?;
+
+        Ok(commit_id)
+    }
+
+    /// 应用解决的冲突
+    pub fn apply_resolution(&self, path: &str, content: &str) -> Result<()> {
+        let mut index = self.repo.index()?;
+
+        // 暂存后冲突条目会被移除，先记录我们一方的文件模式
+        let mode = index
+            .get_path(std::path::Path::new(path), 2)
+            .map(|entry| entry.mode)
+            .filter(|&mode| mode == FILE_MODE_EXECUTABLE || mode == FILE_MODE_REGULAR);
+
+        // 将解决后的内容写入工作目录
+        let file_path = self.workdir()?.join(path);
+        std::fs::write(&file_path, content)?;
+        #[cfg(unix)]
+        if mode == Some(FILE_MODE_EXECUTABLE) {
+            use std::os::unix::fs::PermissionsExt;
+            let mut permissions = std::fs::metadata(&file_path)?.permissions();
+            permissions.set_mode(permissions.mode() | 0o111);
+            std::fs::set_permissions(&file_path, permissions)?;
+        }
+
+        // 将文件添加到索引
+        index.add_path(std::path::Path::new(path))?;
+
+        // core.filemode 为 false 时（例如 Windows）add_path 不会读取可执行位，直接沿用原来的模式
+        if let Some(mode) = mode {
+            if let Some(mut entry) = index.get_path(std::path::Path::new(path), 0) {
+                if entry.mode != mode {
+                    entry.mode = mode;
+                    index.add(&entry)?;
+                }
+            }
+        }
+        index.write()?;
+
+        Ok(())
+    }
+
+    /// 列出 target 分支中不存在于 source 分支的所有 commit
+    pub fn list_unique_commits(
+        &self,
+        target: &str,
+        source: &str,
+        filter: &CommitFilter,
+    ) -> Result<Vec<CommitInfo>> {
+        info!(
+            "Listing commits in '{}' that don't exist in '{}'",
+            target, source
+        );
+
+        // 获取源分支和目标分支的 commit ID
+        let target_commit = self.resolve_revision(target)?;
+        let source_commit = self.resolve_revision(source)?;
+
+        // 创建一个 revwalk 用于遍历 commit
+        let mut revwalk = self.repo.revwalk()?;
+
+        // 配置 revwalk 以获取目标分支的所有 commit
+        revwalk.push(target_commit)?;
+
+        // 隐藏源分支中的 commit
+        revwalk.hide(source_commit)?;
+
+        // 配置排序方式，从新到旧
+        revwalk.set_sorting(git2::Sort::TIME)?;
+
+        // 收集结果
+        let mut results = Vec::new();
+        for oid in revwalk {
+            if filter.limit.is_some_and(|limit| results.len() >= limit) {
+                break;
+            }
+
+            let oid = oid?;
+            let commit = self.repo.find_commit(oid)?;
+            if !filter.matches(&commit) {
+                continue;
+            }
+
+            results.push(CommitInfo::from_commit(&commit));
+        }
+
+        Ok(results)
+    }
+
+    /// 从分支顶端开始列出提交，从新到旧，limit 为最多返回的提交数
+    pub fn log_branch(&self, branch: &str, limit: Option<usize>) -> Result<Vec<CommitInfo>> {
+        info!("Listing commits on '{}'", branch);
+
+        let mut revwalk = self.repo.revwalk()?;
+        revwalk.push(self.resolve_revision(branch)?)?;
+        // 时间相同时保证子提交排在父提交之前
+        revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::TIME)?;
+
+        let mut results = Vec::new();
+        for oid in revwalk {
+            if limit.is_some_and(|limit| results.len() >= limit) {
+                break;
+            }
+            let commit = self.repo.find_commit(oid?)?;
+            results.push(CommitInfo::from_commit(&commit));
+        }
+
+        Ok(results)
+    }
+
+    /// 建立提交到引用名称的映射，标签以 "tag: " 开头，与 git log --decorate 一致
+    pub fn refs_by_commit(&self) -> Result<HashMap<Oid, Vec<String>>> {
+        let mut refs: HashMap<Oid, Vec<String>> = HashMap::new();
+
+        for reference in self.repo.references()? {
+            let reference = reference?;
+            let name = match reference.shorthand() {
+                Some(name) => name.to_string(),
+                None => continue, // 跳过无效的 UTF-8 名称
+            };
+            // 跳过不指向提交的引用（如指向树的标签）
+            let commit = match reference.peel_to_commit() {
+                Ok(commit) => commit,
+                Err(_) => continue,
+            };
+
+            let name = if reference.is_tag() {
+                format!("tag: {}", name)
+            } else {
+                name
+            };
+            refs.entry(commit.id()).or_default().push(name);
+        }
+
+        for names in refs.values_mut() {
+            names.sort();
+        }
+
+        Ok(refs)
+    }
+
+    /// 生成与 `git merge` 一致的默认合并说明
+    pub fn default_merge_message(&self, target: &str, source: &str) -> Result<String> {
+        let safe_target = target.replace('\0', "");
+
+        // 使用不带 refs/ 前缀的分支名，远程跟踪分支与 git 一样单独标注
+        let source_branch = self.resolve_branch(source)?;
+        let source_name = source_branch.name()?.unwrap_or(source).replace('\0', "");
+        if source_branch.get().is_remote() {
+            Ok(format!(
+                "Merge remote-tracking branch '{}' into '{}'",
+                source_name, safe_target
+            ))
+        } else {
+            Ok(format!(
+                "Merge branch '{}' into '{}'",
+                source_name, safe_target
+            ))
+        }
+    }
+
+    // 创建合并提交，trailer 追加在说明之后
+    fn create_merge_commit(
+        &self,
+        target: &str,
+        source: &str,
+        message: Option<&str>,
+        trailer: Option<&str>,
+    ) -> Result<Oid> {
+        let mut index = self.repo.index()?;
+        let oid = index.write_tree()?;
+        let tree = self.repo.find_tree(oid)?;
+
+        let message = match trailer {
+            Some(trailer) => {
+                let message = match message {
+                    Some(message) => message.to_string(),
+                    None => self.default_merge_message(target, source)?,
+                };
+                Some(append_trailer(&message, trailer))
+            }
+            None => message.map(str::to_string),
+        };
+        self.write_merge_commit("HEAD", &tree, target, source, message.as_deref())
+    }
+
+    // 以 target 和 source 为父提交写入合并提交，并更新指定的引用
+    fn write_merge_commit(
+        &self,
+        update_ref: &str,
+        tree: &git2::Tree,
+        target: &str,
+        source: &str,
+        message: Option<&str>,
+    ) -> Result<Oid> {
+        let target_commit = self.get_branch_commit(target)?;
+        let source_commit = self.get_branch_commit(source)?;
+
+        let parent_commits = [
+            &self.repo.find_commit(target_commit)?,
+            &self.repo.find_commit(source_commit)?,
+        ];
+
+        let author = self.author_signature()?;
+        let committer = self.committer_signature()?;
+
+        let message = match message {
+            Some(message) => message.replace('\0', ""),
+            None => self.default_merge_message(target, source)?,
+        };
+
+        self.create_commit_on(
+            update_ref,
+            &author,
+            &committer,
+            &message,
+            tree,
+            &parent_commits,
+        )
+    }
+
+    // 将内存中无冲突的合并结果写为合并提交，然后以安全模式检出目标分支
+    //
+    // 检出在移动分支之前进行：工作目录中与合并结果冲突的本地修改会使检出失败而不是被覆盖，
+    // 此时分支和 HEAD 都保持不变
+    fn commit_clean_merge(
+        &self,
+        target: &str,
+        source: &str,
+        mut index: git2::Index,
+        message: Option<&str>,
+    ) -> Result<Oid> {
+        let tree = self.repo.find_tree(index.write_tree_to(&self.repo)?)?;
+
+        let target_ref = self
+            .repo
+            .find_branch(target, BranchType::Local)?
+            .into_reference();
+        let ref_name = target_ref
+            .name()
+            .ok_or_else(|| anyhow!("Invalid branch reference"))?
+            .to_string();
+
+        let mut checkout_opts = git2::build::CheckoutBuilder::new();
+        checkout_opts.safe().recreate_missing(true);
+        self.repo
+            .checkout_tree(tree.as_object(), Some(&mut checkout_opts))
+            .map_err(|e| {
+                anyhow!(
+                    "Local changes would be overwritten by merge: {}",
+                    e.message()
+                )
+            })?;
+
+        let commit_id = self.write_merge_commit(&ref_name, &tree, target, source, message)?;
+        self.repo.set_head(&ref_name)?;
+
+        Ok(commit_id)
+    }
+
+    // 创建提交并更新 HEAD，需要签名时调用用户配置的签名程序，签名失败时回退到不签名
+    fn create_commit(
+        &self,
+        author: &git2::Signature,
+        committer: &git2::Signature,
+        message: &str,
+        tree: &git2::Tree,
+        parents: &[&git2::Commit],
+    ) -> Result<Oid> {
+        self.create_commit_on("HEAD", author, committer, message, tree, parents)
+    }
+
+    // 是否对创建的提交签名
+    fn should_sign(&self) -> bool {
+        self.sign_commits
+            || self
+                .repo
+                .config()
+                .and_then(|config| config.get_bool("commit.gpgsign"))
+                .unwrap_or(false)
+    }
+
+    // 创建提交并更新指定的引用
+    fn create_commit_on(
+        &self,
+        update_ref: &str,
+        author: &git2::Signature,
+        committer: &git2::Signature,
+        message: &str,
+        tree: &git2::Tree,
+        parents: &[&git2::Commit],
+    ) -> Result<Oid> {
+        if !self.should_sign() {
+            return Ok(self.repo.commit(
+                Some(update_ref),
+                author,
+                committer,
+                message,
+                tree,
+                parents,
+            )?);
+        }
+
+        let buffer = self
+            .repo
+            .commit_create_buffer(author, committer, message, tree, parents)?;
+        let content = buffer
+            .as_str()
+            .ok_or_else(|| anyhow!("Commit buffer is not valid UTF-8"))?;
+
+        match self.sign_buffer(content) {
+            Ok(signature) => {
+                let commit_id = self.repo.commit_signed(content, &signature, None)?;
+
+                // commit_signed 不会更新引用，需要手动移动引用指向的分支
+                let summary = message.lines().next().unwrap_or_default();
+                self.repo
+                    .find_reference(update_ref)?
+                    .resolve()?
+                    .set_target(commit_id, &format!("commit: {}", summary))?;
+                Ok(commit_id)
+            }
+            Err(e) => {
+                warn!("Failed to sign commit, creating an unsigned commit: {}", e);
+                Ok(self
+                    .repo
+                    .commit(Some(update_ref), author, committer, message, tree, parents)?)
+            }
+        }
+    }
+
+    // 按 gpg.format 调用 GPG 或 SSH 签名程序，返回 ASCII 格式的签名
+    fn sign_buffer(&self, content: &str) -> Result<String> {
+        let config = self.repo.config()?;
+        let format = config
+            .get_string("gpg.format")
+            .unwrap_or_else(|_| "openpgp".to_string());
+        let signing_key = config.get_string("user.signingkey").ok();
+
+        if format == "ssh" {
+            let program = config
+                .get_string("gpg.ssh.program")
+                .unwrap_or_else(|_| "ssh-keygen".to_string());
+            let key = signing_key
+                .ok_or_else(|| anyhow!("user.signingkey is required for SSH signing"))?;
+
+            // ssh-keygen 从文件读取内容，并将签名写入同名的 .sig 文件
+            let dir = tempfile::tempdir()?;
+            let path = dir.path().join("commit");
+            std::fs::write(&path, content)?;
+
+            let output = Command::new(&program)
+                .args(["-Y", "sign", "-n", "git", "-f", &key])
+                .arg(&path)
+                .output()
+                .map_err(|e| anyhow!("Failed to run {}: {}", program, e))?;
+            if !output.status.success() {
+                return Err(anyhow!(
+                    "{} failed: {}",
+                    program,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
+            }
+
+            return Ok(std::fs::read_to_string(path.with_extension("sig"))?);
+        }
+
+        let (format_program, default_program) = if format == "x509" {
+            ("gpg.x509.program", "gpgsm")
+        } else {
+            ("gpg.openpgp.program", "gpg")
+        };
+        let program = config
+            .get_string(format_program)
+            .or_else(|_| config.get_string("gpg.program"))
+            .unwrap_or_else(|_| default_program.to_string());
+
+        let mut command = Command::new(&program);
+        command.args(["--status-fd=2", "-bsa"]);
+        if let Some(key) = &signing_key {
+            command.args(["-u", key]);
+        }
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| anyhow!("Failed to run {}: {}", program, e))?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(content.as_bytes())?;
+        }
+        let output = child.wait_with_output()?;
+        let signature = String::from_utf8(output.stdout)?;
+        if !output.status.success() || signature.trim().is_empty() {
+            return Err(anyhow!(
+                "{} failed: {}",
+                program,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        Ok(signature)
+    }
+
+    // 获取作者签名
+    fn author_signature(&self) -> Result<git2::Signature<'static>> {
+        self.signature_with("AUTHOR", |key| std::env::var(key).ok())
+    }
+
+    // 获取提交者签名
+    fn committer_signature(&self) -> Result<git2::Signature<'static>> {
+        self.signature_with("COMMITTER", |key| std::env::var(key).ok())
+    }
+
+    // 按以下顺序确定签名：set_identity 设置的身份、GIT_{role}_NAME/EMAIL 环境变量、
+    // Repository::signature()，最后直接读取 user.name/user.email
+    fn signature_with(
+        &self,
+        role: &str,
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<git2::Signature<'static>> {
+        if let Some((name, email)) = &self.identity {
+            return Ok(git2::Signature::now(name, email)?);
+        }
+
+        let env_value = |key: &str| {
+            lookup(&format!("GIT_{}_{}", role, key))
+                .map(|value| value.replace('\0', ""))
+                .filter(|value| !value.is_empty())
+        };
+        let env_name = env_value("NAME");
+        let env_email = env_value("EMAIL");
+        if let (Some(name), Some(email)) = (&env_name, &env_email) {
+            return Ok(git2::Signature::now(name, email)?);
+        }
+
+        let default = match self.repo.signature() {
+            Ok(signature) => signature,
+            Err(e) => {
+                debug!("Repository::signature failed, reading config: {}", e);
+                let config = self.repo.config()?;
+                let name = config.get_string("user.name")?.replace('\0', "");
+                let email = config.get_string("user.email")?.replace('\0', "");
+                git2::Signature::now(&name, &email)?
+            }
+        };
+        if env_name.is_none() && env_email.is_none() {
+            return Ok(default);
+        }
+
+        let name = env_name.unwrap_or_else(|| default.name().unwrap_or_default().to_string());
+        let email = env_email.unwrap_or_else(|| default.email().unwrap_or_default().to_string());
+        Ok(git2::Signature::now(&name, &email)?)
+    }
+
+    // 快速前进合并
+    fn fast_forward_merge(&self, target_commit: Oid) -> Result<()> {
+        let _commit = self.repo.find_commit(target_commit)?;
         let mut ref_head = self.repo.head()?;
 
-        let ref_name = ref_head
-            .name()
-            .ok_or_else(|| anyhow!("Invalid reference name"))?
-            .to_string();
+        let ref_name = ref_head
+            .name()
+            .ok_or_else(|| anyhow!("Invalid reference name"))?
+            .to_string();
+
+        ref_head.set_target(target_commit, "Fast-forward merge")?;
+        self.repo.set_head(&ref_name)?;
+
+        // 改进 checkout 选项配置
+        let mut checkout_opts = git2::build::CheckoutBuilder::new();
+        checkout_opts
+            .force() // 强制检出
+            .allow_conflicts(true) // 允许冲突
+            .remove_untracked(false) // 不移除未跟踪的文件
+            .remove_ignored(false) // 不移除被忽略的文件
+            .update_index(true) // 更新索引
+            .recreate_missing(true); // 重新创建丢失的文件
+
+        self.repo.checkout_head(Some(&mut checkout_opts))?;
+
+        Ok(())
+    }
+
+    /// 将索引和工作目录重置为 HEAD，丢弃冲突和已暂存的修改
+    fn reset_to_head(&self) -> Result<()> {
+        self.workdir()?;
+        let head = self.repo.head()?.peel_to_commit()?;
+        info!("Resetting index and working tree to {}", head.id());
+        self.repo
+            .reset(head.as_object(), git2::ResetType::Hard, None)?;
+        Ok(())
+    }
+
+    /// 终止合并操作
+    ///
+    /// 与 `git merge --abort` 一样将索引和工作目录重置为合并前的 HEAD，再删除 MERGE_HEAD
+    /// 等状态文件。没有进行中的操作且索引中没有冲突时不会重置，避免丢弃无关的修改
+    pub fn abort_merge(&self) -> Result<()> {
+        if self.repo.state() != git2::RepositoryState::Clean || self.repo.index()?.has_conflicts() {
+            self.reset_to_head()?;
+        }
+        self.repo.cleanup_state()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    fn setup_test_repo() -> Result<(TempDir, GitHandler)> {
+        let temp_dir = TempDir::new()?;
+        let repo = Repository::init(temp_dir.path())?;
+
+        // 设置测试用的 Git 配置，确保使用安全的字符串
+        let mut config = repo.config()?;
+        let safe_name = "Test User".replace('\0', "");
+        let safe_email = "test@example.com".replace('\0', "");
+        config.set_str("user.name", &safe_name)?;
+        config.set_str("user.email", &safe_email)?;
+        config.set_str("init.defaultBranch", "main")?;
+
+        // 创建初始提交
+        let signature = git2::Signature::now(&safe_name, &safe_email)?;
+        {
+            // 创建一个测试文件，确保内容不包含空字节
+            let safe_content = "initial content".replace('\0', "");
+            fs::write(temp_dir.path().join("initial.txt"), safe_content)?;
+
+            let mut index = repo.index()?;
+            index.add_path(Path::new("initial.txt"))?;
+            let id = index.write_tree()?;
+            let tree = repo.find_tree(id)?;
+
+            // 创建初始提交并设置 main 分支
+            let commit_id = repo.commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                "Initial commit",
+                &tree,
+                &[],
+            )?;
+
+            repo.branch("main", &repo.find_commit(commit_id)?, false)?;
+        }
+
+        Ok((
+            temp_dir,
+            GitHandler {
+                repo,
+                identity: None,
+                conflict_style: ConflictStyle::default(),
+                sign_commits: false,
+            },
+        ))
+    }
+
+    fn create_file_and_commit(
+        repo: &Repository,
+        path: &str,
+        content: &str,
+        message: &str,
+    ) -> Result<Oid> {
+        let workdir = repo
+            .workdir()
+            .ok_or_else(|| anyhow!("No working directory"))?;
+        let file_path = workdir.join(path);
+
+        // 写入文件内容前移除空字节
+        let safe_content = content.replace('\0', "");
+        fs::write(&file_path, safe_content)?;
+
+        // 使用安全的签名信息
+        let config = repo.config()?;
+        let name = config.get_string("user.name")?.replace('\0', "");
+        let email = config.get_string("user.email")?.replace('\0', "");
+        let signature = git2::Signature::now(&name, &email)?;
+
+        let mut index = repo.index()?;
+        index.add_path(Path::new(path))?;
+        let id = index.write_tree()?;
+        let tree = repo.find_tree(id)?;
+
+        let parent_commit = repo.head()?.peel_to_commit()?;
+        let commit_id = repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &[&parent_commit],
+        )?;
+
+        Ok(commit_id)
+    }
+
+    #[test]
+    fn test_branch_operations() -> Result<()> {
+        let (_temp_dir, handler) = setup_test_repo()?;
+
+        // 测试分支不存在
+        assert!(!handler.branch_exists("test-branch")?);
+
+        // 创建新分支
+        let _branch_ref = handler.repo.branch(
+            "test-branch",
+            &handler.repo.head()?.peel_to_commit()?,
+            false,
+        )?;
+
+        // 测试分支存在
+        assert!(handler.branch_exists("test-branch")?);
+
+        // 测试切换分支
+        handler.checkout_branch("test-branch")?;
+        assert_eq!(handler.repo.head()?.shorthand().unwrap(), "test-branch");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_branch_commit() -> Result<()> {
+        let (_temp_dir, handler) = setup_test_repo()?;
+
+        // 创建一个新分支
+        let original_head = handler.repo.head()?.peel_to_commit()?.id();
+        let _branch_ref = handler.repo.branch(
+            "test-branch",
+            &handler.repo.head()?.peel_to_commit()?,
+            false,
+        )?;
+
+        // 获取新分支的提交 ID
+        let branch_commit = handler.get_branch_commit("test-branch")?;
+
+        // 验证获取的提交 ID 与原始提交 ID 一致
+        assert_eq!(branch_commit, original_head);
+
+        // 测试对不存在的分支应该失败
+        let result = handler.get_branch_commit("non-existent-branch");
+        assert!(result.is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fast_forward_merge() -> Result<()> {
+        let (_temp_dir, handler) = setup_test_repo()?;
+
+        println!("Setting up feature branch...");
+        let _branch =
+            handler
+                .repo
+                .branch("feature", &handler.repo.head()?.peel_to_commit()?, false)?;
+
+        println!("Checking out feature branch...");
+        handler.checkout_branch("feature")?;
+
+        println!("Creating file in feature branch...");
+        create_file_and_commit(
+            &handler.repo,
+            "feature.txt",
+            "feature content",
+            "Add feature",
+        )?;
+
+        println!("Switching back to main branch...");
+        handler.checkout_branch("main")?;
+
+        println!("Attempting merge...");
+        let outcome = handler.merge_branches("main", "feature", &MergeOptions::default())?;
+
+        println!("Checking results...");
+        println!("Outcome: {:?}", outcome);
+
+        let workdir = handler
+            .repo
+            .workdir()
+            .ok_or_else(|| anyhow!("No working directory"))?;
+        let file_path = workdir.join("feature.txt");
+        println!("Checking file at: {}", file_path.display());
+        println!("File exists: {}", file_path.exists());
+
+        if !file_path.exists() {
+            println!("Current directory contents:");
+            for entry in std::fs::read_dir(workdir)? {
+                let entry = entry?;
+                println!("- {}", entry.path().display());
+            }
+        }
+
+        assert_eq!(
+            outcome,
+            MergeOutcome::FastForwarded(handler.get_branch_commit("feature")?)
+        );
+        assert!(file_path.exists());
+
+        // 快进合并不应创建合并提交
+        let head = handler.repo.head()?.peel_to_commit()?;
+        assert_eq!(head.parent_count(), 1);
+        assert_eq!(head.message().unwrap(), "Add feature");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_with_conflicts() -> Result<()> {
+        let (_temp_dir, handler) = setup_test_repo()?;
+
+        // 两个分支从同一个基础版本出发，修改同一个文件
+        handler.checkout_branch("main")?;
+        create_file_and_commit(&handler.repo, "conflict.txt", "base content\n", "Add file")?;
+        handler
+            .repo
+            .branch("feature", &handler.repo.head()?.peel_to_commit()?, false)?;
+        create_file_and_commit(
+            &handler.repo,
+            "conflict.txt",
+            "main content\n",
+            "Main change",
+        )?;
+
+        handler.checkout_branch("feature")?;
+        create_file_and_commit(
+            &handler.repo,
+            "conflict.txt",
+            "feature content\n",
+            "Feature change",
+        )?;
+        handler.checkout_branch("main")?;
+
+        let outcome = handler.merge_branches("main", "feature", &MergeOptions::default())?;
+        assert_eq!(
+            outcome,
+            MergeOutcome::Conflicted(vec!["conflict.txt".to_string()])
+        );
+        assert_eq!(handler.repo.state(), git2::RepositoryState::Merge);
+
+        let conflicts = handler.get_conflicts()?;
+        assert_eq!(conflicts.len(), 1);
+        let conflict = &conflicts[0];
+        assert_eq!(conflict.path, "conflict.txt");
+        assert_eq!(conflict.our_content, "main content\n");
+        assert_eq!(conflict.their_content, "feature content\n");
+        assert_eq!(conflict.base_content.as_deref(), Some("base content\n"));
+        assert!(!conflict.is_binary);
+        assert_eq!(conflict.kind, ConflictKind::Content);
+
+        let marked = conflict.marked_content.as_deref().unwrap();
+        assert!(marked.contains("<<<<<<< ours"));
+        assert!(marked.contains(">>>>>>> theirs"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_conflict_status() -> Result<()> {
+        let (_temp_dir, handler) = setup_test_repo()?;
+        handler.checkout_branch("main")?;
+        assert_eq!(handler.repository_state(), git2::RepositoryState::Clean);
+        assert_eq!(handler.conflict_status()?, ConflictStatus::default());
+
+        handler
+            .repo
+            .branch("feature", &handler.repo.head()?.peel_to_commit()?, false)?;
+        create_file_and_commit(&handler.repo, "a.txt", "main a", "Main a")?;
+        create_file_and_commit(&handler.repo, "b.txt", "main b", "Main b")?;
+        handler.checkout_branch("feature")?;
+        create_file_and_commit(&handler.repo, "a.txt", "feature a", "Feature a")?;
+        create_file_and_commit(&handler.repo, "b.txt", "feature b", "Feature b")?;
+        handler.checkout_branch("main")?;
+
+        assert!(matches!(
+            handler.merge_branches("main", "feature", &MergeOptions::default())?,
+            MergeOutcome::Conflicted(_)
+        ));
+        assert_eq!(handler.repository_state(), git2::RepositoryState::Merge);
+
+        let mut status = handler.conflict_status()?;
+        status.conflicted.sort();
+        assert_eq!(status.conflicted, vec!["a.txt", "b.txt"]);
+        assert!(status.resolved.is_empty());
+
+        // 解决其中一个后，它出现在已解决列表中
+        handler.apply_resolution("a.txt", "resolved a")?;
+        let status = handler.conflict_status()?;
+        assert_eq!(status.conflicted, vec!["b.txt"]);
+        assert_eq!(status.resolved, vec!["a.txt"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_conflicts_detailed() -> Result<()> {
+        let (_temp_dir, handler) = setup_test_repo()?;
+
+        // 创建一个测试目录以存放冲突文件
+        let workdir = handler
+            .repo
+            .workdir()
+            .ok_or_else(|| anyhow!("No working directory"))?;
+
+        // 创建两个分支并制造冲突
+        let _branch = handler.repo.branch(
+            "conflict-branch",
+            &handler.repo.head()?.peel_to_commit()?,
+            false,
+        )?;
+
+        // 在主分支创建测试文件
+        create_file_and_commit(
+            &handler.repo,
+            "test_conflict.txt",
+            "main content",
+            "Add file in main",
+        )?;
+
+        // 切换到冲突分支
+        handler.checkout_branch("conflict-branch")?;
+
+        // 在冲突分支创建同名文件但内容不同
+        create_file_and_commit(
+            &handler.repo,
+            "test_conflict.txt",
+            "branch content",
+            "Add file in branch",
+        )?;
+
+        // 切回主分支
+        handler.checkout_branch("main")?;
+
+        // 获取冲突 - 实际上这里不会真正获取到冲突，因为我们需要先尝试合并
+        // 但我们可以手动在索引中创建冲突标记
+        let _index_path = workdir.join(".git/index");
+
+        // 创建一个模拟的冲突文件（真实情况下这应该由Git自动生成）
+        let test_conflict_path = workdir.join("test_conflict.txt");
+        std::fs::write(
+            &test_conflict_path,
+            "<<<<<<< HEAD\nmain content\n=======\nbranch content\n>>>>>>> conflict-branch",
+        )?;
+
+        // 尝试获取冲突
+        let conflicts = handler.get_conflicts()?;
+
+        // 虽然在这个测试环境中可能获取不到实际冲突，但函数不应抛出错误
+        println!("Found {} conflict(s)", conflicts.len());
+
+        // 测试通过，只要函数执行不出错
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_resolution() -> Result<()> {
+        let (_temp_dir, handler) = setup_test_repo()?;
+
+        // 创建一个测试文件
+        let workdir = handler
+            .repo
+            .workdir()
+            .ok_or_else(|| anyhow!("No working directory"))?;
+        let conflict_path = "conflict_to_resolve.txt";
+        let file_path = workdir.join(conflict_path);
+
+        // 写入冲突内容
+        std::fs::write(
+            &file_path,
+            "<<<<<<< HEAD\nOur content\n=======\nTheir content\n>>>>>>> feature",
+        )?;
+
+        // 应用解决方案
+        let resolved_content = "Resolved content";
+        handler.apply_resolution(conflict_path, resolved_content)?;
+
+        // 验证文件内容已更新
+        let new_content = std::fs::read_to_string(&file_path)?;
+        assert_eq!(new_content, resolved_content);
+
+        // 验证索引已更新
+        let index = handler.repo.index()?;
+        assert!(index
+            .get_path(std::path::Path::new(conflict_path), 0)
+            .is_some());
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_apply_resolution_preserves_executable_bit() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (temp_dir, handler) = setup_test_repo()?;
+        handler.checkout_branch("main")?;
+
+        let script = temp_dir.path().join("run.sh");
+        let commit_script = |content: &str, message: &str| -> Result<()> {
+            fs::write(&script, content)?;
+            fs::set_permissions(&script, fs::Permissions::from_mode(0o755))?;
+            let mut index = handler.repo.index()?;
+            index.add_path(Path::new("run.sh"))?;
+            index.write()?;
+            let tree = handler.repo.find_tree(index.write_tree()?)?;
+            let head = handler.repo.head()?.peel_to_commit()?;
+            let signature = handler.committer_signature()?;
+            handler.repo.commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                message,
+                &tree,
+                &[&head],
+            )?;
+            Ok(())
+        };
+
+        commit_script("#!/bin/sh\necho base\n", "Add script")?;
+        handler
+            .repo
+            .branch("feature", &handler.repo.head()?.peel_to_commit()?, false)?;
+        handler.checkout_branch("feature")?;
+        commit_script("#!/bin/sh\necho feature\n", "Feature script")?;
+        handler.checkout_branch("main")?;
+        commit_script("#!/bin/sh\necho main\n", "Main script")?;
+
+        assert!(matches!(
+            handler.merge_branches("main", "feature", &MergeOptions::default())?,
+            MergeOutcome::Conflicted(_)
+        ));
+
+        // 模拟写入时丢失了可执行位的情况
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o644))?;
+        handler.apply_resolution("run.sh", "#!/bin/sh\necho merged\n")?;
+
+        assert_eq!(fs::metadata(&script)?.permissions().mode() & 0o111, 0o111);
+        let entry = handler
+            .repo
+            .index()?
+            .get_path(Path::new("run.sh"), 0)
+            .ok_or_else(|| anyhow!("run.sh is not staged"))?;
+        assert_eq!(entry.mode, FILE_MODE_EXECUTABLE);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_abort_merge() -> Result<()> {
+        let (_temp_dir, handler) = setup_test_repo()?;
+
+        // 创建一个分支
+        let _branch = handler.repo.branch(
+            "feature-abort",
+            &handler.repo.head()?.peel_to_commit()?,
+            false,
+        )?;
+
+        // 切换到该分支
+        handler.checkout_branch("feature-abort")?;
+
+        // 创建一个提交
+        create_file_and_commit(
+            &handler.repo,
+            "abort_test.txt",
+            "abort test content",
+            "Add abort test file",
+        )?;
+
+        // 切回主分支
+        handler.checkout_branch("main")?;
+
+        // 创建相同文件的不同版本
+        create_file_and_commit(
+            &handler.repo,
+            "abort_test.txt",
+            "main content for abort test",
+            "Add abort test in main",
+        )?;
+
+        // 模拟合并状态 - 在真实情况下会由Git创建MERGE_HEAD等文件
+        // 我们可以手动创建一些文件来模拟合并状态
+        let workdir = handler
+            .repo
+            .workdir()
+            .ok_or_else(|| anyhow!("No working directory"))?;
+        let git_dir = workdir.join(".git");
+
+        // 创建一个MERGE_HEAD文件（通常在合并过程中会存在）
+        let merge_head_path = git_dir.join("MERGE_HEAD");
+        // 写入一个模拟的commit id
+        std::fs::write(&merge_head_path, "0000000000000000000000000000000000000000")?;
+
+        // 测试终止合并
+        handler.abort_merge()?;
+
+        // 验证MERGE_HEAD不再存在
+        assert!(
+            !merge_head_path.exists(),
+            "MERGE_HEAD should have been removed"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_abort_merge_resets_index_and_workdir() -> Result<()> {
+        let (temp_dir, handler) = setup_test_repo()?;
+        handler.checkout_branch("main")?;
+        handler
+            .repo
+            .branch("feature", &handler.repo.head()?.peel_to_commit()?, false)?;
+        handler.checkout_branch("feature")?;
+        create_file_and_commit(&handler.repo, "initial.txt", "feature", "Feature")?;
+        handler.checkout_branch("main")?;
+        create_file_and_commit(&handler.repo, "initial.txt", "main", "Main")?;
+
+        assert!(matches!(
+            handler.merge_branches("main", "feature", &MergeOptions::default())?,
+            MergeOutcome::Conflicted(_)
+        ));
+        handler.abort_merge()?;
+
+        // 冲突标记和冲突条目都应被清除
+        assert_eq!(handler.repository_state(), git2::RepositoryState::Clean);
+        assert!(!handler.repo.index()?.has_conflicts());
+        assert_eq!(
+            fs::read_to_string(temp_dir.path().join("initial.txt"))?,
+            "main"
+        );
+        assert!(handler.uncommitted_changes()?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_abort_merge_without_merge_keeps_changes() -> Result<()> {
+        let (temp_dir, handler) = setup_test_repo()?;
+        fs::write(temp_dir.path().join("initial.txt"), "local change")?;
+
+        handler.abort_merge()?;
+
+        assert_eq!(
+            fs::read_to_string(temp_dir.path().join("initial.txt"))?,
+            "local change"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_unique_commits_with_filter() -> Result<()> {
+        let (_temp_dir, handler) = setup_test_repo()?;
+        handler
+            .repo
+            .branch("feature", &handler.repo.head()?.peel_to_commit()?, false)?;
+        handler.checkout_branch("feature")?;
+
+        create_file_and_commit(&handler.repo, "mine.txt", "mine", "Add mine")?;
+
+        // 以其他作者身份、指定时间创建提交
+        let author = git2::Signature::new(
+            "Alice Example",
+            "alice@example.com",
+            &git2::Time::new(parse_date("2024-05-01T12:00:00Z", false)?, 0),
+        )?;
+        fs::write(handler.repo.workdir().unwrap().join("alice.txt"), "alice")?;
+        let mut index = handler.repo.index()?;
+        index.add_path(Path::new("alice.txt"))?;
+        index.write()?;
+        let tree = handler.repo.find_tree(index.write_tree()?)?;
+        let parent = handler.repo.head()?.peel_to_commit()?;
+        handler.repo.commit(
+            Some("HEAD"),
+            &author,
+            &author,
+            "Add alice",
+            &tree,
+            &[&parent],
+        )?;
+
+        let by_author = CommitFilter {
+            author: Some("ALICE".to_string()),
+            ..Default::default()
+        };
+        let unique = handler.list_unique_commits("feature", "main", &by_author)?;
+        assert_eq!(unique.len(), 1);
+        assert_eq!(unique[0].summary, "Add alice");
+        assert_eq!(unique[0].author_name, "Alice Example");
+        assert_eq!(unique[0].author_email, "alice@example.com");
+        assert_eq!(
+            unique[0].time.seconds(),
+            parse_date("2024-05-01T12:00:00Z", false)?
+        );
+
+        let in_may = CommitFilter {
+            since: Some(parse_date("2024-05-01", false)?),
+            until: Some(parse_date("2024-05-01", true)?),
+            ..Default::default()
+        };
+        let unique = handler.list_unique_commits("feature", "main", &in_may)?;
+        assert_eq!(unique.len(), 1);
+        assert!(unique[0].summary.contains("Add alice"));
+
+        let after_may = CommitFilter {
+            since: Some(parse_date("2024-05-02", false)?),
+            ..Default::default()
+        };
+        let unique = handler.list_unique_commits("feature", "main", &after_may)?;
+        assert_eq!(unique.len(), 1);
+        assert!(unique[0].summary.contains("Add mine"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_date() -> Result<()> {
+        assert_eq!(parse_date("1970-01-02", false)?, 86400);
+        assert_eq!(parse_date("1970-01-01", true)?, 86399);
+        assert_eq!(parse_date("2024-03-01", false)?, 1709251200);
+        assert_eq!(
+            parse_date("2024-03-01T08:00:00+08:00", false)?,
+            parse_date("2024-03-01", false)?
+        );
+        assert_eq!(parse_date("2024-03-01T00:00:00.5Z", false)?, 1709251200);
+        assert!(parse_date("2023-02-29", false).is_err());
+        assert!(parse_date("yesterday", false).is_err());
+        assert!(parse_date("2024-03-01T25:00:00Z", false).is_err());
+
+        let time = git2::Time::new(parse_date("2024-02-29T23:30:00Z", false)?, 480);
+        assert_eq!(format_time(time), "2024-03-01 07:30 +0800");
+        let time = git2::Time::new(0, -90);
+        assert_eq!(format_time(time), "1969-12-31 22:30 -0130");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lfs_pointer_conflict() -> Result<()> {
+        let (_temp_dir, handler) = setup_test_repo()?;
+        let pointer = |oid: &str| {
+            format!(
+                "version https://git-lfs.github.com/spec/v1\noid sha256:{}\nsize 42\n",
+                oid
+            )
+        };
+
+        create_file_and_commit(&handler.repo, "logo.png", &pointer("base"), "Add logo")?;
+        handler
+            .repo
+            .branch("feature", &handler.repo.head()?.peel_to_commit()?, false)?;
+        handler.checkout_branch("feature")?;
+        create_file_and_commit(&handler.repo, "logo.png", &pointer("theirs"), "New logo")?;
+        handler.checkout_branch("main")?;
+        create_file_and_commit(&handler.repo, "logo.png", &pointer("ours"), "Tweak logo")?;
+
+        let conflicts = handler.simulate_conflicts("main", "feature")?;
+        assert_eq!(conflicts.len(), 1);
+        assert!(conflicts[0].is_lfs_pointer());
+        assert!(conflicts[0].their_content.contains("sha256:theirs"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_unique_commits_from_reflog() -> Result<()> {
+        let (_temp_dir, handler) = setup_test_repo()?;
+        handler.checkout_branch("main")?;
+
+        create_file_and_commit(&handler.repo, "pulled1.txt", "pulled1", "Add pulled1")?;
+        create_file_and_commit(&handler.repo, "pulled2.txt", "pulled2", "Add pulled2")?;
+
+        // main@{2} 是两次提交之前 main 指向的位置
+        let unique = handler.list_unique_commits("main", "main@{2}", &CommitFilter::default())?;
+        assert_eq!(unique.len(), 2);
+        assert!(unique[0].summary.contains("Add pulled2"));
+
+        let unique = handler.list_unique_commits("main", "main@{1}", &CommitFilter::default())?;
+        assert_eq!(unique.len(), 1);
+
+        // 不存在的引用日志条目给出明确的错误
+        let err = handler
+            .list_unique_commits("main", "main@{10}", &CommitFilter::default())
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Reflog entry 'main@{10}' does not exist"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_upstream_of() -> Result<()> {
+        let (_temp_dir, handler) = setup_test_repo()?;
+        handler.checkout_branch("main")?;
+        let head = handler.repo.head()?.peel_to_commit()?;
+
+        let err = handler.upstream_of("main").unwrap_err();
+        assert!(err.to_string().contains("has no upstream configured"));
+        assert!(handler.upstream_of("missing").is_err());
+
+        // 远程跟踪分支
+        handler
+            .repo
+            .remote("origin", "https://example.com/repo.git")?;
+        handler
+            .repo
+            .reference("refs/remotes/origin/main", head.id(), false, "fetch")?;
+        let mut main = handler.repo.find_branch("main", BranchType::Local)?;
+        main.set_upstream(Some("origin/main"))?;
+        assert_eq!(handler.upstream_of("main")?, "origin/main");
+        assert!(handler.branch_exists(&handler.upstream_of("main")?)?);
+
+        // 以本地分支为上游
+        handler.repo.branch("develop", &head, false)?;
+        let mut develop = handler.repo.find_branch("develop", BranchType::Local)?;
+        develop.set_upstream(Some("main"))?;
+        assert_eq!(handler.upstream_of("develop")?, "main");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_unique_commits_from_revisions() -> Result<()> {
+        let (_temp_dir, handler) = setup_test_repo()?;
+        handler.checkout_branch("main")?;
+
+        let released = handler.repo.head()?.peel_to_commit()?.id();
+        handler.create_tag("v1.0.0", None, Some("Release"))?;
+        create_file_and_commit(&handler.repo, "fix1.txt", "fix1", "Add fix1")?;
+        let fix2 = create_file_and_commit(&handler.repo, "fix2.txt", "fix2", "Add fix2")?;
+        create_file_and_commit(&handler.repo, "fix3.txt", "fix3", "Add fix3")?;
+
+        // 附注标签解析为它指向的提交
+        assert_eq!(handler.resolve_revision("v1.0.0")?, released);
+        let unique = handler.list_unique_commits("main", "v1.0.0", &CommitFilter::default())?;
+        assert_eq!(unique.len(), 3);
+
+        // 提交哈希和相对修订
+        let unique =
+            handler.list_unique_commits("main", &fix2.to_string(), &CommitFilter::default())?;
+        assert_eq!(unique.len(), 1);
+        assert!(unique[0].summary.contains("Add fix3"));
+        let unique = handler.list_unique_commits("HEAD~1", "v1.0.0", &CommitFilter::default())?;
+        assert_eq!(unique.len(), 2);
+        assert!(unique[0].summary.contains("Add fix2"));
+
+        assert!(handler.resolve_revision("v9.9.9").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_unique_commits() -> Result<()> {
+        let (_temp_dir, handler) = setup_test_repo()?;
+
+        // 创建一个新分支
+        let _branch =
+            handler
+                .repo
+                .branch("feature", &handler.repo.head()?.peel_to_commit()?, false)?;
+
+        // 切换到新分支并创建几个提交
+        handler.checkout_branch("feature")?;
+
+        // 创建第一个提交
+        create_file_and_commit(
+            &handler.repo,
+            "feature1.txt",
+            "feature1 content",
+            "Add feature1",
+        )?;
+
+        // 创建第二个提交
+        create_file_and_commit(
+            &handler.repo,
+            "feature2.txt",
+            "feature2 content",
+            "Add feature2",
+        )?;
+
+        // 切回 main 分支
+        handler.checkout_branch("main")?;
+
+        // 创建一个 main 分支上的提交
+        create_file_and_commit(&handler.repo, "main1.txt", "main1 content", "Add main1")?;
+
+        // 测试 feature 分支的独有提交（相对于 main）
+        let feature_unique =
+            handler.list_unique_commits("feature", "main", &CommitFilter::default())?;
+        assert_eq!(feature_unique.len(), 2);
+        assert!(feature_unique[0].summary.contains("Add feature2"));
+        assert!(feature_unique[1].summary.contains("Add feature1"));
+
+        // 限制数量时只返回最新的提交
+        let limited = CommitFilter {
+            limit: Some(1),
+            ..Default::default()
+        };
+        let feature_unique = handler.list_unique_commits("feature", "main", &limited)?;
+        assert_eq!(feature_unique.len(), 1);
+        assert!(feature_unique[0].summary.contains("Add feature2"));
+
+        // 测试 main 分支的独有提交（相对于 feature）
+        let main_unique =
+            handler.list_unique_commits("main", "feature", &CommitFilter::default())?;
+        assert_eq!(main_unique.len(), 1);
+        assert!(main_unique[0].summary.contains("Add main1"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dirty_submodules() -> Result<()> {
+        let (temp_dir, handler) = setup_test_repo()?;
+
+        // 没有子模块时不应报告任何修改
+        assert!(handler.dirty_submodules()?.is_empty());
+
+        // 创建一个作为子模块来源的仓库
+        let (sub_dir, _sub_handler) = setup_test_repo()?;
+        let sub_url = sub_dir.path().to_str().unwrap().to_string();
+
+        let mut submodule = handler.repo.submodule(&sub_url, Path::new("sub"), true)?;
+        submodule.clone(None)?;
+        submodule.add_finalize()?;
+
+        // 在父仓库中提交子模块
+        let signature = handler.committer_signature()?;
+        let tree = handler
+            .repo
+            .find_tree(handler.repo.index()?.write_tree()?)?;
+        let head = handler.repo.head()?.peel_to_commit()?;
+        handler.repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "Add sub",
+            &tree,
+            &[&head],
+        )?;
+
+        // 干净的子模块
+        assert!(handler.dirty_submodules()?.is_empty());
+
+        // 修改子模块中已跟踪的文件
+        let tracked = temp_dir.path().join("sub/initial.txt");
+        let original = fs::read_to_string(&tracked)?;
+        fs::write(&tracked, "changed content")?;
+        assert_eq!(handler.dirty_submodules()?, vec!["sub".to_string()]);
+        fs::write(&tracked, original)?;
+        assert!(handler.dirty_submodules()?.is_empty());
+
+        // 子模块中有父仓库未记录的新提交
+        let sub_repo = Repository::open(temp_dir.path().join("sub"))?;
+        let mut sub_config = sub_repo.config()?;
+        sub_config.set_str("user.name", "Test User")?;
+        sub_config.set_str("user.email", "test@example.com")?;
+        create_file_and_commit(&sub_repo, "ahead.txt", "ahead", "Ahead of gitlink")?;
+        assert_eq!(handler.dirty_submodules()?, vec!["sub".to_string()]);
+
+        // 父仓库中已暂存但未提交子模块的新提交
+        let mut index = handler.repo.index()?;
+        index.add_path(Path::new("sub"))?;
+        index.write()?;
+        assert_eq!(handler.dirty_submodules()?, vec!["sub".to_string()]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_remote_tracking_branch() -> Result<()> {
+        let (_temp_dir, handler) = setup_test_repo()?;
+
+        // 在 feature 分支上创建提交，并模拟为远程跟踪分支 origin/feature
+        handler
+            .repo
+            .branch("feature", &handler.repo.head()?.peel_to_commit()?, false)?;
+        handler.checkout_branch("feature")?;
+        let feature_commit = create_file_and_commit(
+            &handler.repo,
+            "feature.txt",
+            "feature content",
+            "Add feature",
+        )?;
+        handler.repo.reference(
+            "refs/remotes/origin/feature",
+            feature_commit,
+            true,
+            "test remote-tracking branch",
+        )?;
+
+        // main 分支上的提交，使合并无法快进
+        handler.checkout_branch("main")?;
+        create_file_and_commit(&handler.repo, "main.txt", "main content", "Main change")?;
+
+        assert!(handler.branch_exists("origin/feature")?);
+        assert_eq!(handler.get_branch_commit("origin/feature")?, feature_commit);
+
+        let outcome = handler.merge_branches("main", "origin/feature", &MergeOptions::default())?;
+        assert_eq!(
+            outcome,
+            MergeOutcome::Merged(handler.get_branch_commit("main")?)
+        );
+
+        let head = handler.repo.head()?.peel_to_commit()?;
+        assert_eq!(head.parent_count(), 2);
+        assert_eq!(head.parent_id(1)?, feature_commit);
+        assert_eq!(
+            head.message().unwrap(),
+            "Merge remote-tracking branch 'origin/feature' into 'main'"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_simulate_conflicts() -> Result<()> {
+        let (temp_dir, handler) = setup_test_repo()?;
+
+        handler
+            .repo
+            .branch("feature", &handler.repo.head()?.peel_to_commit()?, false)?;
+
+        handler.checkout_branch("main")?;
+        create_file_and_commit(&handler.repo, "conflict.txt", "main content", "Main change")?;
+        handler.checkout_branch("feature")?;
+        create_file_and_commit(
+            &handler.repo,
+            "conflict.txt",
+            "feature content",
+            "Feature change",
+        )?;
+        handler.checkout_branch("main")?;
+        let head_before = handler.repo.head()?.target();
+
+        let conflicts = handler.simulate_conflicts("main", "feature")?;
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].path, "conflict.txt");
+        assert_eq!(conflicts[0].our_content, "main content");
+        assert_eq!(conflicts[0].their_content, "feature content");
+
+        // 模拟合并不应修改仓库状态
+        assert_eq!(handler.repo.head()?.target(), head_before);
+        assert_eq!(handler.repo.state(), git2::RepositoryState::Clean);
+        assert!(!handler.repo.index()?.has_conflicts());
+        assert_eq!(
+            fs::read_to_string(temp_dir.path().join("conflict.txt"))?,
+            "main content"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_uncommitted_changes() -> Result<()> {
+        let (temp_dir, handler) = setup_test_repo()?;
+        assert!(handler.uncommitted_changes()?.is_empty());
+
+        // 未跟踪的文件不算作未提交的修改
+        fs::write(temp_dir.path().join("untracked.txt"), "untracked")?;
+        assert!(handler.uncommitted_changes()?.is_empty());
+
+        fs::write(temp_dir.path().join("initial.txt"), "modified")?;
+        assert_eq!(handler.uncommitted_changes()?, vec!["initial.txt"]);
+
+        fs::write(temp_dir.path().join("staged.txt"), "staged")?;
+        let mut index = handler.repo.index()?;
+        index.add_path(Path::new("staged.txt"))?;
+        index.write()?;
+        let mut changes = handler.uncommitted_changes()?;
+        changes.sort();
+        assert_eq!(changes, vec!["initial.txt", "staged.txt"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_log_branch() -> Result<()> {
+        let (_temp_dir, handler) = setup_test_repo()?;
+
+        handler.checkout_branch("main")?;
+        let first = create_file_and_commit(&handler.repo, "a.txt", "a", "First")?;
+        let second = create_file_and_commit(&handler.repo, "b.txt", "b", "Second")?;
+
+        let commits = handler.log_branch("main", None)?;
+        let summaries: Vec<&str> = commits.iter().map(|c| c.summary.as_str()).collect();
+        assert_eq!(summaries, vec!["Second", "First", "Initial commit"]);
+        assert_eq!(commits[0].id, second);
+        assert_eq!(commits[1].id, first);
+
+        let commits = handler.log_branch("main", Some(2))?;
+        assert_eq!(commits.len(), 2);
+        assert_eq!(commits[0].id, second);
+
+        assert!(handler.log_branch("missing", None).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_from_subdirectory() -> Result<()> {
+        let (temp_dir, _handler) = setup_test_repo()?;
+        let subdir = temp_dir.path().join("src/nested");
+        fs::create_dir_all(&subdir)?;
+
+        let handler = GitHandler::new(subdir.to_str().unwrap())?;
+        assert_eq!(
+            handler.workdir()?.canonicalize()?,
+            temp_dir.path().canonicalize()?
+        );
+
+        // 严格模式下只接受仓库根目录
+        assert!(GitHandler::open(subdir.to_str().unwrap()).is_err());
+        assert!(GitHandler::open(temp_dir.path().to_str().unwrap()).is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bare_repository() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        Repository::init_bare(temp_dir.path())?;
+        let handler = GitHandler::new(temp_dir.path().to_str().unwrap())?;
+
+        // 需要工作目录的操作返回错误而不是 panic
+        let err = handler.apply_resolution("a.txt", "content").unwrap_err();
+        assert!(err.to_string().contains("requires a working directory"));
+        assert!(handler.uncommitted_changes().is_err());
+        assert!(handler
+            .merge_branches("main", "feature", &MergeOptions::default())
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_linked_worktree() -> Result<()> {
+        let (temp_dir, handler) = setup_test_repo()?;
+
+        let worktree_path = temp_dir.path().join("wt");
+        let worktree = handler.repo.worktree("wt", &worktree_path, None)?;
+        let handler = GitHandler::new(worktree.path().to_str().unwrap())?;
+
+        // 在工作树中打开时，解决方案应写入该工作树
+        assert_eq!(
+            handler.workdir()?.canonicalize()?,
+            worktree_path.canonicalize()?
+        );
+        handler.apply_resolution("initial.txt", "resolved")?;
+        assert_eq!(
+            fs::read_to_string(worktree_path.join("initial.txt"))?,
+            "resolved"
+        );
+        assert_eq!(
+            fs::read_to_string(temp_dir.path().join("initial.txt"))?,
+            "initial content"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_preview_merge() -> Result<()> {
+        let (temp_dir, handler) = setup_test_repo()?;
+
+        handler
+            .repo
+            .branch("feature", &handler.repo.head()?.peel_to_commit()?, false)?;
+
+        handler.checkout_branch("main")?;
+        create_file_and_commit(&handler.repo, "conflict.txt", "main content", "Main change")?;
+        create_file_and_commit(&handler.repo, "main.txt", "main only", "Main only")?;
+        handler.checkout_branch("feature")?;
+        create_file_and_commit(
+            &handler.repo,
+            "conflict.txt",
+            "feature content",
+            "Feature change",
+        )?;
+        create_file_and_commit(&handler.repo, "feature.txt", "feature only", "Feature only")?;
+        handler.checkout_branch("main")?;
+        let head_before = handler.repo.head()?.target();
+
+        assert_eq!(
+            handler.preview_merge("main", "feature")?,
+            vec!["conflict.txt".to_string()]
+        );
+
+        // 预览不应修改仓库状态
+        assert_eq!(handler.repo.head()?.target(), head_before);
+        assert_eq!(handler.repo.state(), git2::RepositoryState::Clean);
+        assert!(handler.repo.find_reference("MERGE_HEAD").is_err());
+        assert!(!handler.repo.index()?.has_conflicts());
+        assert!(!temp_dir.path().join("feature.txt").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_no_ff() -> Result<()> {
+        let (_temp_dir, handler) = setup_test_repo()?;
+
+        handler
+            .repo
+            .branch("feature", &handler.repo.head()?.peel_to_commit()?, false)?;
+        handler.checkout_branch("main")?;
+        let main_commit = handler.get_branch_commit("main")?;
+
+        handler.checkout_branch("feature")?;
+        let feature_commit = create_file_and_commit(
+            &handler.repo,
+            "feature.txt",
+            "feature content",
+            "Add feature",
+        )?;
+        handler.checkout_branch("main")?;
 
-        ref_head.set_target(target_commit, "Fast-forward merge")?;
-        self.repo.set_head(&ref_name)?;
+        // 可以快进，但要求创建合并提交
+        let options = MergeOptions {
+            no_ff: true,
+            ..Default::default()
+        };
+        let outcome = handler.merge_branches("main", "feature", &options)?;
+        assert_eq!(
+            outcome,
+            MergeOutcome::Merged(handler.get_branch_commit("main")?)
+        );
 
-        // 改进 checkout 选项配置
-        let mut checkout_opts = git2::build::CheckoutBuilder::new();
-        checkout_opts
-            .force() // 强制检出
-            .allow_conflicts(true) // 允许冲突
-            .remove_untracked(false) // 不移除未跟踪的文件
-            .remove_ignored(false) // 不移除被忽略的文件
-            .update_index(true) // 更新索引
-            .recreate_missing(true); // 重新创建丢失的文件
+        let head = handler.repo.head()?.peel_to_commit()?;
+        assert_eq!(head.parent_count(), 2);
+        assert_eq!(head.parent_id(0)?, main_commit);
+        assert_eq!(head.parent_id(1)?, feature_commit);
+        assert!(handler.repo.workdir().unwrap().join("feature.txt").exists());
 
-        self.repo.checkout_head(Some(&mut checkout_opts))?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_clean_merge_keeps_local_changes() -> Result<()> {
+        let (temp_dir, handler) = setup_test_repo()?;
+
+        handler
+            .repo
+            .branch("feature", &handler.repo.head()?.peel_to_commit()?, false)?;
+        handler.checkout_branch("main")?;
+        let main_commit = create_file_and_commit(&handler.repo, "main.txt", "main", "Main")?;
+        handler.checkout_branch("feature")?;
+        let feature_commit =
+            create_file_and_commit(&handler.repo, "feature.txt", "feature", "Feature")?;
+        handler.checkout_branch("main")?;
+
+        // 与合并无关的未提交修改不应被覆盖
+        fs::write(temp_dir.path().join("initial.txt"), "local change")?;
+
+        let outcome = handler.merge_branches("main", "feature", &MergeOptions::default())?;
+        assert_eq!(
+            outcome,
+            MergeOutcome::Merged(handler.get_branch_commit("main")?)
+        );
+
+        let head = handler.repo.head()?;
+        assert_eq!(head.shorthand(), Some("main"));
+        let head = head.peel_to_commit()?;
+        assert_eq!(head.parent_id(0)?, main_commit);
+        assert_eq!(head.parent_id(1)?, feature_commit);
+        assert_eq!(handler.repo.state(), git2::RepositoryState::Clean);
+        assert!(temp_dir.path().join("feature.txt").exists());
+        assert_eq!(
+            fs::read_to_string(temp_dir.path().join("initial.txt"))?,
+            "local change"
+        );
 
         Ok(())
     }
 
-    /// 终止合并操作
-    pub fn abort_merge(&self) -> Result<()> {
-        self.repo.cleanup_state()?;
+    #[test]
+    fn test_clean_merge_refuses_to_overwrite_local_changes() -> Result<()> {
+        let (temp_dir, handler) = setup_test_repo()?;
+
+        handler
+            .repo
+            .branch("feature", &handler.repo.head()?.peel_to_commit()?, false)?;
+        handler.checkout_branch("main")?;
+        let main_commit = create_file_and_commit(&handler.repo, "main.txt", "main", "Main")?;
+        handler.checkout_branch("feature")?;
+        create_file_and_commit(&handler.repo, "initial.txt", "feature", "Feature")?;
+        handler.checkout_branch("main")?;
+
+        // 合并会修改的文件存在本地修改时，应报错并保持分支不变
+        fs::write(temp_dir.path().join("initial.txt"), "local change")?;
+
+        assert!(handler
+            .merge_branches("main", "feature", &MergeOptions::default())
+            .is_err());
+        assert_eq!(handler.get_branch_commit("main")?, main_commit);
+        assert_eq!(
+            fs::read_to_string(temp_dir.path().join("initial.txt"))?,
+            "local change"
+        );
+
         Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs;
-    use std::path::Path;
-    use tempfile::TempDir;
+    /// 创建可以快进的 feature 分支，返回 no-ff 合并后的提交
+    fn merge_feature_no_ff(handler: &GitHandler) -> Result<Oid> {
+        handler
+            .repo
+            .branch("feature", &handler.repo.head()?.peel_to_commit()?, false)?;
+        handler.checkout_branch("feature")?;
+        create_file_and_commit(&handler.repo, "feature.txt", "feature", "Add feature")?;
+        handler.checkout_branch("main")?;
 
-    fn setup_test_repo() -> Result<(TempDir, GitHandler)> {
-        let temp_dir = TempDir::new()?;
-        let repo = Repository::init(temp_dir.path())?;
+        let options = MergeOptions {
+            no_ff: true,
+            ..Default::default()
+        };
+        handler.merge_branches("main", "feature", &options)?;
+        handler.get_branch_commit("main")
+    }
 
-        // 设置测试用的 Git 配置，确保使用安全的字符串
-        let mut config = repo.config()?;
-        let safe_name = "Test User".replace('\0', "");
-        let safe_email = "test@example.com".replace('\0', "");
-        config.set_str("user.name", &safe_name)?;
-        config.set_str("user.email", &safe_email)?;
-        config.set_str("init.defaultBranch", "main")?;
+    #[test]
+    fn test_binary_conflict() -> Result<()> {
+        let (temp_dir, handler) = setup_test_repo()?;
+
+        let write_binary = |content: &[u8], message: &str| -> Result<()> {
+            fs::write(temp_dir.path().join("image.bin"), content)?;
+            let mut index = handler.repo.index()?;
+            index.add_path(Path::new("image.bin"))?;
+            index.write()?;
+            let tree = handler.repo.find_tree(index.write_tree()?)?;
+            let head = handler.repo.head()?.peel_to_commit()?;
+            let signature = handler.committer_signature()?;
+            handler.repo.commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                message,
+                &tree,
+                &[&head],
+            )?;
+            Ok(())
+        };
 
-        // 创建初始提交
-        let signature = git2::Signature::now(&safe_name, &safe_email)?;
-        {
-            // 创建一个测试文件，确保内容不包含空字节
-            let safe_content = "initial content".replace('\0', "");
-            fs::write(temp_dir.path().join("initial.txt"), safe_content)?;
+        write_binary(b"\x00base\x01", "Add binary")?;
+        handler
+            .repo
+            .branch("feature", &handler.repo.head()?.peel_to_commit()?, false)?;
+        handler.checkout_branch("feature")?;
+        write_binary(b"\x00feature\x02", "Feature binary")?;
+        handler.checkout_branch("main")?;
+        write_binary(b"\x00main\x03", "Main binary")?;
 
-            let mut index = repo.index()?;
-            index.add_path(Path::new("initial.txt"))?;
-            let id = index.write_tree()?;
-            let tree = repo.find_tree(id)?;
+        assert!(matches!(
+            handler.merge_branches("main", "feature", &MergeOptions::default())?,
+            MergeOutcome::Conflicted(_)
+        ));
 
-            // 创建初始提交并设置 main 分支
-            let commit_id = repo.commit(
+        // 二进制冲突不会被丢弃
+        let conflicts = handler.get_conflicts()?;
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].path, "image.bin");
+        assert!(conflicts[0].is_binary);
+        assert!(conflicts[0].our_content.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_non_utf8_content_conflict() -> Result<()> {
+        let (temp_dir, handler) = setup_test_repo()?;
+
+        // Latin-1 编码的文本，没有空字节，git 不会将其识别为二进制文件
+        let write_latin1 = |content: &[u8], message: &str| -> Result<()> {
+            fs::write(temp_dir.path().join("latin1.txt"), content)?;
+            let mut index = handler.repo.index()?;
+            index.add_path(Path::new("latin1.txt"))?;
+            index.write()?;
+            let tree = handler.repo.find_tree(index.write_tree()?)?;
+            let head = handler.repo.head()?.peel_to_commit()?;
+            let signature = handler.committer_signature()?;
+            handler.repo.commit(
                 Some("HEAD"),
                 &signature,
                 &signature,
-                "Initial commit",
+                message,
                 &tree,
-                &[],
+                &[&head],
             )?;
+            Ok(())
+        };
 
-            repo.branch("main", &repo.find_commit(commit_id)?, false)?;
-        }
+        write_latin1(b"caf\xe9\n", "Add latin1")?;
+        handler
+            .repo
+            .branch("feature", &handler.repo.head()?.peel_to_commit()?, false)?;
+        handler.checkout_branch("feature")?;
+        write_latin1(b"caf\xe9 feature\n", "Feature latin1")?;
+        handler.checkout_branch("main")?;
+        write_latin1(b"caf\xe9 main\n", "Main latin1")?;
+
+        assert!(matches!(
+            handler.merge_branches("main", "feature", &MergeOptions::default())?,
+            MergeOutcome::Conflicted(_)
+        ));
+
+        // 无法解码的文本冲突按二进制文件报告，不会被丢弃
+        let conflicts = handler.get_conflicts()?;
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].path, "latin1.txt");
+        assert!(conflicts[0].is_binary);
+        assert!(conflicts[0].our_content.is_empty());
 
-        Ok((temp_dir, GitHandler { repo }))
+        Ok(())
     }
 
-    fn create_file_and_commit(
-        repo: &Repository,
-        path: &str,
-        content: &str,
-        message: &str,
-    ) -> Result<Oid> {
-        let workdir = repo
-            .workdir()
-            .ok_or_else(|| anyhow!("No working directory"))?;
-        let file_path = workdir.join(path);
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_path_conflict() -> Result<()> {
+        use std::os::unix::ffi::OsStrExt;
+
+        let (temp_dir, handler) = setup_test_repo()?;
+        let raw_path = b"caf\xe9.txt";
+        let path = Path::new(std::ffi::OsStr::from_bytes(raw_path));
+
+        let write_and_commit = |content: &str, message: &str| -> Result<()> {
+            fs::write(temp_dir.path().join(path), content)?;
+            let mut index = handler.repo.index()?;
+            index.add_path(path)?;
+            index.write()?;
+            let tree = handler.repo.find_tree(index.write_tree()?)?;
+            let head = handler.repo.head()?.peel_to_commit()?;
+            let signature = handler.committer_signature()?;
+            handler.repo.commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                message,
+                &tree,
+                &[&head],
+            )?;
+            Ok(())
+        };
 
-        // 写入文件内容前移除空字节
-        let safe_content = content.replace('\0', "");
-        fs::write(&file_path, safe_content)?;
+        handler.checkout_branch("main")?;
+        write_and_commit("base\n", "Add file")?;
+        handler
+            .repo
+            .branch("feature", &handler.repo.head()?.peel_to_commit()?, false)?;
+        handler.checkout_branch("feature")?;
+        write_and_commit("feature\n", "Feature change")?;
+        handler.checkout_branch("main")?;
+        write_and_commit("main\n", "Main change")?;
 
-        // 使用安全的签名信息
-        let config = repo.config()?;
-        let name = config.get_string("user.name")?.replace('\0', "");
-        let email = config.get_string("user.email")?.replace('\0', "");
-        let signature = git2::Signature::now(&name, &email)?;
+        assert!(matches!(
+            handler.merge_branches("main", "feature", &MergeOptions::default())?,
+            MergeOutcome::Conflicted(_)
+        ));
 
-        let mut index = repo.index()?;
-        index.add_path(Path::new(path))?;
-        let id = index.write_tree()?;
-        let tree = repo.find_tree(id)?;
+        // 路径不是有效的 UTF-8 的冲突不会被丢弃，显示路径替换了无效字节
+        let conflicts = handler.get_conflicts()?;
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].path, "caf\u{fffd}.txt");
+        assert_eq!(conflicts[0].raw_path, raw_path);
+        assert!(!conflicts[0].has_utf8_path());
+        assert_eq!(conflicts[0].our_content, "main\n");
+        assert_eq!(conflicts[0].their_content, "feature\n");
 
-        let parent_commit = repo.head()?.peel_to_commit()?;
-        let commit_id = repo.commit(
-            Some("HEAD"),
-            &signature,
-            &signature,
-            message,
-            &tree,
-            &[&parent_commit],
-        )?;
+        Ok(())
+    }
 
-        Ok(commit_id)
+    #[test]
+    fn test_deletion_conflicts() -> Result<()> {
+        let (temp_dir, handler) = setup_test_repo()?;
+        handler.checkout_branch("main")?;
+        create_file_and_commit(&handler.repo, "other.txt", "other", "Add other")?;
+
+        let remove_and_commit = |path: &str, message: &str| -> Result<()> {
+            fs::remove_file(temp_dir.path().join(path))?;
+            let mut index = handler.repo.index()?;
+            index.remove_path(Path::new(path))?;
+            index.write()?;
+            let tree = handler.repo.find_tree(index.write_tree()?)?;
+            let head = handler.repo.head()?.peel_to_commit()?;
+            let signature = handler.committer_signature()?;
+            handler.repo.commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                message,
+                &tree,
+                &[&head],
+            )?;
+            Ok(())
+        };
+
+        handler
+            .repo
+            .branch("feature", &handler.repo.head()?.peel_to_commit()?, false)?;
+        handler.checkout_branch("feature")?;
+        remove_and_commit("initial.txt", "Remove initial")?;
+        create_file_and_commit(&handler.repo, "other.txt", "feature other", "Edit other")?;
+        handler.checkout_branch("main")?;
+        create_file_and_commit(&handler.repo, "initial.txt", "main content", "Edit initial")?;
+        remove_and_commit("other.txt", "Remove other")?;
+
+        assert!(matches!(
+            handler.merge_branches("main", "feature", &MergeOptions::default())?,
+            MergeOutcome::Conflicted(_)
+        ));
+
+        // 一方删除了文件的冲突不会被丢弃，并保留另一方的内容
+        let mut conflicts = handler.get_conflicts()?;
+        conflicts.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(conflicts.len(), 2);
+
+        assert_eq!(conflicts[0].path, "initial.txt");
+        assert_eq!(conflicts[0].kind, ConflictKind::DeletedByThem);
+        assert!(conflicts[0].is_deletion());
+        assert_eq!(conflicts[0].our_content, "main content");
+        assert!(conflicts[0].their_content.is_empty());
+        assert_eq!(
+            conflicts[0].base_content.as_deref(),
+            Some("initial content")
+        );
+
+        assert_eq!(conflicts[1].path, "other.txt");
+        assert_eq!(conflicts[1].kind, ConflictKind::DeletedByUs);
+        assert!(conflicts[1].our_content.is_empty());
+        assert_eq!(conflicts[1].their_content, "feature other");
+
+        Ok(())
     }
 
     #[test]
-    fn test_branch_operations() -> Result<()> {
+    fn test_merge_message() -> Result<()> {
         let (_temp_dir, handler) = setup_test_repo()?;
 
-        // 测试分支不存在
-        assert!(!handler.branch_exists("test-branch")?);
+        assert_eq!(
+            handler.default_merge_message("main", "main")?,
+            "Merge branch 'main' into 'main'"
+        );
 
-        // 创建新分支
-        let _branch_ref = handler.repo.branch(
-            "test-branch",
-            &handler.repo.head()?.peel_to_commit()?,
-            false,
-        )?;
+        handler
+            .repo
+            .branch("feature", &handler.repo.head()?.peel_to_commit()?, false)?;
+        handler.checkout_branch("feature")?;
+        create_file_and_commit(&handler.repo, "feature.txt", "feature", "Add feature")?;
+        handler.checkout_branch("main")?;
 
-        // 测试分支存在
-        assert!(handler.branch_exists("test-branch")?);
+        let options = MergeOptions {
+            no_ff: true,
+            message: Some("Custom merge message".to_string()),
+            ..Default::default()
+        };
+        handler.merge_branches("main", "feature", &options)?;
 
-        // 测试切换分支
-        handler.checkout_branch("test-branch")?;
-        assert_eq!(handler.repo.head()?.shorthand().unwrap(), "test-branch");
+        let head = handler.repo.head()?.peel_to_commit()?;
+        assert_eq!(head.message(), Some("Custom merge message"));
+        assert_eq!(head.parent_count(), 2);
 
         Ok(())
     }
 
+    #[cfg(unix)]
     #[test]
-    fn test_get_branch_commit() -> Result<()> {
-        let (_temp_dir, handler) = setup_test_repo()?;
+    fn test_signed_merge_commit() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
 
-        // 创建一个新分支
-        let original_head = handler.repo.head()?.peel_to_commit()?.id();
-        let _branch_ref = handler.repo.branch(
-            "test-branch",
-            &handler.repo.head()?.peel_to_commit()?,
-            false,
+        let (temp_dir, handler) = setup_test_repo()?;
+
+        // 模拟的签名程序：读取提交内容并输出固定的签名
+        let program = temp_dir.path().join("fake-gpg");
+        fs::write(
+            &program,
+            "#!/bin/sh\ncat > /dev/null\necho '-----BEGIN PGP SIGNATURE-----'\necho 'fake'\necho '-----END PGP SIGNATURE-----'\n",
         )?;
+        fs::set_permissions(&program, fs::Permissions::from_mode(0o755))?;
 
-        // 获取新分支的提交 ID
-        let branch_commit = handler.get_branch_commit("test-branch")?;
+        let mut config = handler.repo.config()?;
+        config.set_bool("commit.gpgsign", true)?;
+        config.set_str("gpg.program", program.to_str().unwrap())?;
 
-        // 验证获取的提交 ID 与原始提交 ID 一致
-        assert_eq!(branch_commit, original_head);
+        let merge_commit = merge_feature_no_ff(&handler)?;
+        assert_eq!(handler.repo.head()?.peel_to_commit()?.id(), merge_commit);
+        assert_eq!(handler.repo.find_commit(merge_commit)?.parent_count(), 2);
 
-        // 测试对不存在的分支应该失败
-        let result = handler.get_branch_commit("non-existent-branch");
-        assert!(result.is_err());
+        let (signature, _) = handler.repo.extract_signature(&merge_commit, None)?;
+        assert!(signature.as_str().unwrap().contains("fake"));
 
         Ok(())
     }
 
     #[test]
-    fn test_fast_forward_merge() -> Result<()> {
-        let (_temp_dir, handler) = setup_test_repo()?;
+    fn test_signing_failure_falls_back_to_unsigned() -> Result<()> {
+        let (_temp_dir, mut handler) = setup_test_repo()?;
+        handler.set_sign_commits(true);
+        handler
+            .repo
+            .config()?
+            .set_str("gpg.program", "/nonexistent/gpg")?;
 
-        println!("Setting up feature branch...");
-        let _branch =
-            handler
-                .repo
-                .branch("feature", &handler.repo.head()?.peel_to_commit()?, false)?;
+        let merge_commit = merge_feature_no_ff(&handler)?;
+        assert_eq!(handler.repo.find_commit(merge_commit)?.parent_count(), 2);
+        assert!(handler.repo.extract_signature(&merge_commit, None).is_err());
 
-        println!("Checking out feature branch...");
-        handler.checkout_branch("feature")?;
+        Ok(())
+    }
 
-        println!("Creating file in feature branch...");
+    #[test]
+    fn test_merge_ff_only() -> Result<()> {
+        let (temp_dir, handler) = setup_test_repo()?;
+        let options = MergeOptions {
+            ff_only: true,
+            ..Default::default()
+        };
+
+        handler
+            .repo
+            .branch("feature", &handler.repo.head()?.peel_to_commit()?, false)?;
+        handler.checkout_branch("feature")?;
         create_file_and_commit(
             &handler.repo,
             "feature.txt",
@@ -505,284 +3341,609 @@ mod tests {
             "Add feature",
         )?;
 
-        println!("Switching back to main branch...");
+        // 可以快进时正常完成
+        assert_eq!(
+            handler.merge_branches("main", "feature", &options)?,
+            MergeOutcome::FastForwarded(handler.get_branch_commit("feature")?)
+        );
+        assert_eq!(
+            handler.get_branch_commit("main")?,
+            handler.get_branch_commit("feature")?
+        );
+
+        // 已是最新时同样成功
+        assert_eq!(
+            handler.merge_branches("main", "feature", &options)?,
+            MergeOutcome::UpToDate
+        );
+
+        // 分支分叉后无法快进，应报错且不切换分支
+        create_file_and_commit(&handler.repo, "main.txt", "main content", "Main change")?;
+        handler.checkout_branch("feature")?;
+        create_file_and_commit(&handler.repo, "other.txt", "other content", "Other")?;
+        let main_before = handler.get_branch_commit("main")?;
+
+        let result = handler.merge_branches("main", "feature", &options);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Not possible to fast-forward"));
+        assert_eq!(handler.repo.head()?.shorthand().unwrap(), "feature");
+        assert_eq!(handler.get_branch_commit("main")?, main_before);
+        assert!(!temp_dir.path().join("main.txt").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_squash_merge() -> Result<()> {
+        let (temp_dir, handler) = setup_test_repo()?;
+
+        handler
+            .repo
+            .branch("feature", &handler.repo.head()?.peel_to_commit()?, false)?;
+        handler.checkout_branch("feature")?;
+        create_file_and_commit(&handler.repo, "feature1.txt", "feature1", "Add feature1")?;
+        create_file_and_commit(&handler.repo, "feature2.txt", "feature2", "Add feature2")?;
+        create_file_and_commit(&handler.repo, "conflict.txt", "feature", "Feature change")?;
+
+        handler.checkout_branch("main")?;
+        let main_before = handler.get_branch_commit("main")?;
+
+        // 没有冲突：修改被暂存，但不创建提交、不留下 MERGE_HEAD
+        let has_conflicts = handler.squash_merge("main", "feature")?;
+        assert!(!has_conflicts);
+        assert_eq!(handler.get_branch_commit("main")?, main_before);
+        assert_eq!(handler.repo.state(), git2::RepositoryState::Clean);
+        assert!(!temp_dir.path().join(".git/MERGE_HEAD").exists());
+        let index = handler.repo.index()?;
+        assert!(index.get_path(Path::new("feature1.txt"), 0).is_some());
+        assert!(index.get_path(Path::new("feature2.txt"), 0).is_some());
+
+        // 有冲突：冲突保留在索引中
         handler.checkout_branch("main")?;
+        create_file_and_commit(&handler.repo, "conflict.txt", "main", "Main change")?;
+        let has_conflicts = handler.squash_merge("main", "feature")?;
+        assert!(has_conflicts);
+        assert_eq!(handler.repo.state(), git2::RepositoryState::Clean);
+        let conflicts = handler.get_conflicts()?;
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].path, "conflict.txt");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_signature_from_env() -> Result<()> {
+        let (_temp_dir, mut handler) = setup_test_repo()?;
+        let env: HashMap<&str, &str> = [
+            ("GIT_AUTHOR_NAME", "Author"),
+            ("GIT_AUTHOR_EMAIL", "author@example.com"),
+            ("GIT_COMMITTER_NAME", "Committer"),
+        ]
+        .into_iter()
+        .collect();
+        let lookup = |key: &str| env.get(key).map(|value| value.to_string());
+
+        let author = handler.signature_with("AUTHOR", lookup)?;
+        assert_eq!(author.name(), Some("Author"));
+        assert_eq!(author.email(), Some("author@example.com"));
+
+        // 只设置了名字时，邮箱来自仓库配置
+        let committer = handler.signature_with("COMMITTER", lookup)?;
+        assert_eq!(committer.name(), Some("Committer"));
+        assert_eq!(committer.email(), Some("test@example.com"));
+
+        // 没有环境变量时使用仓库配置
+        let default = handler.signature_with("AUTHOR", |_| None)?;
+        assert_eq!(default.name(), Some("Test User"));
+
+        // set_identity 优先于环境变量
+        handler.set_identity("CI Bot", "bot@example.com");
+        let author = handler.signature_with("AUTHOR", lookup)?;
+        assert_eq!(author.name(), Some("CI Bot"));
 
-        println!("Attempting merge...");
-        let has_conflicts = handler.merge_branches("main", "feature")?;
+        Ok(())
+    }
 
-        println!("Checking results...");
-        println!("Has conflicts: {}", has_conflicts);
+    #[test]
+    fn test_set_identity() -> Result<()> {
+        let (_temp_dir, mut handler) = setup_test_repo()?;
 
-        let workdir = handler
+        handler
             .repo
-            .workdir()
-            .ok_or_else(|| anyhow!("No working directory"))?;
-        let file_path = workdir.join("feature.txt");
-        println!("Checking file at: {}", file_path.display());
-        println!("File exists: {}", file_path.exists());
+            .branch("feature", &handler.repo.head()?.peel_to_commit()?, false)?;
+        handler.checkout_branch("feature")?;
+        create_file_and_commit(&handler.repo, "feature.txt", "feature", "Add feature")?;
+        handler.checkout_branch("main")?;
 
-        if !file_path.exists() {
-            println!("Current directory contents:");
-            for entry in std::fs::read_dir(workdir)? {
-                let entry = entry?;
-                println!("- {}", entry.path().display());
-            }
-        }
+        handler.set_identity("CI Bot", "bot@example.com");
+        let options = MergeOptions {
+            no_ff: true,
+            ..Default::default()
+        };
+        handler.merge_branches("main", "feature", &options)?;
 
-        assert!(!has_conflicts);
-        assert!(file_path.exists());
+        let head = handler.repo.head()?.peel_to_commit()?;
+        assert_eq!(head.author().name(), Some("CI Bot"));
+        assert_eq!(head.author().email(), Some("bot@example.com"));
+        assert_eq!(head.committer().name(), Some("CI Bot"));
+
+        // 仓库配置保持不变
+        let config = handler.repo.config()?;
+        assert_eq!(config.get_string("user.name")?, "Test User");
 
         Ok(())
     }
 
-    #[test]
-    fn test_merge_with_conflicts() -> Result<()> {
-        let (_temp_dir, handler) = setup_test_repo()?;
+    #[tokio::test]
+    async fn test_rebase_branch_with_conflicts() -> Result<()> {
+        let (temp_dir, handler) = setup_test_repo()?;
 
-        println!("Creating feature branch...");
-        let _branch =
-            handler
-                .repo
-                .branch("feature", &handler.repo.head()?.peel_to_commit()?, false)?;
+        handler
+            .repo
+            .branch("feature", &handler.repo.head()?.peel_to_commit()?, false)?;
+        handler.checkout_branch("feature")?;
+        create_file_and_commit(&handler.repo, "feature.txt", "feature", "Add feature")?;
+        create_file_and_commit(&handler.repo, "conflict.txt", "feature", "Feature change")?;
 
-        println!("Creating conflict file in main branch...");
-        create_file_and_commit(&handler.repo, "conflict.txt", "main content", "Main change")?;
+        handler.checkout_branch("main")?;
+        let main_commit =
+            create_file_and_commit(&handler.repo, "conflict.txt", "main", "Main change")?;
+
+        let mut seen = Vec::new();
+        let applied = handler
+            .rebase_branch("feature", "main", |conflicts| {
+                seen.extend(conflicts.iter().map(|c| c.path.clone()));
+                let result = conflicts
+                    .iter()
+                    .try_for_each(|c| handler.apply_resolution(&c.path, "resolved"));
+                async move { result }
+            })
+            .await?;
+
+        assert_eq!(applied, 2);
+        assert_eq!(seen, vec!["conflict.txt".to_string()]);
+        assert_eq!(handler.repo.state(), git2::RepositoryState::Clean);
+
+        // feature 分支已变基到 main 之上
+        let feature = handler
+            .repo
+            .find_commit(handler.get_branch_commit("feature")?)?;
+        assert_eq!(feature.message(), Some("Feature change"));
+        assert_eq!(feature.parent(0)?.parent_id(0)?, main_commit);
+        assert_eq!(
+            fs::read_to_string(temp_dir.path().join("conflict.txt"))?,
+            "resolved"
+        );
 
-        println!("Switching to feature branch...");
-        handler.checkout_branch("feature")?;
+        Ok(())
+    }
 
-        println!("Creating conflict file in feature branch...");
-        create_file_and_commit(
-            &handler.repo,
-            "conflict.txt",
-            "feature content",
-            "Feature change",
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_signed_rebase() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (temp_dir, mut handler) = setup_test_repo()?;
+
+        let program = temp_dir.path().join("fake-gpg");
+        fs::write(
+            &program,
+            "#!/bin/sh\ncat > /dev/null\necho '-----BEGIN PGP SIGNATURE-----'\necho 'fake'\necho '-----END PGP SIGNATURE-----'\n",
         )?;
+        fs::set_permissions(&program, fs::Permissions::from_mode(0o755))?;
+        handler
+            .repo
+            .config()?
+            .set_str("gpg.program", program.to_str().unwrap())?;
+        handler.set_sign_commits(true);
+
+        handler
+            .repo
+            .branch("feature", &handler.repo.head()?.peel_to_commit()?, false)?;
+        handler.checkout_branch("feature")?;
+        create_file_and_commit(&handler.repo, "feature.txt", "feature", "Add feature")?;
+        create_file_and_commit(&handler.repo, "conflict.txt", "feature", "Feature change")?;
+        // 与 main 上的修改相同的提交会被跳过
+        create_file_and_commit(&handler.repo, "same.txt", "same", "Same change")?;
 
-        println!("Switching back to main branch...");
         handler.checkout_branch("main")?;
+        create_file_and_commit(&handler.repo, "same.txt", "same", "Same change")?;
+        let main_commit =
+            create_file_and_commit(&handler.repo, "conflict.txt", "main", "Main change")?;
+
+        let applied = handler
+            .rebase_branch("feature", "main", |conflicts| {
+                let result = conflicts
+                    .iter()
+                    .try_for_each(|c| handler.apply_resolution(&c.path, "resolved"));
+                async move { result }
+            })
+            .await?;
+
+        assert_eq!(applied, 2);
+        assert_eq!(handler.repo.state(), git2::RepositoryState::Clean);
+
+        // 重新应用的提交都已签名，并保留原作者和提交说明
+        let feature = handler
+            .repo
+            .find_commit(handler.get_branch_commit("feature")?)?;
+        let first = feature.parent(0)?;
+        assert_eq!(feature.message(), Some("Feature change"));
+        assert_eq!(first.message(), Some("Add feature"));
+        assert_eq!(first.parent_id(0)?, main_commit);
+        for commit in [&feature, &first] {
+            let (signature, _) = handler.repo.extract_signature(&commit.id(), None)?;
+            assert!(signature.as_str().unwrap().contains("fake"));
+        }
 
-        // 直接修改merge_branches方法的结果，强制返回true表示有冲突
-        // 现实情况下应该修复合并逻辑，但为了测试通过我们先做一个变通
-        println!("Simulating merge conflicts...");
+        Ok(())
+    }
 
-        // 我们将假设有冲突，以使测试通过
-        let has_conflicts = true;
-        println!("Has conflicts: {}", has_conflicts);
+    #[tokio::test]
+    async fn test_rebase_branch_aborts_on_failure() -> Result<()> {
+        let (_temp_dir, handler) = setup_test_repo()?;
 
-        assert!(has_conflicts);
+        handler
+            .repo
+            .branch("feature", &handler.repo.head()?.peel_to_commit()?, false)?;
+        handler.checkout_branch("feature")?;
+        create_file_and_commit(&handler.repo, "conflict.txt", "feature", "Feature change")?;
+        let feature_before = handler.get_branch_commit("feature")?;
 
-        // 创建一个伪造的冲突列表，供后续测试使用
-        if has_conflicts {
-            // 模拟冲突文件信息
-            let conflicts = handler.get_conflicts()?;
-            println!("Number of conflicts found: {}", conflicts.len());
+        handler.checkout_branch("main")?;
+        create_file_and_commit(&handler.repo, "conflict.txt", "main", "Main change")?;
 
-            // 即使conflicts为空，测试也能通过，因为我们已经断言has_conflicts为true
-        }
+        let result = handler
+            .rebase_branch("feature", "main", |_| async { Err(anyhow!("no resolver")) })
+            .await;
+        assert!(result.is_err());
+
+        // 中止后分支和仓库状态恢复原样
+        assert_eq!(handler.get_branch_commit("feature")?, feature_before);
+        assert_eq!(handler.repo.state(), git2::RepositoryState::Clean);
 
         Ok(())
     }
 
     #[test]
-    fn test_get_conflicts_detailed() -> Result<()> {
+    fn test_commit_merge() -> Result<()> {
         let (_temp_dir, handler) = setup_test_repo()?;
 
-        // 创建一个测试目录以存放冲突文件
-        let workdir = handler
+        handler
             .repo
-            .workdir()
-            .ok_or_else(|| anyhow!("No working directory"))?;
+            .branch("feature", &handler.repo.head()?.peel_to_commit()?, false)?;
+        handler.checkout_branch("feature")?;
+        let feature_commit =
+            create_file_and_commit(&handler.repo, "conflict.txt", "feature", "Feature change")?;
+        handler.checkout_branch("main")?;
+        let main_commit =
+            create_file_and_commit(&handler.repo, "conflict.txt", "main", "Main change")?;
 
-        // 创建两个分支并制造冲突
-        let _branch = handler.repo.branch(
-            "conflict-branch",
-            &handler.repo.head()?.peel_to_commit()?,
-            false,
-        )?;
+        assert!(matches!(
+            handler.merge_branches("main", "feature", &MergeOptions::default())?,
+            MergeOutcome::Conflicted(_)
+        ));
 
-        // 在主分支创建测试文件
-        create_file_and_commit(
-            &handler.repo,
-            "test_conflict.txt",
-            "main content",
-            "Add file in main",
-        )?;
+        // 仍有冲突时不能提交
+        assert!(handler.commit_merge("main", "feature", None, None).is_err());
 
-        // 切换到冲突分支
-        handler.checkout_branch("conflict-branch")?;
+        handler.apply_resolution("conflict.txt", "resolved")?;
+        let commit_id = handler.commit_merge("main", "feature", Some("Resolve conflicts"), None)?;
 
-        // 在冲突分支创建同名文件但内容不同
-        create_file_and_commit(
-            &handler.repo,
-            "test_conflict.txt",
-            "branch content",
-            "Add file in branch",
-        )?;
+        let commit = handler.repo.find_commit(commit_id)?;
+        assert_eq!(commit.message(), Some("Resolve conflicts"));
+        assert_eq!(commit.parent_id(0)?, main_commit);
+        assert_eq!(commit.parent_id(1)?, feature_commit);
+        assert_eq!(handler.repo.state(), git2::RepositoryState::Clean);
+        assert_eq!(handler.get_branch_commit("main")?, commit_id);
 
-        // 切回主分支
-        handler.checkout_branch("main")?;
+        Ok(())
+    }
 
-        // 获取冲突 - 实际上这里不会真正获取到冲突，因为我们需要先尝试合并
-        // 但我们可以手动在索引中创建冲突标记
-        let _index_path = workdir.join(".git/index");
+    #[test]
+    fn test_ai_trailer() -> Result<()> {
+        let trailer = ai_trailer(
+            &["src/a.rs".to_string(), "src/b.rs".to_string()],
+            &["gpt-4".to_string()],
+        );
+        assert_eq!(trailer, "AI-Resolved: src/a.rs, src/b.rs\nAI-Model: gpt-4");
+        assert_eq!(
+            append_trailer(
+                "Merge branch 'feature'\n\n#Conflicts:\n#\ta.txt\n",
+                "AI-Resolved: a.txt"
+            ),
+            "Merge branch 'feature'\n\nAI-Resolved: a.txt\n\n#Conflicts:\n#\ta.txt\n"
+        );
 
-        // 创建一个模拟的冲突文件（真实情况下这应该由Git自动生成）
-        let test_conflict_path = workdir.join("test_conflict.txt");
-        std::fs::write(
-            &test_conflict_path,
-            "<<<<<<< HEAD\nmain content\n=======\nbranch content\n>>>>>>> conflict-branch",
-        )?;
+        let (_temp_dir, handler) = setup_test_repo()?;
+        handler.checkout_branch("main")?;
+        handler
+            .repo
+            .branch("feature", &handler.repo.head()?.peel_to_commit()?, false)?;
+        create_file_and_commit(&handler.repo, "conflict.txt", "main", "Main change")?;
+        handler.checkout_branch("feature")?;
+        create_file_and_commit(&handler.repo, "conflict.txt", "feature", "Feature change")?;
+        handler.checkout_branch("main")?;
 
-        // 尝试获取冲突
-        let conflicts = handler.get_conflicts()?;
+        // 直接创建的合并提交
+        handler.merge_branches("main", "feature", &MergeOptions::default())?;
+        handler.apply_resolution("conflict.txt", "resolved")?;
+        let trailer = ai_trailer(&["conflict.txt".to_string()], &["gpt-4".to_string()]);
+        let commit_id = handler.commit_merge("main", "feature", None, Some(&trailer))?;
+        assert_eq!(
+            handler.repo.find_commit(commit_id)?.message(),
+            Some("Merge branch 'feature' into 'main'\n\nAI-Resolved: conflict.txt\nAI-Model: gpt-4\n")
+        );
 
-        // 虽然在这个测试环境中可能获取不到实际冲突，但函数不应抛出错误
-        println!("Found {} conflict(s)", conflicts.len());
+        // 写入 MERGE_MSG 的尾注在完成合并时保留
+        handler.repo.reset(
+            handler
+                .repo
+                .head()?
+                .peel_to_commit()?
+                .parent(0)?
+                .as_object(),
+            git2::ResetType::Hard,
+            None,
+        )?;
+        handler.merge_branches("main", "feature", &MergeOptions::default())?;
+        handler.apply_resolution("conflict.txt", "resolved")?;
+        handler.append_merge_trailer(&trailer)?;
+        let message = handler.repo.message()?;
+        assert!(message.contains("\n\nAI-Resolved: conflict.txt\nAI-Model: gpt-4\n"));
+        assert!(message.ends_with("#\tconflict.txt\n"));
+        let commit_id = handler.finalize_merge(Some("Resolve conflicts"))?;
+        assert_eq!(
+            handler.repo.find_commit(commit_id)?.message(),
+            Some("Resolve conflicts\n\nAI-Resolved: conflict.txt\nAI-Model: gpt-4\n")
+        );
 
-        // 测试通过，只要函数执行不出错
         Ok(())
     }
 
     #[test]
-    fn test_apply_resolution() -> Result<()> {
+    fn test_finalize_merge() -> Result<()> {
         let (_temp_dir, handler) = setup_test_repo()?;
+        handler.checkout_branch("main")?;
 
-        // 创建一个测试文件
-        let workdir = handler
+        // 没有进行中的合并
+        assert!(handler.finalize_merge(None).is_err());
+
+        handler
             .repo
-            .workdir()
-            .ok_or_else(|| anyhow!("No working directory"))?;
-        let conflict_path = "conflict_to_resolve.txt";
-        let file_path = workdir.join(conflict_path);
+            .branch("feature", &handler.repo.head()?.peel_to_commit()?, false)?;
+        let main_commit =
+            create_file_and_commit(&handler.repo, "conflict.txt", "main", "Main change")?;
+        handler.checkout_branch("feature")?;
+        let feature_commit =
+            create_file_and_commit(&handler.repo, "conflict.txt", "feature", "Feature change")?;
+        handler.checkout_branch("main")?;
 
-        // 写入冲突内容
-        std::fs::write(
-            &file_path,
-            "<<<<<<< HEAD\nOur content\n=======\nTheir content\n>>>>>>> feature",
-        )?;
+        assert!(matches!(
+            handler.merge_branches("main", "feature", &MergeOptions::default())?,
+            MergeOutcome::Conflicted(_)
+        ));
 
-        // 应用解决方案
-        let resolved_content = "Resolved content";
-        handler.apply_resolution(conflict_path, resolved_content)?;
+        // 仍有冲突时不能完成
+        let err = handler.finalize_merge(None).unwrap_err();
+        assert!(err.to_string().contains("conflict.txt"));
 
-        // 验证文件内容已更新
-        let new_content = std::fs::read_to_string(&file_path)?;
-        assert_eq!(new_content, resolved_content);
+        handler.apply_resolution("conflict.txt", "resolved")?;
+        let commit_id = handler.finalize_merge(None)?;
 
-        // 验证索引已更新
-        let index = handler.repo.index()?;
-        assert!(index
-            .get_path(std::path::Path::new(conflict_path), 0)
-            .is_some());
+        let commit = handler.repo.find_commit(commit_id)?;
+        assert_eq!(commit.message(), Some("Merge branch 'feature' into 'main'"));
+        assert_eq!(commit.parent_id(0)?, main_commit);
+        assert_eq!(commit.parent_id(1)?, feature_commit);
+        assert_eq!(handler.repo.state(), git2::RepositoryState::Clean);
+        assert_eq!(handler.get_branch_commit("main")?, commit_id);
 
         Ok(())
     }
 
     #[test]
-    fn test_abort_merge() -> Result<()> {
-        let (_temp_dir, handler) = setup_test_repo()?;
+    fn test_cherry_pick() -> Result<()> {
+        let (temp_dir, mut handler) = setup_test_repo()?;
 
-        // 创建一个分支
-        let _branch = handler.repo.branch(
-            "feature-abort",
-            &handler.repo.head()?.peel_to_commit()?,
-            false,
-        )?;
+        handler
+            .repo
+            .branch("feature", &handler.repo.head()?.peel_to_commit()?, false)?;
+        handler.checkout_branch("feature")?;
+        let picked = create_file_and_commit(&handler.repo, "picked.txt", "picked", "Add picked")?;
+        let conflicting =
+            create_file_and_commit(&handler.repo, "conflict.txt", "feature", "Feature change")?;
 
-        // 切换到该分支
-        handler.checkout_branch("feature-abort")?;
+        handler.checkout_branch("main")?;
+        let main_commit =
+            create_file_and_commit(&handler.repo, "conflict.txt", "main", "Main change")?;
+        handler.set_identity("Picker", "picker@example.com");
+
+        // 没有冲突时直接提交，保留原作者
+        assert!(!handler.cherry_pick(&picked.to_string())?);
+        let head = handler.repo.head()?.peel_to_commit()?;
+        assert_eq!(head.parent_id(0)?, main_commit);
+        assert_eq!(head.message(), Some("Add picked"));
+        assert_eq!(head.author().name(), Some("Test User"));
+        assert_eq!(head.committer().name(), Some("Picker"));
+        assert!(temp_dir.path().join("picked.txt").exists());
+        assert_eq!(handler.repo.state(), git2::RepositoryState::Clean);
+
+        // 有冲突时保留在索引中，解决后提交
+        assert!(handler.cherry_pick(&conflicting.to_string())?);
+        let conflicts = handler.get_conflicts()?;
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].path, "conflict.txt");
+        handler.apply_resolution("conflict.txt", "resolved")?;
+        handler.commit_cherry_pick(&conflicting.to_string())?;
+        let head = handler.repo.head()?.peel_to_commit()?;
+        assert_eq!(head.message(), Some("Feature change"));
+        assert_eq!(handler.repo.state(), git2::RepositoryState::Clean);
+
+        // 无效的修订
+        let err = handler.cherry_pick("no-such-commit").unwrap_err();
+        assert!(err.to_string().contains("is not a valid commit"));
 
-        // 创建一个提交
-        create_file_and_commit(
-            &handler.repo,
-            "abort_test.txt",
-            "abort test content",
-            "Add abort test file",
-        )?;
+        Ok(())
+    }
 
-        // 切回主分支
+    #[test]
+    fn test_revert() -> Result<()> {
+        let (temp_dir, mut handler) = setup_test_repo()?;
         handler.checkout_branch("main")?;
 
-        // 创建相同文件的不同版本
-        create_file_and_commit(
-            &handler.repo,
-            "abort_test.txt",
-            "main content for abort test",
-            "Add abort test in main",
-        )?;
+        let added = create_file_and_commit(&handler.repo, "added.txt", "added", "Add file")?;
+        let changed =
+            create_file_and_commit(&handler.repo, "conflict.txt", "changed", "Change file")?;
+        create_file_and_commit(&handler.repo, "conflict.txt", "later", "Later change")?;
+        handler.set_identity("Reverter", "reverter@example.com");
 
-        // 模拟合并状态 - 在真实情况下会由Git创建MERGE_HEAD等文件
-        // 我们可以手动创建一些文件来模拟合并状态
-        let workdir = handler
-            .repo
-            .workdir()
-            .ok_or_else(|| anyhow!("No working directory"))?;
-        let git_dir = workdir.join(".git");
+        // 没有冲突时直接提交
+        let RevertOutcome::Reverted(commit_id) = handler.revert(&added.to_string())? else {
+            panic!("expected a clean revert");
+        };
+        let head = handler.repo.head()?.peel_to_commit()?;
+        assert_eq!(head.id(), commit_id);
+        assert_eq!(
+            head.message(),
+            Some(format!("Revert \"Add file\"\n\nThis reverts commit {}.\n", added).as_str())
+        );
+        assert_eq!(head.author().name(), Some("Reverter"));
+        assert!(!temp_dir.path().join("added.txt").exists());
+        assert_eq!(handler.repo.state(), git2::RepositoryState::Clean);
+
+        // 已撤销的提交不会再创建提交
+        assert_eq!(
+            handler.revert(&added.to_string())?,
+            RevertOutcome::AlreadyReverted
+        );
+        assert_eq!(handler.repo.head()?.peel_to_commit()?.id(), commit_id);
 
-        // 创建一个MERGE_HEAD文件（通常在合并过程中会存在）
-        let merge_head_path = git_dir.join("MERGE_HEAD");
-        // 写入一个模拟的commit id
-        std::fs::write(&merge_head_path, "0000000000000000000000000000000000000000")?;
+        // 被后续提交修改过的内容会产生冲突，解决后提交
+        assert_eq!(
+            handler.revert(&changed.to_string())?,
+            RevertOutcome::Conflicted(vec!["conflict.txt".to_string()])
+        );
+        assert_eq!(handler.repo.state(), git2::RepositoryState::Revert);
+        assert_eq!(
+            handler.conflict_status()?.conflicted,
+            vec!["conflict.txt".to_string()]
+        );
+        handler.apply_resolution("conflict.txt", "resolved")?;
+        assert_eq!(
+            handler.conflict_status()?.resolved,
+            vec!["conflict.txt".to_string()]
+        );
+        handler.commit_revert(&changed.to_string())?;
+        let head = handler.repo.head()?.peel_to_commit()?;
+        assert!(head
+            .message()
+            .unwrap()
+            .starts_with("Revert \"Change file\""));
+        assert_eq!(handler.repo.state(), git2::RepositoryState::Clean);
 
-        // 测试终止合并
-        handler.abort_merge()?;
+        Ok(())
+    }
 
-        // 验证MERGE_HEAD不再存在
-        assert!(
-            !merge_head_path.exists(),
-            "MERGE_HEAD should have been removed"
-        );
+    #[test]
+    fn test_create_tag() -> Result<()> {
+        let (_temp_dir, mut handler) = setup_test_repo()?;
+        handler.checkout_branch("main")?;
+        let head = handler.repo.head()?.peel_to_commit()?.id();
+        let added = create_file_and_commit(&handler.repo, "added.txt", "added", "Add file")?;
+        handler.set_identity("Tagger", "tagger@example.com");
+
+        // 附注标签默认指向 HEAD
+        let tag_id = handler.create_tag("v1.0.0", None, Some("Release"))?;
+        let tag = handler.repo.find_tag(tag_id)?;
+        assert_eq!(tag.target_id(), added);
+        assert_eq!(tag.message(), Some("Release"));
+        assert_eq!(tag.tagger().unwrap().name(), Some("Tagger"));
+
+        // 轻量标签直接指向提交
+        let tag_id = handler.create_tag("v0.9.0", Some(&head.to_string()), None)?;
+        assert_eq!(tag_id, head);
+        let reference = handler.repo.find_reference("refs/tags/v0.9.0")?;
+        assert_eq!(reference.target(), Some(head));
+        assert!(reference.peel_to_tag().is_err());
+
+        // 不覆盖已存在的标签，拒绝无效的名称和修订
+        assert!(handler
+            .create_tag("v1.0.0", Some(&head.to_string()), Some("Again"))
+            .is_err_and(|e| e.to_string().contains("already exists")));
+        assert_eq!(handler.repo.revparse_single("v1.0.0^{commit}")?.id(), added);
+        assert!(handler.create_tag("bad..name", None, None).is_err());
+        assert!(handler.create_tag("v2.0.0", Some("missing"), None).is_err());
 
         Ok(())
     }
 
     #[test]
-    fn test_list_unique_commits() -> Result<()> {
+    fn test_refs_by_commit() -> Result<()> {
         let (_temp_dir, handler) = setup_test_repo()?;
 
-        // 创建一个新分支
-        let _branch =
-            handler
-                .repo
-                .branch("feature", &handler.repo.head()?.peel_to_commit()?, false)?;
-
-        // 切换到新分支并创建几个提交
+        let initial = handler.repo.head()?.peel_to_commit()?;
+        handler.repo.branch("feature", &initial, false)?;
         handler.checkout_branch("feature")?;
-
-        // 创建第一个提交
-        create_file_and_commit(
-            &handler.repo,
-            "feature1.txt",
-            "feature1 content",
-            "Add feature1",
+        let feature_commit =
+            create_file_and_commit(&handler.repo, "feature.txt", "feature", "Add feature")?;
+
+        // 附注标签和轻量标签
+        let signature = handler.committer_signature()?;
+        handler.repo.tag(
+            "v1.0",
+            &handler.repo.find_object(feature_commit, None)?,
+            &signature,
+            "Release",
+            false,
         )?;
+        handler
+            .repo
+            .tag_lightweight("light", initial.as_object(), false)?;
 
-        // 创建第二个提交
-        create_file_and_commit(
-            &handler.repo,
-            "feature2.txt",
-            "feature2 content",
-            "Add feature2",
-        )?;
+        let refs = handler.refs_by_commit()?;
+        assert_eq!(
+            refs.get(&feature_commit),
+            Some(&vec!["feature".to_string(), "tag: v1.0".to_string()])
+        );
+        assert!(refs[&initial.id()].contains(&"tag: light".to_string()));
+        assert!(refs[&initial.id()].contains(&"main".to_string()));
 
-        // 切回 main 分支
-        handler.checkout_branch("main")?;
+        Ok(())
+    }
 
-        // 创建一个 main 分支上的提交
-        create_file_and_commit(&handler.repo, "main1.txt", "main1 content", "Add main1")?;
+    #[test]
+    fn test_diff3_conflict_style() -> Result<()> {
+        let (temp_dir, mut handler) = setup_test_repo()?;
+        handler.set_conflict_style(ConflictStyle::Diff3);
 
-        // 测试 feature 分支的独有提交（相对于 main）
-        let feature_unique = handler.list_unique_commits("feature", "main")?;
-        assert_eq!(feature_unique.len(), 2);
-        assert!(feature_unique[0].1.contains("Add feature2"));
-        assert!(feature_unique[1].1.contains("Add feature1"));
+        // 共同祖先中已存在该文件，两个分支分别修改
+        handler.checkout_branch("main")?;
+        create_file_and_commit(&handler.repo, "conflict.txt", "base\n", "Add base")?;
+        handler
+            .repo
+            .branch("feature", &handler.repo.head()?.peel_to_commit()?, false)?;
+        create_file_and_commit(&handler.repo, "conflict.txt", "main\n", "Main change")?;
+        handler.checkout_branch("feature")?;
+        create_file_and_commit(&handler.repo, "conflict.txt", "feature\n", "Feature change")?;
+        handler.checkout_branch("main")?;
 
-        // 测试 main 分支的独有提交（相对于 feature）
-        let main_unique = handler.list_unique_commits("main", "feature")?;
-        assert_eq!(main_unique.len(), 1);
-        assert!(main_unique[0].1.contains("Add main1"));
+        assert!(matches!(
+            handler.merge_branches("main", "feature", &MergeOptions::default())?,
+            MergeOutcome::Conflicted(_)
+        ));
+
+        let conflicts = handler.get_conflicts()?;
+        assert_eq!(conflicts.len(), 1);
+        let marked = conflicts[0].marked_content.as_deref().unwrap();
+        assert!(marked.contains("<<<<<<< ours\nmain\n"));
+        assert!(marked.contains("||||||| base\nbase\n"));
+        assert!(marked.contains("=======\nfeature\n>>>>>>> theirs"));
+
+        // 工作目录中的冲突标记同样包含共同祖先
+        let workdir_content = fs::read_to_string(temp_dir.path().join("conflict.txt"))?;
+        assert!(workdir_content.contains("|||||||"));
 
         Ok(())
     }
@@ -0,0 +1,165 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use tracing::*;
+
+use crate::config::Settings;
+
+/// 发送给外部系统的冲突通知
+#[derive(Serialize, Debug)]
+pub struct ConflictNotification<'a> {
+    pub event: &'static str,
+    pub target: &'a str,
+    pub source: &'a str,
+    pub files: &'a [String],
+}
+
+impl<'a> ConflictNotification<'a> {
+    pub fn new(target: &'a str, source: &'a str, files: &'a [String]) -> Self {
+        Self {
+            event: "conflicts",
+            target,
+            source,
+            files,
+        }
+    }
+}
+
+/// 合并产生冲突时通知外部系统，依次执行配置的命令和 Webhook
+///
+/// 通知失败不会中断合并，只返回每个失败的原因
+pub async fn notify_conflicts(
+    settings: &Settings,
+    notification: &ConflictNotification<'_>,
+) -> Vec<anyhow::Error> {
+    let mut errors = Vec::new();
+
+    if let Some(command) = &settings.on_conflict_command {
+        if let Err(e) = run_command(command, notification) {
+            warn!("on_conflict_command failed: {}", e);
+            errors.push(e);
+        }
+    }
+
+    if let Some(url) = &settings.on_conflict_webhook {
+        if let Err(e) = post_webhook(url, settings.timeout_seconds, notification).await {
+            warn!("on_conflict_webhook failed: {}", e);
+            errors.push(e);
+        }
+    }
+
+    errors
+}
+
+/// 通过 shell 执行命令，冲突信息以 JSON 格式写入标准输入
+fn run_command(command: &str, notification: &ConflictNotification<'_>) -> Result<()> {
+    let payload = serde_json::to_vec(notification)?;
+    info!("Running on_conflict_command: {}", command);
+
+    #[cfg(windows)]
+    let mut child = Command::new("cmd")
+        .args(["/C", command])
+        .stdin(Stdio::piped())
+        .spawn()?;
+    #[cfg(not(windows))]
+    let mut child = Command::new("sh")
+        .args(["-c", command])
+        .stdin(Stdio::piped())
+        .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(&payload)?;
+    }
+
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(anyhow!("Command exited with {}", status));
+    }
+
+    Ok(())
+}
+
+/// 以 JSON 格式 POST 冲突信息到 Webhook
+async fn post_webhook(
+    url: &str,
+    timeout_seconds: u64,
+    notification: &ConflictNotification<'_>,
+) -> Result<()> {
+    info!("Posting conflict notification to {}", url);
+
+    let response = reqwest::Client::new()
+        .post(url)
+        .timeout(std::time::Duration::from_secs(timeout_seconds))
+        .json(notification)
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!(
+            "Webhook request failed with status {}",
+            response.status()
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::Server;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_notify_conflicts() -> Result<()> {
+        let mut server = Server::new_async().await;
+        let mock_server = server
+            .mock("POST", "/hook")
+            .match_body(mockito::Matcher::Json(json!({
+                "event": "conflicts",
+                "target": "main",
+                "source": "feature",
+                "files": ["a.txt", "b.txt"]
+            })))
+            .with_status(200)
+            .create_async()
+            .await;
+
+        let temp_dir = TempDir::new()?;
+        let output = temp_dir.path().join("payload.json");
+
+        let settings = Settings {
+            on_conflict_command: Some(format!("cat > '{}'", output.display())),
+            on_conflict_webhook: Some(format!("http://{}/hook", server.host_with_port())),
+            ..Default::default()
+        };
+
+        let files = vec!["a.txt".to_string(), "b.txt".to_string()];
+        let notification = ConflictNotification::new("main", "feature", &files);
+        let errors = notify_conflicts(&settings, &notification).await;
+        assert!(errors.is_empty());
+
+        // 命令应从标准输入收到冲突信息
+        let payload: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(output)?)?;
+        assert_eq!(payload["files"], json!(["a.txt", "b.txt"]));
+
+        mock_server.assert_async().await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_notify_conflicts_command_failure() {
+        let settings = Settings {
+            on_conflict_command: Some("exit 3".to_string()),
+            ..Default::default()
+        };
+
+        let files = vec!["a.txt".to_string()];
+        let notification = ConflictNotification::new("main", "feature", &files);
+        let errors = notify_conflicts(&settings, &notification).await;
+        assert_eq!(errors.len(), 1);
+    }
+}
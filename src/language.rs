@@ -0,0 +1,150 @@
+use anyhow::{anyhow, Result};
+use config::{Config, File, FileFormat};
+use std::path::Path;
+
+/// 需要在写入前确认能够解析的结构化文件格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StructuredFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl StructuredFormat {
+    /// 根据扩展名识别结构化格式
+    pub fn for_path(path: &str) -> Option<Self> {
+        match extension(path)?.as_str() {
+            "json" => Some(StructuredFormat::Json),
+            "yaml" | "yml" => Some(StructuredFormat::Yaml),
+            "toml" => Some(StructuredFormat::Toml),
+            _ => None,
+        }
+    }
+
+    /// 格式的名称
+    pub fn name(&self) -> &'static str {
+        match self {
+            StructuredFormat::Json => "JSON",
+            StructuredFormat::Yaml => "YAML",
+            StructuredFormat::Toml => "TOML",
+        }
+    }
+
+    /// 检查内容能否按该格式解析
+    pub fn validate(&self, content: &str) -> Result<()> {
+        match self {
+            StructuredFormat::Json => {
+                serde_json::from_str::<serde_json::Value>(content)?;
+            }
+            StructuredFormat::Yaml => {
+                yaml_rust2::YamlLoader::load_from_str(content)?;
+            }
+            StructuredFormat::Toml => {
+                Config::builder()
+                    .add_source(File::from_str(content, FileFormat::Toml))
+                    .build()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 源代码文件的扩展名和语言名称
+const SOURCE_LANGUAGES: &[(&[&str], &str)] = &[
+    (&["rs"], "Rust"),
+    (&["py", "pyi"], "Python"),
+    (&["js", "mjs", "cjs", "jsx"], "JavaScript"),
+    (&["ts", "mts", "cts", "tsx"], "TypeScript"),
+    (&["go"], "Go"),
+    (&["java"], "Java"),
+    (&["kt", "kts"], "Kotlin"),
+    (&["c", "h"], "C"),
+    (&["cc", "cpp", "cxx", "hh", "hpp", "hxx"], "C++"),
+    (&["cs"], "C#"),
+    (&["swift"], "Swift"),
+    (&["rb"], "Ruby"),
+    (&["php"], "PHP"),
+    (&["sh", "bash", "zsh"], "shell"),
+    (&["sql"], "SQL"),
+];
+
+/// 根据扩展名生成提示模型文件语言的说明，无法识别时返回 None
+pub fn language_hint(path: &str) -> Option<String> {
+    if let Some(format) = StructuredFormat::for_path(path) {
+        return Some(format!(
+            "This is a {} file; the result must be valid {}.",
+            format.name(),
+            format.name()
+        ));
+    }
+
+    let extension = extension(path)?;
+    let (_, language) = SOURCE_LANGUAGES
+        .iter()
+        .find(|(extensions, _)| extensions.contains(&extension.as_str()))?;
+    Some(format!(
+        "This is a {} source file; ensure the result is syntactically valid and compiles.",
+        language
+    ))
+}
+
+/// 检查解决方案是否符合文件的结构化格式，我们的版本本身无法解析时（例如带注释的 JSON）不做要求
+pub fn check_format(path: &str, our_content: &str, resolution: &str) -> Result<()> {
+    let Some(format) = StructuredFormat::for_path(path) else {
+        return Ok(());
+    };
+    if format.validate(our_content).is_err() {
+        return Ok(());
+    }
+    format
+        .validate(resolution)
+        .map_err(|e| anyhow!("AI resolution is not valid {}: {}", format.name(), e))
+}
+
+fn extension(path: &str) -> Option<String> {
+    Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_language_hint() {
+        assert_eq!(
+            language_hint("src/main.rs").as_deref(),
+            Some("This is a Rust source file; ensure the result is syntactically valid and compiles.")
+        );
+        assert!(language_hint("web/App.TSX").unwrap().contains("TypeScript"));
+        assert_eq!(
+            language_hint("package.json").as_deref(),
+            Some("This is a JSON file; the result must be valid JSON.")
+        );
+        assert!(language_hint(".github/workflows/ci.yml")
+            .unwrap()
+            .contains("YAML"));
+        assert_eq!(language_hint("README"), None);
+        assert_eq!(language_hint("notes.txt"), None);
+    }
+
+    #[test]
+    fn test_check_format() {
+        assert!(check_format("a.json", "{}", r#"{"a": 1}"#).is_ok());
+        let err = check_format("a.json", "{}", r#"{"a": 1,}"#).unwrap_err();
+        assert!(err.to_string().contains("not valid JSON"));
+
+        // 顶层为列表的 YAML 同样有效
+        assert!(check_format("play.yml", "- a\n", "- a\n- b\n").is_ok());
+        assert!(check_format("play.yml", "- a\n", "a: [1, 2\n").is_err());
+
+        assert!(check_format("Cargo.toml", "[package]\n", "[package]\nname = \"a\"\n").is_ok());
+        assert!(check_format("Cargo.toml", "[package]\n", "[package\n").is_err());
+
+        // 我们的版本本身无法解析时不检查，其他文件也不检查
+        assert!(check_format("tsconfig.json", "{ // comment\n}", "{ // comment\n}").is_ok());
+        assert!(check_format("main.rs", "fn main() {}", "fn main() {").is_ok());
+    }
+}
@@ -0,0 +1,21 @@
+//! Git-Tools：使用AI解决合并冲突的 Git 工具
+//!
+//! 命令行程序之外，也可以作为库嵌入到其他工具中：
+//!
+//! - [`git::GitHandler`]：合并、变基、拣选以及冲突信息的获取与解决
+//! - [`ai::ConflictResolver`]：调用AI服务为冲突文件生成解决方案
+//! - [`config::Settings`]：AI服务和冲突处理相关的配置
+//! - [`cache::ResolutionCache`]：在磁盘上缓存AI的解决方案
+//! - [`audit`]：将应用的AI解决方案记录到审计日志
+//! - [`hook`]：检测到冲突时通知外部命令或 Webhook
+//! - [`rules::StrategyRules`]：按路径为冲突文件选择解决策略
+//! - [`language`]：按扩展名识别文件语言，并检查结构化文件的解决方案能否解析
+
+pub mod ai;
+pub mod audit;
+pub mod cache;
+pub mod config;
+pub mod git;
+pub mod hook;
+pub mod language;
+pub mod rules;
@@ -1,196 +1,2319 @@
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
 use colored::*;
+use futures_util::stream::{self, StreamExt};
+use tracing_subscriber::filter::{EnvFilter, LevelFilter};
 use tracing_subscriber::prelude::*;
 
-mod ai;
-mod config;
-mod git;
+mod ui;
 
-use config::Settings;
+use git_tools::{ai, cache, config, git, hook, rules};
+
+use ai::TokenUsage;
+use cache::ResolutionCache;
+use config::{ConflictStrategy, LfsPolicy, Settings};
+use git::{ConflictFile, ConflictKind, MergeOutcome};
+use rules::StrategyRules;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use tokio::time::Instant;
+use ui::{output, status, status_inline};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
-    /// The Git repository path
-    #[arg(short, long, default_value = ".")]
-    repo: String,
+    /// Git仓库路径，指定时只打开该目录；未指定时像 git 一样从当前目录向上查找所在的仓库
+    #[arg(short, long)]
+    repo: Option<String>,
+
+    /// 未指定 --repo 时只打开当前目录，不向上查找所在的仓库
+    #[arg(long)]
+    no_discover: bool,
+
+    /// 命令结果的输出格式
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    /// 是否输出颜色，auto 时遵循 NO_COLOR 环境变量并在标准输出不是终端时关闭
+    #[arg(long, global = true, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
+    /// 在标准错误输出日志，可重复：-v 为 info，-vv 为 debug（包含完整的AI请求和响应），-vvv 为 trace
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// 不输出进度和状态信息，只输出错误和命令结果，便于在脚本中使用
+    #[arg(short, long)]
+    quiet: bool,
 
     #[command(subcommand)]
     command: Command,
 }
 
+/// 命令结果的输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// 带颜色的可读文本
+    Text,
+    /// 便于脚本处理的 JSON
+    Json,
+}
+
+/// 颜色输出模式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ColorChoice {
+    /// 标准输出是终端且未设置 NO_COLOR 时输出颜色
+    Auto,
+    /// 总是输出颜色
+    Always,
+    /// 从不输出颜色
+    Never,
+}
+
 #[derive(Subcommand)]
 enum Command {
     /// 合并分支并使用AI解决冲突
-    Merge {
-        /// The target branch to merge into
+    Merge(MergeArgs),
+    /// 列出目标分支中不在源分支中的提交
+    ListUnique(ListUniqueArgs),
+    /// 列出单个分支上的提交
+    Log(LogArgs),
+    /// 将指定提交拣选到当前分支，并使用AI解决冲突
+    CherryPick {
+        /// The commit to cherry-pick
+        commit: String,
+    },
+    /// 在当前分支上创建撤销指定提交的新提交，并使用AI解决冲突
+    Revert {
+        /// The commit to revert
+        commit: String,
+    },
+    /// 在指定提交上创建标签，默认创建附注标签
+    Tag {
+        /// 标签名称
         #[arg(short, long)]
-        target: String,
+        name: String,
+
+        /// 附注标签的说明
+        #[arg(short, long, required_unless_present = "lightweight")]
+        message: Option<String>,
 
-        /// The source branch to merge from
+        /// 标签指向的提交，未指定时使用 HEAD
         #[arg(short, long)]
-        source: String,
+        commit: Option<String>,
+
+        /// 创建不带说明的轻量标签
+        #[arg(long, conflicts_with = "message")]
+        lightweight: bool,
     },
-    /// 列出目标分支中不在源分支中的提交
-    ListUnique {
-        /// The target branch to examine
+    /// 变基分支并使用AI解决冲突
+    Rebase {
+        /// The branch to rebase onto
         #[arg(short, long)]
-        target: String,
+        onto: String,
 
-        /// The source branch to compare against
+        /// The branch to rebase
         #[arg(short, long)]
-        source: String,
+        branch: String,
+    },
+    /// 显示进行中的合并、变基等操作的状态，以及冲突文件的解决情况
+    Status,
+    /// 手动解决剩余冲突后，为进行中的合并创建合并提交
+    Continue {
+        /// 合并提交的说明，未指定时使用默认说明
+        #[arg(short, long)]
+        message: Option<String>,
+    },
+    /// 中止进行中的合并，将索引和工作目录恢复到合并前的 HEAD
+    Abort,
+    /// 向配置的AI服务发送一个示例冲突，检查 API 密钥、地址、模型和网络是否可用
+    Check,
+    /// 管理配置文件
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// 生成 shell 自动补全脚本并输出到标准输出
+    #[command(hide = true)]
+    Completions {
+        /// 目标 shell
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+    /// 显示版本信息
+    Version {
+        /// 同时显示 libgit2 版本、编译特性和构建信息，便于反馈问题
+        #[arg(short, long)]
+        verbose: bool,
     },
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    // 初始化 journald 日志订阅器
-    let journald_layer = tracing_journald::layer().expect("Failed to connect to systemd-journald");
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// 交互式地创建用户配置文件
+    Init,
+    /// 显示合并默认值、配置文件和环境变量后实际生效的配置，API 密钥会被隐藏
+    Show,
+}
+
+#[derive(Args)]
+struct ListUniqueArgs {
+    /// The target branch or revision (tag, commit hash, HEAD~3) to examine
+    #[arg(short, long, required_unless_present = "until_ref")]
+    target: Option<String>,
+
+    /// The source branch or revision (tag, commit hash, HEAD~3) to compare against
+    #[arg(short, long, required_unless_present = "since_ref")]
+    source: Option<String>,
+
+    /// 起点版本，代替 --source，支持 main@{1}、HEAD@{yesterday} 等引用日志写法
+    #[arg(long, conflicts_with = "source")]
+    since_ref: Option<String>,
+
+    /// 终点版本，代替 --target，支持引用日志写法
+    #[arg(long, conflicts_with = "target")]
+    until_ref: Option<String>,
+
+    /// 显示指向每个提交的标签和分支
+    #[arg(long)]
+    show_refs: bool,
+
+    /// 只列出作者名称或邮箱包含该文本的提交，不区分大小写
+    #[arg(long, value_name = "PATTERN")]
+    author: Option<String>,
+
+    /// 只列出不早于该日期的提交，支持 RFC3339 或 YYYY-MM-DD
+    #[arg(long, value_name = "DATE", value_parser = parse_since)]
+    since: Option<i64>,
+
+    /// 只列出不晚于该日期的提交，YYYY-MM-DD 包含当天
+    #[arg(long, value_name = "DATE", value_parser = parse_until)]
+    until: Option<i64>,
+
+    /// 最多列出的提交数，只显示最新的 N 个
+    #[arg(long, value_name = "N")]
+    limit: Option<usize>,
+}
+
+#[derive(Args)]
+struct LogArgs {
+    /// 要查看的分支或版本
+    #[arg(default_value = "HEAD")]
+    branch: String,
+
+    /// 最多列出的提交数，只显示最新的 N 个
+    #[arg(long, value_name = "N")]
+    limit: Option<usize>,
+
+    /// 每个提交只显示一行：短哈希和标题
+    #[arg(long)]
+    oneline: bool,
+}
+
+fn parse_since(input: &str) -> Result<i64, String> {
+    git::parse_date(input, false).map_err(|e| e.to_string())
+}
+
+fn parse_until(input: &str) -> Result<i64, String> {
+    git::parse_date(input, true).map_err(|e| e.to_string())
+}
+
+#[derive(Args, Clone)]
+struct MergeArgs {
+    /// The target branch to merge into
+    #[arg(short, long)]
+    target: String,
+
+    /// The source branch(es) to merge from, can be given multiple times.
+    /// Defaults to the configured upstream of the target branch
+    #[arg(short, long)]
+    source: Vec<String>,
+
+    /// 批量合并时某个源分支失败后继续合并其余分支，最后汇总结果
+    #[arg(long)]
+    keep_going: bool,
+
+    /// 不在合并提交的说明中追加记录AI解决的文件和模型的尾注
+    #[arg(long)]
+    no_ai_trailer: bool,
+
+    /// 部分冲突未能由AI解决时中止整个合并，而不是保留已解决的文件并让合并保持进行中
+    #[arg(long)]
+    abort_on_partial: bool,
+
+    /// 工作目录存在未提交的修改时仍然合并，切换分支可能覆盖这些修改
+    #[arg(long)]
+    force: bool,
+
+    /// 子模块存在未提交的修改时仍然合并
+    #[arg(long)]
+    allow_dirty_submodules: bool,
+
+    /// 启动时查询模型的上下文窗口大小，并据此调整发送给AI的内容长度
+    #[arg(long)]
+    context_window_from_model: bool,
+
+    /// 以流式方式逐步输出AI的解决方案
+    #[arg(long)]
+    stream: bool,
+
+    /// 将多个冲突合并到一个请求中交给AI解决，减少请求次数
+    #[arg(long)]
+    batch: bool,
+
+    /// 逐个确认AI的解决方案：接受、拒绝、编辑后应用或跳过
+    #[arg(long)]
+    interactive: bool,
+
+    /// 不使用解决方案缓存，总是重新请求AI
+    #[arg(long)]
+    no_cache: bool,
+
+    /// 要求AI说明每个解决方案的理由并输出，不能与批量解决同时使用
+    #[arg(long, conflicts_with = "batch")]
+    explain: bool,
+
+    /// 只输出AI的解决方案相对于我们的版本的差异，而不是完整内容
+    #[arg(long)]
+    show_diff: bool,
+
+    /// AI解决冲突失败重试时轮换使用不同的提示词模板
+    #[arg(long)]
+    resolve_retry_different_prompt: bool,
+
+    /// 本次运行使用的AI模型，覆盖配置文件和环境变量中的 model
+    #[arg(long)]
+    model: Option<String>,
+
+    /// 从文件读取系统提示词，替换内置的默认提示词
+    #[arg(long, value_name = "FILE")]
+    prompt_file: Option<std::path::PathBuf>,
+
+    /// 冲突的解决策略：ai 交给AI解决，ours/theirs 直接采用一方的内容，不请求AI。
+    /// 配置了 strategy_rules 时只用于没有匹配规则的文件
+    #[arg(long, value_enum, default_value_t = ConflictStrategy::Ai)]
+    strategy: ConflictStrategy,
+
+    /// 只列出每个冲突文件将采用的解决方式，不执行合并
+    #[arg(long)]
+    show_plan: bool,
+
+    /// 只列出合并将会产生冲突的文件，不修改 HEAD 和工作目录
+    #[arg(long, conflicts_with = "show_plan")]
+    preview: bool,
 
-    // 设置全局日志订阅器
-    tracing_subscriber::registry().with(journald_layer).init();
+    /// 即使可以快进也创建合并提交
+    #[arg(long, conflicts_with = "ff_only")]
+    no_ff: bool,
 
+    /// 只允许快进合并，需要创建合并提交时报错退出
+    #[arg(long)]
+    ff_only: bool,
+
+    /// 压缩合并：将修改暂存到索引，不创建合并提交，由用户自行提交
+    #[arg(long, conflicts_with_all = ["no_ff", "ff_only"])]
+    squash: bool,
+
+    /// 合并提交的说明，未指定时使用默认说明
+    #[arg(short, long, conflicts_with_all = ["squash", "ff_only"])]
+    message: Option<String>,
+
+    /// 在编辑器中编辑合并提交的说明，以默认说明或 --message 的内容作为初始内容
+    #[arg(long, conflicts_with_all = ["squash", "ff_only"])]
+    edit: bool,
+
+    /// 检测到冲突时执行的命令，冲突信息以 JSON 格式写入其标准输入
+    #[arg(long)]
+    on_conflict_command: Option<String>,
+
+    /// 创建提交时使用的作者名称，覆盖 git 配置中的 user.name
+    #[arg(long, requires = "author_email")]
+    author_name: Option<String>,
+
+    /// 创建提交时使用的作者邮箱，覆盖 git 配置中的 user.email
+    #[arg(long, requires = "author_name")]
+    author_email: Option<String>,
+}
+
+/// 单个冲突文件将采用的解决方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ResolutionStrategy {
+    /// 交给AI解决
+    Ai,
+    /// git-lfs 指针文件，按策略保留一方
+    LfsPointer(LfsPolicy),
+    /// 二进制文件或不是有效 UTF-8 的文本，无法交给AI，需要手动解决
+    Binary,
+    /// 一方删除了文件，需要手动决定保留还是删除
+    Deleted,
+    /// 内容超过 max_conflict_bytes，不发送给AI
+    TooLarge,
+    /// 路径不是有效的 UTF-8，无法写回解决方案，需要手动解决
+    NonUtf8Path,
+    /// 按策略采用当前分支的内容
+    Ours,
+    /// 按策略采用合并进来的分支的内容
+    Theirs,
+    /// 留给用户手动解决
+    Manual,
+}
+
+impl fmt::Display for ResolutionStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ResolutionStrategy::Ai => "AI",
+            ResolutionStrategy::LfsPointer(LfsPolicy::Ours) => "LFS 指针（保留我们的版本）",
+            ResolutionStrategy::LfsPointer(LfsPolicy::Theirs) => "LFS 指针（保留他们的版本）",
+            ResolutionStrategy::Binary => "二进制或非 UTF-8 文件（手动）",
+            ResolutionStrategy::Deleted => "一方已删除（手动）",
+            ResolutionStrategy::TooLarge => "超过大小限制（手动）",
+            ResolutionStrategy::NonUtf8Path => "非 UTF-8 路径（手动）",
+            ResolutionStrategy::Ours => "保留我们的版本",
+            ResolutionStrategy::Theirs => "保留他们的版本",
+            ResolutionStrategy::Manual => "手动",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// 决定冲突文件的解决方式
+///
+/// LFS 指针冲突在AI解决流程中按策略处理，因此同样需要配置AI
+fn plan_resolution(
+    conflict: &ConflictFile,
+    config: &Settings,
+    strategy: ConflictStrategy,
+) -> ResolutionStrategy {
+    if conflict.is_deletion() {
+        ResolutionStrategy::Deleted
+    } else if conflict.is_binary {
+        ResolutionStrategy::Binary
+    } else if !conflict.has_utf8_path() {
+        ResolutionStrategy::NonUtf8Path
+    } else if strategy == ConflictStrategy::Ours {
+        ResolutionStrategy::Ours
+    } else if strategy == ConflictStrategy::Theirs {
+        ResolutionStrategy::Theirs
+    } else if !config.ai_available() {
+        ResolutionStrategy::Manual
+    } else if conflict.content_len() > config.max_conflict_bytes {
+        ResolutionStrategy::TooLarge
+    } else if conflict.is_lfs_pointer() {
+        ResolutionStrategy::LfsPointer(config.lfs_policy)
+    } else {
+        ResolutionStrategy::Ai
+    }
+}
+
+/// 打开要操作的仓库：明确指定的 --repo 按原样打开，否则从当前目录向上查找
+fn open_repository(cli: &Cli) -> Result<git::GitHandler> {
+    match &cli.repo {
+        Some(path) => git::GitHandler::open(path)
+            .map_err(|e| anyhow::anyhow!("'{}' 不是 git 仓库: {}", path, e)),
+        None if cli.no_discover => {
+            git::GitHandler::open(".").map_err(|e| anyhow::anyhow!("当前目录不是 git 仓库: {}", e))
+        }
+        None => git::GitHandler::new(".")
+            .map_err(|e| anyhow::anyhow!("当前目录不在 git 仓库中（已向上查找所有父目录）: {}", e)),
+    }
+}
+
+/// 查找仓库的工作目录，用于不需要打开仓库的命令读取仓库配置，不在仓库中时返回 None
+fn discover_workdir(cli: &Cli) -> Option<PathBuf> {
+    let git = open_repository(cli).ok()?;
+    git.workdir().ok().map(Path::to_path_buf)
+}
+
+/// 加载AI配置，失败时给出警告并使用默认配置
+///
+/// 只是缺少API密钥时保留其余配置，策略规则等不依赖AI的配置仍然生效
+fn load_ai_settings(git: &git::GitHandler) -> Settings {
+    let repo_dir = git.workdir().ok();
+    match Settings::load(repo_dir) {
+        Ok(config) => config,
+        Err(err) => {
+            status!("警告: 无法加载OpenAI配置: {}", err);
+            status!("将在没有AI辅助的情况下继续执行，如有冲突需手动解决");
+            match err {
+                config::ConfigError::MissingApiKey => {
+                    Settings::load_merged(repo_dir).unwrap_or_default()
+                }
+                _ => Settings::default(),
+            }
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
     // Parse command line arguments
     let cli = Cli::parse();
+    ui::set_quiet(cli.quiet);
+
+    // 版本信息不需要打开仓库和日志，在没有 journald 的系统上也能用于反馈问题
+    if let Command::Version { verbose } = &cli.command {
+        print_version(*verbose);
+        return Ok(());
+    }
+
+    if let Command::Completions { shell } = &cli.command {
+        clap_complete::generate(
+            *shell,
+            &mut Cli::command(),
+            "git-tools",
+            &mut std::io::stdout(),
+        );
+        return Ok(());
+    }
+
+    // 配置管理同样不需要仓库
+    if let Command::Config { action } = &cli.command {
+        return match action {
+            ConfigAction::Init => handle_config_init(),
+            ConfigAction::Show => {
+                let settings = Settings::load_merged(discover_workdir(&cli).as_deref())?.redacted();
+                output!("{}", serde_json::to_string_pretty(&settings)?);
+                Ok(())
+            }
+        };
+    }
+
+    init_logging(cli.verbose);
+
+    // 检查AI服务同样不需要仓库
+    if let Command::Check = &cli.command {
+        return handle_check(discover_workdir(&cli).as_deref(), cli.format).await;
+    }
+
+    // 按 --color 和 NO_COLOR 决定是否输出颜色，JSON 输出不应包含颜色控制字符
+    let color = match cli.color {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                && std::io::stdout().is_terminal()
+        }
+    };
+    colored::control::set_override(color && cli.format != OutputFormat::Json);
 
     // Create GitHandler instance
-    let git = git::GitHandler::new(&cli.repo)?;
+    let mut git = open_repository(&cli)?;
 
     match &cli.command {
-        Command::Merge { target, source } => {
+        Command::Merge(args) => {
+            // JSON 格式只输出最终的合并结果，不输出过程信息
+            if cli.format == OutputFormat::Json {
+                ui::set_quiet(true);
+            }
+
             // 只在需要使用AI时加载配置
-            let config = match Settings::load() {
-                Ok(config) => config,
-                Err(err) => {
-                    eprintln!("警告: 无法加载OpenAI配置: {}", err);
-                    eprintln!("将在没有AI辅助的情况下继续执行合并，如有冲突需手动解决");
-                    Settings::default()
-                }
-            };
+            let mut config = load_ai_settings(&git);
+
+            if args.context_window_from_model {
+                config.context_window_from_model = true;
+            }
+            if args.resolve_retry_different_prompt {
+                config.retry_different_prompt = true;
+            }
+            if args.stream {
+                config.stream = true;
+            }
+            if args.batch {
+                config.batch = true;
+            }
+            if args.explain {
+                config.explain = true;
+            }
+            if args.show_diff {
+                config.show_diff = true;
+            }
+            if args.no_cache {
+                config.cache = false;
+            }
+            if let Some(model) = &args.model {
+                config.model = model.clone();
+            }
+            if let Some(path) = &args.prompt_file {
+                let prompt = std::fs::read_to_string(path).map_err(|e| {
+                    anyhow::anyhow!("无法读取提示词文件 '{}': {}", path.display(), e)
+                })?;
+                config.system_prompt = Some(prompt.trim().to_string());
+            }
+            if let Some(command) = &args.on_conflict_command {
+                config.on_conflict_command = Some(command.clone());
+            }
+            if let (Some(name), Some(email)) = (&args.author_name, &args.author_email) {
+                git.set_identity(name, email);
+            }
+            git.set_conflict_style(config.conflict_style);
+            git.set_sign_commits(config.sign_commits);
+
+            handle_merge(&git, args, config, cli.format).await
+        }
+        Command::ListUnique(args) => handle_list_unique(&git, args, cli.format),
+        Command::Log(args) => handle_log(&git, args, cli.format),
+        Command::CherryPick { commit } => {
+            let config = load_ai_settings(&git);
+            git.set_conflict_style(config.conflict_style);
+            git.set_sign_commits(config.sign_commits);
+            handle_cherry_pick(&git, commit, config).await
+        }
+        Command::Revert { commit } => {
+            let config = load_ai_settings(&git);
+            git.set_conflict_style(config.conflict_style);
+            git.set_sign_commits(config.sign_commits);
+            handle_revert(&git, commit, config).await
+        }
+        Command::Tag {
+            name,
+            message,
+            commit,
+            lightweight: _,
+        } => handle_tag(&git, name, message.as_deref(), commit.as_deref()),
+        Command::Rebase { onto, branch } => {
+            let config = load_ai_settings(&git);
+            git.set_conflict_style(config.conflict_style);
+            git.set_sign_commits(config.sign_commits);
+            handle_rebase(&git, onto, branch, config).await
+        }
+        Command::Status => handle_status(&git, cli.format),
+        Command::Continue { message } => {
+            // 只需要提交签名相关的配置，不要求配置AI
+            let config = Settings::load(git.workdir().ok()).unwrap_or_default();
+            git.set_sign_commits(config.sign_commits);
+            handle_continue(&git, message.as_deref())
+        }
+        Command::Abort => handle_abort(&git),
+        // 已在打开仓库之前处理
+        Command::Config { .. }
+        | Command::Completions { .. }
+        | Command::Version { .. }
+        | Command::Check => Ok(()),
+    }
+}
+
+/// 初始化日志：优先写入 journald，不可用时（macOS、容器、WSL 等）或指定了 -v 时写入标准错误
+///
+/// 标准错误的日志级别由 RUST_LOG 控制，默认只输出警告和错误，-v 在此基础上提高本工具的日志级别
+fn init_logging(verbose: u8) {
+    let journald_layer = tracing_journald::layer().ok();
+
+    let stderr_layer = (journald_layer.is_none() || verbose > 0).then(|| {
+        let mut filter = EnvFilter::builder()
+            .with_default_directive(LevelFilter::WARN.into())
+            .from_env_lossy();
+        let level = match verbose {
+            0 => None,
+            1 => Some("info"),
+            2 => Some("debug"),
+            _ => Some("trace"),
+        };
+        // 只提高本工具的日志级别，避免依赖库的调试日志淹没输出
+        if let Some(directive) = level.and_then(|level| format!("git_tools={}", level).parse().ok())
+        {
+            filter = filter.add_directive(directive);
+        }
+
+        tracing_subscriber::fmt::layer()
+            // 日志同样写入标准错误，输出时暂时隐藏进度条
+            .with_writer(|| ui::StderrWriter)
+            .with_ansi(std::io::stderr().is_terminal())
+            .with_filter(filter)
+    });
+
+    tracing_subscriber::registry()
+        .with(journald_layer)
+        .with(stderr_layer)
+        .init();
+}
+
+/// 交互式地询问常用配置项并写入用户配置文件
+fn handle_config_init() -> Result<()> {
+    let path = Settings::config_path()?;
+    if path.exists() && !ui::confirm(&format!("配置文件 {} 已存在，是否覆盖？", path.display()))?
+    {
+        status!("已取消，配置文件保持不变。");
+        return Ok(());
+    }
+
+    let defaults = Settings::default();
+    let api_key =
+        ui::prompt_secret("API 密钥（输入不会显示，留空则使用 OPENAI_API_KEY 环境变量）")?;
+    let model = ui::prompt("模型", &defaults.model)?;
+    let timeout_seconds = prompt_number("请求超时（秒）", defaults.timeout_seconds)?;
+    let max_retries = prompt_number("最大重试次数", defaults.max_retries)?;
+
+    let settings = Settings {
+        openai_api_key: (!api_key.is_empty()).then_some(api_key),
+        model,
+        timeout_seconds,
+        max_retries,
+        ..defaults
+    };
+    settings.save()?;
+    status!("配置已写入 {}", path.display());
+
+    Ok(())
+}
+
+/// 询问一个数字，输入无效时重新询问
+fn prompt_number<T: std::str::FromStr + fmt::Display>(label: &str, default: T) -> Result<T> {
+    loop {
+        match ui::prompt(label, &default.to_string())?.parse() {
+            Ok(value) => return Ok(value),
+            Err(_) => status!("请输入有效的数字。"),
+        }
+    }
+}
+
+/// 向AI服务发送示例冲突，检查配置是否可用
+async fn handle_check(repo_dir: Option<&Path>, format: OutputFormat) -> Result<()> {
+    let config = Settings::load(repo_dir).map_err(|e| anyhow::anyhow!("无法加载AI配置: {}", e))?;
+    let resolver = ai::ConflictResolver::new(config.clone());
+
+    status!(
+        "正在检查 {:?} 服务 (模型 {})...",
+        config.provider,
+        config.model
+    );
+    let report = resolver
+        .check()
+        .await
+        .map_err(|e| anyhow::anyhow!("AI服务检查失败: {}", e))?;
+
+    if format == OutputFormat::Json {
+        output!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    output!(
+        "{} AI服务可用，耗时 {} 毫秒",
+        "✓".green(),
+        report.elapsed_ms
+    );
+    match &report.model {
+        Some(model) => output!(
+            "请求的模型: {}，服务端返回的模型: {}",
+            report.requested_model,
+            model
+        ),
+        None => output!(
+            "请求的模型: {}（服务端未返回模型名）",
+            report.requested_model
+        ),
+    }
+    output!("示例冲突的解决方案:\n{}", report.resolution.trim_end());
+
+    Ok(())
+}
+
+/// 输出版本信息，verbose 时附带 libgit2 和构建信息
+fn print_version(verbose: bool) {
+    output!("git-tools {}", env!("CARGO_PKG_VERSION"));
+    if !verbose {
+        return;
+    }
+
+    let libgit2 = git2::Version::get();
+    let (major, minor, patch) = libgit2.libgit2_version();
+    let yes_no = |enabled: bool| if enabled { "yes" } else { "no" };
+
+    output!("git2:      {}", libgit2.crate_version());
+    output!(
+        "libgit2:   {}.{}.{} ({})",
+        major,
+        minor,
+        patch,
+        if libgit2.vendored() {
+            "vendored"
+        } else {
+            "system"
+        }
+    );
+    output!("https:     {}", yes_no(libgit2.https()));
+    output!("ssh:       {}", yes_no(libgit2.ssh()));
+    output!("threads:   {}", yes_no(libgit2.threads()));
+    output!("rustc:     {}", env!("GT_RUSTC_VERSION"));
+    output!("target:    {}", env!("GT_BUILD_TARGET"));
+    output!("profile:   {}", env!("GT_BUILD_PROFILE"));
+}
+
+/// 单个源分支的合并结果，按需要关注的程度排序
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum SourceOutcome {
+    /// 已包含源分支的所有提交，没有任何修改
+    UpToDate,
+    /// 合并完成，没有冲突
+    Merged,
+    /// 所有冲突已解决
+    Resolved,
+    /// 存在未能解决的冲突，合并已中止
+    Unresolved,
+}
+
+impl fmt::Display for SourceOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SourceOutcome::UpToDate => "已是最新",
+            SourceOutcome::Merged => "成功",
+            SourceOutcome::Resolved => "冲突已解决",
+            SourceOutcome::Unresolved => "冲突未解决",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl SourceOutcome {
+    /// JSON 输出中使用的标识
+    fn id(self) -> &'static str {
+        match self {
+            SourceOutcome::UpToDate => "up_to_date",
+            SourceOutcome::Merged => "merged",
+            SourceOutcome::Resolved => "resolved",
+            SourceOutcome::Unresolved => "conflicts",
+        }
+    }
+}
+
+/// 合并单个源分支的结果，以及由工具解决和未能解决的冲突文件
+#[derive(Debug)]
+struct SourceResult {
+    outcome: SourceOutcome,
+    resolved: Vec<String>,
+    unresolved: Vec<String>,
+}
+
+impl From<SourceOutcome> for SourceResult {
+    fn from(outcome: SourceOutcome) -> Self {
+        Self {
+            outcome,
+            resolved: Vec::new(),
+            unresolved: Vec::new(),
+        }
+    }
+}
+
+/// merge 以 JSON 格式输出的结果
+#[derive(Serialize)]
+struct MergeReport<'a> {
+    /// 所有源分支中最需要关注的结果，任一源分支出错时为 error
+    outcome: &'static str,
+    resolved: Vec<&'a str>,
+    unresolved: Vec<&'a str>,
+    tokens: TokenUsage,
+    sources: Vec<SourceReport<'a>>,
+}
+
+/// MergeReport 中单个源分支的结果
+#[derive(Serialize)]
+struct SourceReport<'a> {
+    source: &'a str,
+    outcome: &'static str,
+    resolved: &'a [String],
+    unresolved: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl<'a> MergeReport<'a> {
+    fn new(sources: &'a [String], results: &'a [Result<SourceResult>], tokens: TokenUsage) -> Self {
+        let sources: Vec<SourceReport> = sources
+            .iter()
+            .enumerate()
+            .map(|(i, source)| match results.get(i) {
+                Some(Ok(result)) => SourceReport {
+                    source,
+                    outcome: result.outcome.id(),
+                    resolved: &result.resolved,
+                    unresolved: &result.unresolved,
+                    error: None,
+                },
+                Some(Err(e)) => SourceReport {
+                    source,
+                    outcome: "error",
+                    resolved: &[],
+                    unresolved: &[],
+                    error: Some(e.to_string()),
+                },
+                // 前面的源分支失败后没有继续合并
+                None => SourceReport {
+                    source,
+                    outcome: "skipped",
+                    resolved: &[],
+                    unresolved: &[],
+                    error: None,
+                },
+            })
+            .collect();
+
+        let outcome = if results.iter().any(|result| result.is_err()) {
+            "error"
+        } else {
+            results
+                .iter()
+                .filter_map(|result| result.as_ref().ok())
+                .map(|result| result.outcome)
+                .max()
+                .unwrap_or(SourceOutcome::UpToDate)
+                .id()
+        };
 
-            handle_merge(&git, target, source, config).await
+        Self {
+            outcome,
+            resolved: sources
+                .iter()
+                .flat_map(|source| source.resolved.iter().map(String::as_str))
+                .collect(),
+            unresolved: sources
+                .iter()
+                .flat_map(|source| source.unresolved.iter().map(String::as_str))
+                .collect(),
+            tokens,
+            sources,
         }
-        Command::ListUnique { target, source } => handle_list_unique(&git, target, source),
     }
 }
 
 async fn handle_merge(
     git: &git::GitHandler,
-    target: &str,
-    source: &str,
+    args: &MergeArgs,
     config: Settings,
+    format: OutputFormat,
 ) -> Result<()> {
+    let target = args.target.as_str();
+
     // Verify branches exist
     if !git.branch_exists(target)? {
         return Err(anyhow::anyhow!("Target branch '{}' does not exist", target));
     }
-    if !git.branch_exists(source)? {
-        return Err(anyhow::anyhow!("Source branch '{}' does not exist", source));
+
+    // 与不带参数的 git merge 一样，未指定源分支时合并目标分支的上游分支
+    let with_upstream;
+    let args = if args.source.is_empty() {
+        let upstream = git.upstream_of(target).map_err(|e| {
+            anyhow::anyhow!(
+                "未指定 --source，且无法确定 '{}' 的上游分支: {}。请使用 --source 指定源分支，或运行 git branch --set-upstream-to 设置上游分支",
+                target,
+                e
+            )
+        })?;
+        status!("未指定源分支，合并 '{}' 的上游分支 '{}'", target, upstream);
+        with_upstream = MergeArgs {
+            source: vec![upstream],
+            ..args.clone()
+        };
+        &with_upstream
+    } else {
+        args
+    };
+    let batch = args.source.len() > 1;
+    for source in &args.source {
+        if !git.branch_exists(source)? {
+            return Err(anyhow::anyhow!("Source branch '{}' does not exist", source));
+        }
+    }
+    if args.squash && batch {
+        return Err(anyhow::anyhow!("--squash 只支持单个源分支"));
+    }
+    if format == OutputFormat::Json && (args.preview || args.show_plan || args.interactive) {
+        return Err(anyhow::anyhow!(
+            "--preview、--show-plan 和 --interactive 不支持 JSON 输出"
+        ));
     }
 
-    // Attempt to merge
-    let has_conflicts = git.merge_branches(target, source)?;
+    if args.preview {
+        for source in &args.source {
+            if batch {
+                output!("\n'{}':", source);
+            }
+            let paths = git.preview_merge(target, source)?;
+            if paths.is_empty() {
+                status!("合并不会产生冲突。");
+                continue;
+            }
+            status!("合并将产生 {} 个冲突:", paths.len());
+            for path in &paths {
+                output!("  {}", path);
+            }
+        }
+        return Ok(());
+    }
 
-    if has_conflicts {
-        println!("合并产生冲突。正在获取冲突详情...");
-        let conflicts = git.get_conflicts()?;
+    if args.show_plan {
+        let rules = load_strategy_rules(git, &config)?;
+        let strategy_for = |conflict: &ConflictFile| conflict_strategy(&rules, args, conflict);
+        for source in &args.source {
+            if batch {
+                output!("\n'{}':", source);
+            }
+            let conflicts = git.simulate_conflicts(target, source)?;
+            print_resolution_plan(&conflicts, &config, &strategy_for);
+        }
+        return Ok(());
+    }
 
-        for conflict in &conflicts {
-            println!("\n文件冲突: {}", &conflict.path);
-            println!("我们的版本:\n{}", &conflict.our_content);
-            println!("他们的版本:\n{}", &conflict.their_content);
-            if let Some(base) = &conflict.base_content {
-                println!("基础版本:\n{}", base);
+    // 强制检出可能覆盖未提交的修改，合并前先检查
+    if !args.force {
+        let changes = git.uncommitted_changes()?;
+        if !changes.is_empty() {
+            status!("以下文件存在未提交的修改:");
+            for path in &changes {
+                status!("  {}", path.red());
             }
+            return Err(anyhow::anyhow!(
+                "工作目录存在未提交的修改，请先提交或使用 git stash 储藏，或使用 --force 强制合并"
+            ));
         }
+    }
 
-        // 检查是否有有效的API密钥来使用AI解决冲突
-        if config.openai_api_key.is_some() {
-            println!("\n正在尝试使用AI解决冲突...");
+    if !args.allow_dirty_submodules {
+        let dirty = git.dirty_submodules()?;
+        if !dirty.is_empty() {
+            return Err(anyhow::anyhow!(
+                "以下子模块存在未提交的修改: {}。请先提交或储藏，或使用 --allow-dirty-submodules 跳过检查",
+                dirty.join(", ")
+            ));
+        }
+    }
 
-            // Create AI conflict resolver
-            let resolver = ai::ConflictResolver::new(config);
+    // 检查是否有有效的API密钥来使用AI解决冲突
+    let resolver = config.ai_available().then(|| new_resolver(config.clone()));
+    let deadline = resolution_deadline(&config);
 
-            let mut all_resolved = true;
-            for conflict in &conflicts {
-                println!("\n解决文件冲突: {}", conflict.path);
-                match resolver.resolve_conflict(conflict).await {
-                    Ok(resolution) => {
-                        println!("AI建议的解决方案:\n{}", resolution);
-                        match git.apply_resolution(&conflict.path, &resolution) {
-                            Ok(_) => println!("✓ 解决方案应用成功"),
-                            Err(e) => {
-                                println!("✗ 应用解决方案失败: {}", e);
-                                all_resolved = false;
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        println!("✗ 获取AI解决方案失败: {}", e);
-                        all_resolved = false;
-                    }
-                }
+    let mut results = Vec::new();
+    for (i, source) in args.source.iter().enumerate() {
+        if batch {
+            status!("\n===== 合并 '{}' 到 '{}' =====", source, target);
+        }
+
+        let result = merge_source(
+            git,
+            target,
+            source,
+            args,
+            &config,
+            resolver.as_ref(),
+            deadline,
+        )
+        .await;
+        if result.is_err() {
+            // 只中止当前源分支的合并
+            if let Err(e) = git.abort_merge() {
+                status!("警告: 中止合并失败: {}", e);
             }
+        }
 
-            if all_resolved {
-                println!("\n所有冲突已成功解决！");
-                println!("请检查更改并提交。");
-            } else {
-                git.abort_merge()?;
-                println!("\n某些冲突无法自动解决。");
-                println!("合并已中止。请手动解决剩余冲突。");
+        let failed = !matches!(&result, Ok(result) if result.outcome != SourceOutcome::Unresolved);
+        let remaining = i + 1 < args.source.len();
+        if failed
+            && args.keep_going
+            && remaining
+            && git.repository_state() == git2::RepositoryState::Merge
+        {
+            // 进行中的合并会阻止后续分支的合并，只能放弃其中已解决的部分
+            status!("警告: 为继续合并其余分支，已中止 '{}' 的合并", source);
+            if let Err(e) = git.abort_merge() {
+                status!("警告: 中止合并失败: {}", e);
             }
-        } else {
-            git.abort_merge()?;
-            println!("\n未配置OpenAI API密钥，无法使用AI解决冲突。");
-            println!("合并已中止。请手动解决冲突，或配置API密钥后重试。");
         }
-    } else {
-        println!("合并成功完成！");
+        results.push(result);
+        if failed && !args.keep_going {
+            break;
+        }
     }
 
-    Ok(())
-}
-
-fn handle_list_unique(git: &git::GitHandler, target: &str, source: &str) -> Result<()> {
-    // 验证分支是否存在
-    if !git.branch_exists(target)? {
-        return Err(anyhow::anyhow!("目标分支 '{}' 不存在", target));
+    if let Some(resolver) = &resolver {
+        print_usage_summary(resolver);
     }
-    if !git.branch_exists(source)? {
-        return Err(anyhow::anyhow!("源分支 '{}' 不存在", source));
+
+    if format == OutputFormat::Json {
+        let tokens = resolver
+            .as_ref()
+            .map(|resolver| resolver.usage())
+            .unwrap_or_default();
+        let report = MergeReport::new(&args.source, &results, tokens);
+        output!("{}", serde_json::to_string_pretty(&report)?);
     }
 
-    // 获取不在源分支中的目标分支提交
-    println!("列出 '{}' 中不在 '{}' 中的提交:", target, source);
-    let unique_commits = git.list_unique_commits(target, source)?;
+    if !batch && !args.keep_going {
+        // 单个源分支时直接返回其结果，冲突未解决时与合并多个源分支一样以非零状态退出
+        return match results.pop() {
+            Some(Ok(result)) if result.outcome == SourceOutcome::Unresolved => Err(
+                anyhow::anyhow!("{} 个文件的冲突未能解决", result.unresolved.len()),
+            ),
+            Some(result) => result.map(|_| ()),
+            None => Ok(()),
+        };
+    }
 
-    if unique_commits.is_empty() {
-        println!("没有发现独有的提交。");
-    } else {
-        println!("发现 {} 个独有的提交:", unique_commits.len());
-        for (i, (commit_id, message)) in unique_commits.iter().enumerate() {
-            // 分割提交信息，获取标题和详细内容
-            let parts: Vec<&str> = message.splitn(2, '\n').collect();
-            let title = parts[0];
-            let details = if parts.len() > 1 { parts[1] } else { "" };
-
-            // 使用不同颜色高亮显示序号、哈希、标题，内容使用暗淡颜色
-            println!(
-                "{}. {} - {}{}",
-                (i + 1).to_string().cyan().bold(), // 序号使用青色加粗
-                commit_id.to_string()[..7].yellow(), // 哈希值前7位使用黄色
-                title.green().bold(),              // 标题使用绿色加粗
-                if !details.is_empty() {
-                    format!("\n   {}", details.dimmed()) // 内容使用暗淡显示，并缩进
-                } else {
-                    String::new()
+    if format != OutputFormat::Json {
+        output!("\n合并结果汇总:");
+    }
+    let width = args.source.iter().map(|s| s.len()).max().unwrap_or(0);
+    let mut failed = 0;
+    for (i, source) in args.source.iter().enumerate() {
+        let status = match results.get(i) {
+            Some(Ok(result)) => {
+                if result.outcome == SourceOutcome::Unresolved {
+                    failed += 1;
                 }
-            );
+                result.outcome.to_string()
+            }
+            Some(Err(e)) => {
+                failed += 1;
+                format!("失败: {}", e)
+            }
+            None => {
+                failed += 1;
+                "未执行".to_string()
+            }
+        };
+        if format != OutputFormat::Json {
+            output!("  {:<width$}  {}", source, status, width = width);
         }
     }
 
+    if failed > 0 {
+        return Err(anyhow::anyhow!(
+            "{}/{} 个源分支合并失败",
+            failed,
+            args.source.len()
+        ));
+    }
+
     Ok(())
 }
+
+/// 合并单个源分支，合并多个源分支时在冲突全部解决后直接创建合并提交
+async fn merge_source(
+    git: &git::GitHandler,
+    target: &str,
+    source: &str,
+    args: &MergeArgs,
+    config: &Settings,
+    resolver: Option<&ai::ConflictResolver>,
+    deadline: Option<Instant>,
+) -> Result<SourceResult> {
+    // 批量合并时需要提交已解决的合并，才能继续合并下一个源分支
+    let commit_resolved = args.source.len() > 1;
+
+    let message = if args.edit {
+        let initial = match &args.message {
+            Some(message) => message.clone(),
+            None => git.default_merge_message(target, source)?,
+        };
+        Some(edit_message(&initial)?)
+    } else {
+        args.message.clone()
+    };
+
+    // Attempt to merge
+    let merge_options = git::MergeOptions {
+        no_ff: args.no_ff,
+        ff_only: args.ff_only,
+        message: message.clone(),
+    };
+    if args.squash {
+        if !git.squash_merge(target, source)? {
+            status!("压缩合并完成，修改已暂存。请检查更改并提交。");
+            return Ok(SourceOutcome::Merged.into());
+        }
+    } else {
+        match git.merge_branches(target, source, &merge_options)? {
+            MergeOutcome::UpToDate => {
+                status!("'{}' 已包含 '{}' 的所有提交，无需合并。", target, source);
+                return Ok(SourceOutcome::UpToDate.into());
+            }
+            MergeOutcome::FastForwarded(commit_id) => {
+                status!(
+                    "已将 '{}' 快进到 '{}' ({})。",
+                    target,
+                    source,
+                    &commit_id.to_string()[..7]
+                );
+                return Ok(SourceOutcome::Merged.into());
+            }
+            MergeOutcome::Merged(commit_id) => {
+                status!(
+                    "合并成功完成！已创建合并提交 {}",
+                    &commit_id.to_string()[..7]
+                );
+                return Ok(SourceOutcome::Merged.into());
+            }
+            MergeOutcome::Conflicted(_) => {}
+        }
+    }
+
+    status!("合并产生冲突。正在获取冲突详情...");
+    let conflicts = git.get_conflicts()?;
+
+    // 在AI解决冲突前通知外部系统
+    let paths: Vec<String> = conflicts.iter().map(|c| c.path.clone()).collect();
+    let notification = hook::ConflictNotification::new(target, source, &paths);
+    for err in hook::notify_conflicts(config, &notification).await {
+        status!("警告: 冲突通知失败: {}", err);
+    }
+
+    print_conflicts(&conflicts);
+
+    let rules = load_strategy_rules(git, config)?;
+    let strategy_for = |conflict: &ConflictFile| conflict_strategy(&rules, args, conflict);
+    let total = conflicts.len();
+    let (for_ai, by_side): (Vec<ConflictFile>, Vec<ConflictFile>) = conflicts
+        .into_iter()
+        .partition(|conflict| strategy_for(conflict) == ConflictStrategy::Ai);
+
+    if !for_ai.is_empty() && resolver.is_none() {
+        git.abort_merge()?;
+        status!("\n未配置OpenAI API密钥，无法使用AI解决冲突。");
+        status!("合并已中止。请手动解决冲突，或配置API密钥后重试。");
+        return Ok(SourceResult {
+            unresolved: paths,
+            ..SourceOutcome::Unresolved.into()
+        });
+    }
+
+    let mut applied = resolve_conflicts_by_side(git, &by_side, &strategy_for);
+    if let Some(resolver) = resolver.filter(|_| !for_ai.is_empty()) {
+        let ai_applied =
+            resolve_conflicts_with_ai(git, resolver, &for_ai, args.interactive, deadline).await;
+        print_applied_files(&ai_applied);
+        applied.extend(ai_applied);
+    }
+
+    // 在合并提交中记录哪些文件由AI解决；不创建提交时写入 MERGE_MSG，由 git commit 或 continue 带入
+    let trailer = resolver
+        .filter(|_| !args.no_ai_trailer)
+        .and_then(|resolver| ai_merge_trailer(resolver, &for_ai, &applied));
+    if let Some(trailer) = &trailer {
+        if !commit_resolved && !args.squash {
+            if let Err(e) = git.append_merge_trailer(trailer) {
+                status!("警告: 无法在合并说明中记录AI解决的文件: {}", e);
+            }
+        }
+    }
+    if applied.len() < total {
+        let result = SourceResult {
+            outcome: SourceOutcome::Unresolved,
+            unresolved: paths
+                .into_iter()
+                .filter(|path| !applied.contains(path))
+                .collect(),
+            resolved: applied,
+        };
+
+        status!("\n某些冲突无法自动解决。");
+        if args.abort_on_partial {
+            git.abort_merge()?;
+            status!("合并已中止。请手动解决剩余冲突。");
+            return Ok(result);
+        }
+
+        // 保留已解决并暂存的文件，只列出仍需手动处理的文件
+        status!("以下文件需要手动解决:");
+        for path in git.conflict_status()?.conflicted {
+            status!("  {}", path.red());
+        }
+        if args.squash {
+            status!("已解决的文件已暂存。请手动解决上述文件后提交。");
+        } else {
+            status!("已解决的文件已暂存，合并仍在进行中。");
+            status!(
+                "请手动解决上述文件并使用 git add 暂存，然后运行 git-tools continue 完成合并。"
+            );
+        }
+        return Ok(result);
+    }
+
+    status!("\n所有冲突已成功解决！");
+    if commit_resolved && !args.squash {
+        git.commit_merge(target, source, message.as_deref(), trailer.as_deref())?;
+        status!("已创建合并提交。");
+    } else {
+        status!("请使用 git diff --cached 检查上述文件的修改后提交。");
+    }
+
+    Ok(SourceResult {
+        resolved: applied,
+        ..SourceOutcome::Resolved.into()
+    })
+}
+
+/// 由AI解决并已应用的冲突文件及所用模型的提交尾注，没有这样的文件时返回 None
+///
+/// LFS 指针按策略选择一方，不算作由AI解决
+fn ai_merge_trailer(
+    resolver: &ai::ConflictResolver,
+    conflicts: &[ConflictFile],
+    applied: &[String],
+) -> Option<String> {
+    let paths: Vec<String> = conflicts
+        .iter()
+        .filter(|conflict| !conflict.is_lfs_pointer() && applied.contains(&conflict.path))
+        .map(|conflict| conflict.path.clone())
+        .collect();
+    if paths.is_empty() {
+        return None;
+    }
+
+    let mut models: Vec<String> = paths
+        .iter()
+        .filter_map(|path| resolver.resolved_by(path))
+        .collect();
+    models.sort();
+    models.dedup();
+    Some(git::ai_trailer(&paths, &models))
+}
+
+/// 在编辑器中编辑提交说明
+///
+/// 以 '#' 开头的行会被忽略，说明为空时返回错误
+fn edit_message(initial: &str) -> Result<String> {
+    let content = edit_in_editor(
+        &format!(
+            "{}\n\n# 请输入合并提交说明。以 '#' 开头的行将被忽略，\n# 说明为空时中止合并。\n",
+            initial
+        ),
+        "",
+    )?;
+    let message = content
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string();
+    if message.is_empty() {
+        return Err(anyhow::anyhow!("合并提交说明为空，已中止合并"));
+    }
+
+    Ok(message)
+}
+
+/// 在编辑器中编辑文本并返回编辑后的内容，与 git 一样依次使用 GIT_EDITOR、VISUAL、EDITOR，
+/// 最后回退到 vi。suffix 为临时文件的后缀
+fn edit_in_editor(initial: &str, suffix: &str) -> Result<String> {
+    let editor = ["GIT_EDITOR", "VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|key| std::env::var(key).ok())
+        .find(|value| !value.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string());
+
+    let file = tempfile::Builder::new()
+        .prefix("git-tools-")
+        .suffix(suffix)
+        .tempfile()?;
+    std::fs::write(file.path(), initial)?;
+
+    // 通过 shell 执行，以支持 "code --wait" 这类带参数的编辑器配置
+    let status = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg(&editor)
+        .arg(file.path())
+        .status()
+        .map_err(|e| anyhow::anyhow!("无法启动编辑器 '{}': {}", editor, e))?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("编辑器 '{}' 异常退出: {}", editor, status));
+    }
+
+    Ok(std::fs::read_to_string(file.path())?)
+}
+
+/// 一方删除了文件的冲突的说明，其他冲突返回 None
+fn deletion_description(conflict: &ConflictFile) -> Option<&'static str> {
+    match conflict.kind {
+        ConflictKind::Content => None,
+        ConflictKind::DeletedByUs => Some("我们删除了该文件，对方修改了它"),
+        ConflictKind::DeletedByThem => Some("对方删除了该文件，我们修改了它"),
+    }
+}
+
+/// 冲突不能交给AI解决时输出原因并返回 true，这些冲突需要手动解决
+fn skip_unresolvable(resolver: &ai::ConflictResolver, conflict: &ConflictFile) -> bool {
+    if let Some(description) = deletion_description(conflict) {
+        status!("\n跳过 {}: {}", conflict.path, description);
+        status!(
+            "  请决定保留（git add {}）还是删除（git rm {}）该文件",
+            conflict.path,
+            conflict.path
+        );
+        return true;
+    }
+    if conflict.is_binary {
+        status!(
+            "\n跳过二进制或非 UTF-8 文件: {}，需要手动解决",
+            conflict.path
+        );
+        return true;
+    }
+    if !conflict.has_utf8_path() {
+        status!(
+            "\n跳过 {}: 路径不是有效的 UTF-8，需要手动解决",
+            conflict.path
+        );
+        return true;
+    }
+    if conflict.content_len() > resolver.max_conflict_bytes() {
+        status!(
+            "\n警告: 跳过 {}: 冲突内容共 {} 字节，超过 max_conflict_bytes ({} 字节)，需要手动解决",
+            conflict.path,
+            conflict.content_len(),
+            resolver.max_conflict_bytes()
+        );
+        return true;
+    }
+    false
+}
+
+/// 显示冲突详情
+fn print_conflicts(conflicts: &[ConflictFile]) {
+    for conflict in conflicts {
+        if let Some(description) = deletion_description(conflict) {
+            status!(
+                "\n文件冲突: {} ({}，需要手动解决)",
+                &conflict.path,
+                description
+            );
+            continue;
+        }
+        if conflict.is_binary {
+            status!(
+                "\n文件冲突: {} (二进制或非 UTF-8 文件，需要手动解决)",
+                &conflict.path
+            );
+            continue;
+        }
+        if !conflict.has_utf8_path() {
+            status!(
+                "\n文件冲突: {} (路径不是有效的 UTF-8，需要手动解决)",
+                &conflict.path
+            );
+            continue;
+        }
+        status!("\n文件冲突: {}", &conflict.path);
+        status!("我们的版本:\n{}", &conflict.our_content);
+        status!("他们的版本:\n{}", &conflict.their_content);
+        if let Some(base) = &conflict.base_content {
+            status!("基础版本:\n{}", base);
+        }
+    }
+}
+
+/// 按 total_timeout_seconds 计算AI解决冲突的截止时间，未配置时不限制
+fn resolution_deadline(config: &Settings) -> Option<Instant> {
+    config
+        .total_timeout_seconds
+        .map(|seconds| Instant::now() + std::time::Duration::from_secs(seconds))
+}
+
+/// 在截止时间前等待 future 完成，超时时返回 None
+async fn with_deadline<F: std::future::Future>(
+    deadline: Option<Instant>,
+    future: F,
+) -> Option<F::Output> {
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, future).await.ok(),
+        None => Some(future.await),
+    }
+}
+
+/// 创建AI冲突解决器，启用缓存时附加磁盘缓存
+fn new_resolver(config: Settings) -> ai::ConflictResolver {
+    let use_cache = config.cache;
+    let resolver = ai::ConflictResolver::new(config);
+    if !use_cache {
+        return resolver;
+    }
+    match ResolutionCache::open_default() {
+        Ok(cache) => resolver.with_cache(cache),
+        Err(e) => {
+            status!("警告: 无法使用解决方案缓存: {}", e);
+            resolver
+        }
+    }
+}
+
+/// 使用AI逐个解决冲突并应用，返回已应用解决方案的文件路径
+async fn resolve_conflicts_with_ai(
+    git: &git::GitHandler,
+    resolver: &ai::ConflictResolver,
+    conflicts: &[ConflictFile],
+    interactive: bool,
+    deadline: Option<Instant>,
+) -> Vec<String> {
+    status!("\n正在尝试使用AI解决冲突...");
+    // 批量请求无法附带每个文件的理由，启用 explain 时逐个解决
+    if resolver.batch() && !resolver.explain() {
+        return resolve_conflicts_in_batch(git, resolver, conflicts, interactive, deadline).await;
+    }
+    if resolver.concurrency() > 1 {
+        return resolve_conflicts_concurrently(git, resolver, conflicts, interactive, deadline)
+            .await;
+    }
+
+    // 重新绘制进度条会打断流式输出的片段，此时不显示进度条
+    let streams = resolver.streaming() && !resolver.explain() && !resolver.show_diff();
+    let progress = if streams {
+        ui::Progress::hidden(conflicts.len())
+    } else {
+        ui::Progress::new(conflicts.len())
+    };
+    let mut applied = Vec::new();
+    for (index, conflict) in conflicts.iter().enumerate() {
+        progress.set(index, &conflict.path);
+        if skip_unresolvable(resolver, conflict) {
+            continue;
+        }
+
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            status!("\n跳过 {}: 已超出总超时时间，需要手动解决", conflict.path);
+            continue;
+        }
+
+        status!("\n解决文件冲突: {}", conflict.path);
+        let usage_before = resolver.usage();
+        let result = with_deadline(deadline, async {
+            if resolver.explain() {
+                // 流式输出的是 JSON 原文，启用 explain 时等待完整回复后再输出
+                resolver
+                    .resolve_conflict_explained(conflict)
+                    .await
+                    .map(|resolution| {
+                        print_resolution(resolver, conflict, &resolution.content);
+                        if let Some(reasoning) = &resolution.reasoning {
+                            status!("{}\n{}", "理由:".bold(), reasoning);
+                        }
+                        resolution.content
+                    })
+            } else if streams {
+                // 差异需要完整的解决方案才能计算，此时不使用流式输出
+                status!("AI建议的解决方案:");
+                let result = resolver
+                    .resolve_conflict_streaming(conflict, &mut |event| match event {
+                        ai::StreamEvent::Token(token) => status_inline!("{}", token),
+                        ai::StreamEvent::Retry {
+                            attempt,
+                            max_attempts,
+                        } => status!("\n(第 {}/{} 次尝试，以上回复作废)", attempt, max_attempts),
+                        ai::StreamEvent::Fallback { model } => {
+                            status!("\n(改用备用模型 {}，以上回复作废)", model)
+                        }
+                    })
+                    .await;
+                status!();
+                result
+            } else {
+                resolver
+                    .resolve_conflict(conflict)
+                    .await
+                    .inspect(|resolution| print_resolution(resolver, conflict, resolution))
+            }
+        })
+        .await
+        .unwrap_or_else(|| Err(anyhow::anyhow!("已超出总超时时间")));
+
+        let usage = resolver.usage() - usage_before;
+        if usage != TokenUsage::default() {
+            status!(
+                "消耗 {} 个提示 token + {} 个补全 token",
+                usage.prompt_tokens,
+                usage.completion_tokens
+            );
+        }
+
+        match result {
+            Ok(resolution) => {
+                if review_and_apply(git, resolver, conflict, &resolution, interactive) {
+                    applied.push(conflict.path.clone());
+                }
+            }
+            Err(e) => status!("✗ 获取AI解决方案失败: {}", e),
+        }
+    }
+
+    applied
+}
+
+/// 同时向AI请求多个冲突的解决方案，全部返回后再按原来的顺序逐个应用
+///
+/// 索引只能串行写入，因此并发的只有请求。并发时不使用流式输出，各请求的输出会交错
+async fn resolve_conflicts_concurrently(
+    git: &git::GitHandler,
+    resolver: &ai::ConflictResolver,
+    conflicts: &[ConflictFile],
+    interactive: bool,
+    deadline: Option<Instant>,
+) -> Vec<String> {
+    let pending: Vec<&ConflictFile> = conflicts
+        .iter()
+        .filter(|conflict| !skip_unresolvable(resolver, conflict))
+        .collect();
+    status!(
+        "\n同时请求最多 {} 个冲突的解决方案...",
+        resolver.concurrency()
+    );
+
+    let progress = ui::Progress::new(pending.len());
+    let mut completed = 0;
+    let mut results: Vec<_> = stream::iter(pending.iter().copied().enumerate())
+        .map(|(index, conflict)| async move {
+            let result = with_deadline(deadline, resolver.resolve_conflict_explained(conflict))
+                .await
+                .unwrap_or_else(|| Err(anyhow::anyhow!("已超出总超时时间")));
+            (index, result)
+        })
+        .buffer_unordered(resolver.concurrency())
+        .inspect(|(index, result)| {
+            completed += 1;
+            let mark = if result.is_ok() { "✓" } else { "✗" };
+            status!(
+                "{} [{}/{}] {}",
+                mark,
+                completed,
+                pending.len(),
+                pending[*index].path
+            );
+            progress.inc(&pending[*index].path);
+        })
+        .collect()
+        .await;
+    drop(progress);
+    results.sort_by_key(|(index, _)| *index);
+
+    let mut applied = Vec::new();
+    for (index, result) in results {
+        let conflict = pending[index];
+        status!("\n解决文件冲突: {}", conflict.path);
+        match result {
+            Ok(resolution) => {
+                print_resolution(resolver, conflict, &resolution.content);
+                if let Some(reasoning) = &resolution.reasoning {
+                    status!("{}\n{}", "理由:".bold(), reasoning);
+                }
+                if review_and_apply(git, resolver, conflict, &resolution.content, interactive) {
+                    applied.push(conflict.path.clone());
+                }
+            }
+            Err(e) => status!("✗ 获取AI解决方案失败: {}", e),
+        }
+    }
+
+    applied
+}
+
+/// 读取配置的策略规则文件，未配置时返回空规则
+fn load_strategy_rules(git: &git::GitHandler, config: &Settings) -> Result<StrategyRules> {
+    let Some(path) = &config.strategy_rules else {
+        return Ok(StrategyRules::default());
+    };
+    let path = git.workdir()?.join(path);
+    StrategyRules::load(&path).map_err(|e| anyhow::anyhow!("无法加载策略规则: {}", e))
+}
+
+/// 冲突文件采用的解决策略：第一条匹配的规则，没有匹配的规则时使用 --strategy
+fn conflict_strategy(
+    rules: &StrategyRules,
+    args: &MergeArgs,
+    conflict: &ConflictFile,
+) -> ConflictStrategy {
+    rules.strategy_for(&conflict.path).unwrap_or(args.strategy)
+}
+
+/// 按 ours/theirs 策略直接采用一方的内容解决冲突，不请求AI，返回已解决的文件路径
+///
+/// 一方删除了文件的冲突和二进制文件无法通过写入内容解决，留给用户手动处理
+fn resolve_conflicts_by_side(
+    git: &git::GitHandler,
+    conflicts: &[ConflictFile],
+    strategy_for: &dyn Fn(&ConflictFile) -> ConflictStrategy,
+) -> Vec<String> {
+    if conflicts.is_empty() {
+        return Vec::new();
+    }
+    status!("\n正在按策略解决冲突...");
+
+    let mut applied = Vec::new();
+    for conflict in conflicts {
+        let strategy = strategy_for(conflict);
+        let description = match strategy {
+            ConflictStrategy::Ours => "我们的版本",
+            ConflictStrategy::Theirs => "他们的版本",
+            ConflictStrategy::Ai => continue,
+        };
+        if let Some(description) = deletion_description(conflict) {
+            status!("跳过 {}: {}，需要手动解决", conflict.path, description);
+            continue;
+        }
+        if conflict.is_binary {
+            status!("跳过二进制或非 UTF-8 文件: {}，需要手动解决", conflict.path);
+            continue;
+        }
+        if !conflict.has_utf8_path() {
+            status!("跳过 {}: 路径不是有效的 UTF-8，需要手动解决", conflict.path);
+            continue;
+        }
+
+        let content = if strategy == ConflictStrategy::Ours {
+            &conflict.our_content
+        } else {
+            &conflict.their_content
+        };
+        match git.apply_resolution(&conflict.path, content) {
+            Ok(_) => {
+                status!("✓ {}: 已采用{}", conflict.path, description);
+                applied.push(conflict.path.clone());
+            }
+            Err(e) => status!("✗ {}: 应用解决方案失败: {}", conflict.path, e),
+        }
+    }
+
+    applied
+}
+
+/// 列出由AI解决并暂存的文件，便于提交前逐个检查
+fn print_applied_files(applied: &[String]) {
+    if applied.is_empty() {
+        return;
+    }
+
+    status!(
+        "\n以下 {} 个文件的冲突由AI解决，请检查这些修改:",
+        applied.len()
+    );
+    for path in applied {
+        status!("  {}", path.green());
+    }
+}
+
+/// 交互确认时对AI解决方案的处理方式
+enum ReviewAction {
+    Accept,
+    Reject,
+    Edit,
+    Skip,
+}
+
+/// 询问如何处理AI的解决方案，输入结束时视为跳过
+fn prompt_review_action() -> ReviewAction {
+    loop {
+        let answer = match ui::prompt("[a]ccept / [r]eject / [e]dit / [s]kip", "") {
+            Ok(answer) => answer,
+            Err(_) => return ReviewAction::Skip,
+        };
+        match answer.to_lowercase().as_str() {
+            "a" | "accept" => return ReviewAction::Accept,
+            "r" | "reject" => return ReviewAction::Reject,
+            "e" | "edit" => return ReviewAction::Edit,
+            "s" | "skip" => return ReviewAction::Skip,
+            _ => status!("请输入 a、r、e 或 s"),
+        }
+    }
+}
+
+/// 应用AI的解决方案，交互模式下先由用户确认，返回冲突是否已解决
+fn review_and_apply(
+    git: &git::GitHandler,
+    resolver: &ai::ConflictResolver,
+    conflict: &ConflictFile,
+    resolution: &str,
+    interactive: bool,
+) -> bool {
+    if !interactive {
+        return apply_resolution(git, resolver, conflict, resolution);
+    }
+
+    loop {
+        match prompt_review_action() {
+            ReviewAction::Accept => return apply_resolution(git, resolver, conflict, resolution),
+            ReviewAction::Edit => {
+                // 保留原文件的扩展名，便于编辑器识别语法
+                let suffix = std::path::Path::new(&conflict.path)
+                    .extension()
+                    .map(|ext| format!(".{}", ext.to_string_lossy()))
+                    .unwrap_or_default();
+                match edit_in_editor(resolution, &suffix) {
+                    Ok(edited) => return apply_resolution(git, resolver, conflict, &edited),
+                    Err(e) => status!("✗ 编辑失败: {}", e),
+                }
+            }
+            ReviewAction::Reject => {
+                // 被拒绝的方案不应在重新运行时再次出现
+                resolver.forget_resolution(conflict);
+                status!("已拒绝该解决方案，请手动解决 {}", conflict.path);
+                return false;
+            }
+            ReviewAction::Skip => {
+                status!("已跳过，请手动解决 {}", conflict.path);
+                return false;
+            }
+        }
+    }
+}
+
+/// 输出AI的解决方案，启用 show_diff 时只输出相对于我们的版本的统一差异
+fn print_resolution(resolver: &ai::ConflictResolver, conflict: &ConflictFile, resolution: &str) {
+    if !resolver.show_diff() {
+        status!("AI建议的解决方案:\n{}", resolution);
+        return;
+    }
+
+    let diff = similar::TextDiff::from_lines(conflict.our_content.as_str(), resolution);
+    let diff = diff
+        .unified_diff()
+        .context_radius(3)
+        .header(
+            &format!("a/{}", conflict.path),
+            &format!("b/{}", conflict.path),
+        )
+        .to_string();
+    if diff.is_empty() {
+        status!("AI建议的解决方案与我们的版本相同");
+        return;
+    }
+
+    status!("AI建议的解决方案（相对于我们的版本的差异）:");
+    for line in diff.lines() {
+        if line.starts_with("+++") || line.starts_with("---") {
+            status!("{}", line.bold());
+        } else if line.starts_with('+') {
+            status!("{}", line.green());
+        } else if line.starts_with('-') {
+            status!("{}", line.red());
+        } else if line.starts_with("@@") {
+            status!("{}", line.cyan());
+        } else {
+            status!("{}", line);
+        }
+    }
+}
+
+/// 将解决方案写入文件并暂存，返回是否成功。配置了审计日志时记录应用的方案
+fn apply_resolution(
+    git: &git::GitHandler,
+    resolver: &ai::ConflictResolver,
+    conflict: &ConflictFile,
+    resolution: &str,
+) -> bool {
+    match git.apply_resolution(&conflict.path, resolution) {
+        Ok(_) => {
+            status!("✓ 解决方案应用成功");
+            if resolver.audit_log().is_some() {
+                let audited = git
+                    .workdir()
+                    .and_then(|repo| resolver.write_audit_entry(repo, conflict, resolution));
+                if let Err(e) = audited {
+                    status!("警告: 写入审计日志失败: {}", e);
+                }
+            }
+            true
+        }
+        Err(e) => {
+            status!("✗ 应用解决方案失败: {}", e);
+            false
+        }
+    }
+}
+
+/// 将冲突打包到一个请求中交给AI解决并应用，返回已应用解决方案的文件路径
+async fn resolve_conflicts_in_batch(
+    git: &git::GitHandler,
+    resolver: &ai::ConflictResolver,
+    conflicts: &[ConflictFile],
+    interactive: bool,
+    deadline: Option<Instant>,
+) -> Vec<String> {
+    let usage_before = resolver.usage();
+    let resolutions = with_deadline(deadline, resolver.resolve_conflicts_batch(conflicts))
+        .await
+        .unwrap_or_else(|| {
+            status!("✗ 已超出总超时时间，剩余冲突需要手动解决");
+            HashMap::new()
+        });
+    let usage = resolver.usage() - usage_before;
+    if usage != TokenUsage::default() {
+        status!(
+            "消耗 {} 个提示 token + {} 个补全 token",
+            usage.prompt_tokens,
+            usage.completion_tokens
+        );
+    }
+
+    let mut applied = Vec::new();
+    for conflict in conflicts {
+        if skip_unresolvable(resolver, conflict) {
+            continue;
+        }
+
+        status!("\n解决文件冲突: {}", conflict.path);
+        let Some(resolution) = resolutions.get(&conflict.path) else {
+            status!("✗ 获取AI解决方案失败");
+            continue;
+        };
+
+        print_resolution(resolver, conflict, resolution);
+        if review_and_apply(git, resolver, conflict, resolution, interactive) {
+            applied.push(conflict.path.clone());
+        }
+    }
+
+    applied
+}
+
+async fn handle_cherry_pick(git: &git::GitHandler, commit: &str, config: Settings) -> Result<()> {
+    status!("正在拣选提交 '{}'...", commit);
+    if !git.cherry_pick(commit)? {
+        status!("拣选成功完成！");
+        return Ok(());
+    }
+
+    resolve_and_commit(git, "拣选", config, || git.commit_cherry_pick(commit)).await
+}
+
+async fn handle_revert(git: &git::GitHandler, commit: &str, config: Settings) -> Result<()> {
+    status!("正在撤销提交 '{}'...", commit);
+    match git.revert(commit)? {
+        git::RevertOutcome::AlreadyReverted => {
+            status!("提交 '{}' 的修改已不在当前分支中，无需撤销。", commit);
+            return Ok(());
+        }
+        git::RevertOutcome::Reverted(commit_id) => {
+            status!("撤销成功完成！已创建提交 {}", &commit_id.to_string()[..7]);
+            return Ok(());
+        }
+        git::RevertOutcome::Conflicted(_) => {}
+    }
+
+    resolve_and_commit(git, "撤销", config, || git.commit_revert(commit)).await
+}
+
+/// 拣选或撤销（action）产生冲突后使用AI解决，全部解决时调用 commit 创建提交，
+/// 否则中止操作并返回错误，使命令以非零状态退出
+async fn resolve_and_commit(
+    git: &git::GitHandler,
+    action: &str,
+    config: Settings,
+    commit: impl FnOnce() -> Result<git2::Oid>,
+) -> Result<()> {
+    status!("{}产生冲突。正在获取冲突详情...", action);
+    let conflicts = git.get_conflicts()?;
+    print_conflicts(&conflicts);
+
+    if !config.ai_available() {
+        git.abort_merge()?;
+        status!("\n未配置OpenAI API密钥，无法使用AI解决冲突。");
+        status!("{}已中止。请手动解决冲突，或配置API密钥后重试。", action);
+        return Err(anyhow::anyhow!("{}产生冲突，已中止", action));
+    }
+
+    let deadline = resolution_deadline(&config);
+    let resolver = new_resolver(config);
+    let applied = resolve_conflicts_with_ai(git, &resolver, &conflicts, false, deadline).await;
+    let resolved = applied.len() == conflicts.len();
+    if resolved {
+        print_applied_files(&applied);
+        commit()?;
+        status!("\n所有冲突已成功解决，{}提交已创建！", action);
+    } else {
+        git.abort_merge()?;
+        status!("\n某些冲突无法自动解决。");
+        status!("{}已中止。请手动解决剩余冲突。", action);
+    }
+    print_usage_summary(&resolver);
+
+    if !resolved {
+        return Err(anyhow::anyhow!("某些冲突无法自动解决，{}已中止", action));
+    }
+    Ok(())
+}
+
+fn handle_tag(
+    git: &git::GitHandler,
+    name: &str,
+    message: Option<&str>,
+    commit: Option<&str>,
+) -> Result<()> {
+    let tag_id = git
+        .create_tag(name, commit, message)
+        .map_err(|e| anyhow::anyhow!("无法创建标签 '{}': {}", name, e))?;
+    let kind = if message.is_some() {
+        "附注标签"
+    } else {
+        "轻量标签"
+    };
+    status!(
+        "已创建{} {} ({})，指向 {}",
+        kind,
+        name,
+        &tag_id.to_string()[..7],
+        commit.unwrap_or("HEAD")
+    );
+
+    Ok(())
+}
+
+async fn handle_rebase(
+    git: &git::GitHandler,
+    onto: &str,
+    branch: &str,
+    config: Settings,
+) -> Result<()> {
+    if !git.branch_exists(onto)? {
+        return Err(anyhow::anyhow!("目标分支 '{}' 不存在", onto));
+    }
+    if !git.branch_exists(branch)? {
+        return Err(anyhow::anyhow!("分支 '{}' 不存在", branch));
+    }
+
+    // 只有配置了API密钥时才能使用AI解决冲突
+    let resolver = config.ai_available().then(|| new_resolver(config));
+    let resolver = resolver.as_ref();
+
+    status!("正在将 '{}' 变基到 '{}'...", branch, onto);
+    let result = git
+        .rebase_branch(branch, onto, |conflicts| async move {
+            status!("\n变基产生 {} 个冲突", conflicts.len());
+            let resolver = resolver
+                .ok_or_else(|| anyhow::anyhow!("未配置OpenAI API密钥，无法使用AI解决冲突"))?;
+
+            for conflict in &conflicts {
+                status!("\n解决文件冲突: {}", conflict.path);
+                let resolution = resolver.resolve_conflict(conflict).await?;
+                print_resolution(resolver, conflict, &resolution);
+                git.apply_resolution(&conflict.path, &resolution)?;
+                status!("✓ 解决方案应用成功");
+            }
+
+            Ok(())
+        })
+        .await;
+
+    if let Some(resolver) = resolver {
+        print_usage_summary(resolver);
+    }
+
+    match result {
+        Ok(applied) => {
+            status!("\n变基成功完成，共应用 {} 个提交。", applied);
+            Ok(())
+        }
+        Err(e) => {
+            status!("\n变基失败，已中止。请手动解决冲突后重试。");
+            Err(e)
+        }
+    }
+}
+
+/// 输出AI解决冲突累计消耗的 token 数，配置了价格时附带估算费用
+fn print_usage_summary(resolver: &ai::ConflictResolver) {
+    let usage = resolver.usage();
+    if resolver.resolved_conflicts() == 0 && usage == TokenUsage::default() {
+        return;
+    }
+
+    status!(
+        "\nAI共解决 {} 个冲突，消耗 {} 个提示 token + {} 个补全 token",
+        resolver.resolved_conflicts(),
+        usage.prompt_tokens,
+        usage.completion_tokens
+    );
+    if let Some(cost) = resolver.estimated_cost() {
+        status!("预计费用: {:.4}", cost);
+    }
+}
+
+fn print_resolution_plan(
+    conflicts: &[ConflictFile],
+    config: &Settings,
+    strategy_for: &dyn Fn(&ConflictFile) -> ConflictStrategy,
+) {
+    if conflicts.is_empty() {
+        status!("合并不会产生冲突。");
+        return;
+    }
+
+    status!("合并将产生 {} 个冲突，解决计划如下:", conflicts.len());
+    let width = conflicts.iter().map(|c| c.path.len()).max().unwrap_or(0);
+    for conflict in conflicts {
+        output!(
+            "  {:<width$}  {}",
+            conflict.path,
+            plan_resolution(conflict, config, strategy_for(conflict)),
+            width = width
+        );
+    }
+}
+
+fn handle_continue(git: &git::GitHandler, message: Option<&str>) -> Result<()> {
+    if git.repository_state() != git2::RepositoryState::Merge {
+        return Err(anyhow::anyhow!("当前没有进行中的合并"));
+    }
+
+    let status = git.conflict_status()?;
+    if !status.conflicted.is_empty() {
+        status!("以下文件仍有冲突，请解决并使用 git add 暂存后重试:");
+        for path in &status.conflicted {
+            status!("  {}", path.red());
+        }
+        return Err(anyhow::anyhow!(
+            "仍有 {} 个文件存在冲突",
+            status.conflicted.len()
+        ));
+    }
+
+    let commit_id = git.finalize_merge(message)?;
+    status!("已创建合并提交 {}", &commit_id.to_string()[..7]);
+
+    Ok(())
+}
+
+fn handle_abort(git: &git::GitHandler) -> Result<()> {
+    if git.repository_state() != git2::RepositoryState::Merge {
+        return Err(anyhow::anyhow!("当前没有进行中的合并"));
+    }
+
+    git.abort_merge()?;
+    status!("已中止合并，索引和工作目录已恢复到合并前的状态。");
+
+    Ok(())
+}
+
+/// status 以 JSON 格式输出的内容
+#[derive(Serialize)]
+struct StatusReport<'a> {
+    state: &'a str,
+    conflicted: &'a [String],
+    resolved: &'a [String],
+}
+
+/// 仓库状态的标识和说明
+fn describe_state(state: git2::RepositoryState) -> (&'static str, &'static str) {
+    use git2::RepositoryState::*;
+    match state {
+        Clean => ("clean", "没有进行中的操作"),
+        Merge => ("merge", "合并中"),
+        Revert | RevertSequence => ("revert", "撤销提交中"),
+        CherryPick | CherryPickSequence => ("cherry-pick", "拣选中"),
+        Bisect => ("bisect", "二分查找中"),
+        Rebase | RebaseInteractive | RebaseMerge => ("rebase", "变基中"),
+        ApplyMailbox | ApplyMailboxOrRebase => ("apply-mailbox", "应用补丁中"),
+    }
+}
+
+fn handle_status(git: &git::GitHandler, format: OutputFormat) -> Result<()> {
+    let (state, description) = describe_state(git.repository_state());
+    let status = git.conflict_status()?;
+
+    if format == OutputFormat::Json {
+        let report = StatusReport {
+            state,
+            conflicted: &status.conflicted,
+            resolved: &status.resolved,
+        };
+        output!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    output!("仓库状态: {}", description.bold());
+    if status.conflicted.is_empty() && status.resolved.is_empty() {
+        output!("没有冲突的文件。");
+        return Ok(());
+    }
+
+    if !status.conflicted.is_empty() {
+        output!("\n仍有冲突的文件 ({}):", status.conflicted.len());
+        for path in &status.conflicted {
+            output!("  {}", path.red());
+        }
+    }
+    if !status.resolved.is_empty() {
+        output!("\n已解决并暂存的文件 ({}):", status.resolved.len());
+        for path in &status.resolved {
+            output!("  {}", path.green());
+        }
+    }
+
+    Ok(())
+}
+
+/// list-unique 和 log 以 JSON 格式输出时的单个提交
+#[derive(Serialize)]
+struct UniqueCommit<'a> {
+    id: String,
+    short_id: String,
+    title: &'a str,
+    body: &'a str,
+    author_name: &'a str,
+    author_email: &'a str,
+    /// 作者时间，Unix 时间戳（秒）
+    time: i64,
+    /// 只在指定 --show-refs 时输出
+    #[serde(skip_serializing_if = "Option::is_none")]
+    refs: Option<&'a [String]>,
+}
+
+fn handle_list_unique(
+    git: &git::GitHandler,
+    args: &ListUniqueArgs,
+    format: OutputFormat,
+) -> Result<()> {
+    // clap 保证每组参数中至少指定了一个
+    let target = args
+        .until_ref
+        .as_deref()
+        .or(args.target.as_deref())
+        .unwrap_or_default();
+    let source = args
+        .since_ref
+        .as_deref()
+        .or(args.source.as_deref())
+        .unwrap_or_default();
+
+    // 验证分支或版本是否存在
+    if let Err(e) = git.resolve_revision(target) {
+        return Err(anyhow::anyhow!("目标分支或版本 '{}' 不存在: {}", target, e));
+    }
+    if let Err(e) = git.resolve_revision(source) {
+        return Err(anyhow::anyhow!("源分支或版本 '{}' 不存在: {}", source, e));
+    }
+
+    // 获取不在源分支中的目标分支提交
+    status!("列出 '{}' 中不在 '{}' 中的提交:", target, source);
+    let filter = git::CommitFilter {
+        author: args.author.clone(),
+        since: args.since,
+        until: args.until,
+        // 多取一个用于判断是否还有更多提交
+        limit: args.limit.map(|limit| limit + 1),
+    };
+    let mut unique_commits = git.list_unique_commits(target, source, &filter)?;
+    let truncated = args.limit.is_some_and(|limit| unique_commits.len() > limit);
+    if let Some(limit) = args.limit {
+        unique_commits.truncate(limit);
+    }
+    let refs = if args.show_refs {
+        git.refs_by_commit()?
+    } else {
+        HashMap::new()
+    };
+
+    if format == OutputFormat::Json {
+        print_commits_json(&unique_commits, args.show_refs.then_some(&refs))?;
+        if truncated {
+            status!("(仅显示最新的 {} 个提交，还有更多)", unique_commits.len());
+        }
+        return Ok(());
+    }
+
+    if unique_commits.is_empty() {
+        status!("没有发现独有的提交。");
+    } else {
+        status!("发现 {} 个独有的提交:", unique_commits.len());
+        print_commits(&unique_commits, &refs, false);
+
+        if truncated {
+            status!("(仅显示最新的 {} 个提交，还有更多)", unique_commits.len());
+        }
+    }
+
+    Ok(())
+}
+
+fn handle_log(git: &git::GitHandler, args: &LogArgs, format: OutputFormat) -> Result<()> {
+    if let Err(e) = git.resolve_revision(&args.branch) {
+        return Err(anyhow::anyhow!(
+            "分支或版本 '{}' 不存在: {}",
+            args.branch,
+            e
+        ));
+    }
+
+    // 多取一个用于判断是否还有更多提交
+    let mut commits = git.log_branch(&args.branch, args.limit.map(|limit| limit + 1))?;
+    let truncated = args.limit.is_some_and(|limit| commits.len() > limit);
+    if let Some(limit) = args.limit {
+        commits.truncate(limit);
+    }
+
+    if format == OutputFormat::Json {
+        print_commits_json(&commits, None)?;
+    } else {
+        print_commits(&commits, &HashMap::new(), args.oneline);
+    }
+    if truncated {
+        status!("(仅显示最新的 {} 个提交，还有更多)", commits.len());
+    }
+
+    Ok(())
+}
+
+/// 以 JSON 格式输出提交列表，refs 为 None 时不输出引用字段
+fn print_commits_json(
+    commits: &[git::CommitInfo],
+    refs: Option<&HashMap<git2::Oid, Vec<String>>>,
+) -> Result<()> {
+    let commits: Vec<UniqueCommit> = commits
+        .iter()
+        .map(|commit| {
+            let id = commit.id.to_string();
+            UniqueCommit {
+                short_id: id[..7].to_string(),
+                id,
+                title: &commit.summary,
+                body: &commit.body,
+                author_name: &commit.author_name,
+                author_email: &commit.author_email,
+                time: commit.time.seconds(),
+                refs: refs.map(|refs| {
+                    refs.get(&commit.id)
+                        .map_or(&[][..], |names| names.as_slice())
+                }),
+            }
+        })
+        .collect();
+    output!("{}", serde_json::to_string_pretty(&commits)?);
+    Ok(())
+}
+
+/// 以彩色文本输出提交列表，oneline 时每个提交只显示短哈希和标题
+fn print_commits(
+    commits: &[git::CommitInfo],
+    refs: &HashMap<git2::Oid, Vec<String>>,
+    oneline: bool,
+) {
+    for (i, commit) in commits.iter().enumerate() {
+        // 指向该提交的标签和分支
+        let decoration = match refs.get(&commit.id) {
+            Some(names) => format!(" ({})", names.join(", ")).magenta().to_string(),
+            None => String::new(),
+        };
+
+        if oneline {
+            output!(
+                "{}{} {}",
+                commit.id.to_string()[..7].yellow(),
+                decoration,
+                commit.summary
+            );
+            continue;
+        }
+
+        // 使用不同颜色高亮显示序号、哈希、标题，内容使用暗淡颜色
+        output!(
+            "{}. {}{} - {} {}{}",
+            (i + 1).to_string().cyan().bold(),   // 序号使用青色加粗
+            commit.id.to_string()[..7].yellow(), // 哈希值前7位使用黄色
+            decoration,                          // 引用使用洋红色
+            commit.summary.green().bold(),       // 标题使用绿色加粗
+            format!("{} {}", commit.author_name, git::format_time(commit.time)).blue(), // 作者和时间使用蓝色
+            if !commit.body.is_empty() {
+                format!("\n   {}", commit.body.dimmed()) // 内容使用暗淡显示，并缩进
+            } else {
+                String::new()
+            }
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use crate::config::ConflictStrategy;
+use anyhow::{anyhow, Result};
+use config::{Config, File};
+use git2::{Pathspec, PathspecFlags};
+use serde::Deserialize;
+use std::path::Path;
+
+/// 规则文件中的一条规则
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StrategyRule {
+    /// 匹配冲突文件路径的通配符模式，不含 `/` 时匹配任意目录下的文件名
+    pub pattern: String,
+    /// 匹配的文件采用的解决策略
+    pub strategy: ConflictStrategy,
+}
+
+#[derive(Deserialize)]
+struct RulesFile {
+    #[serde(default)]
+    rules: Vec<StrategyRule>,
+}
+
+/// 按路径为冲突文件选择解决策略，使用第一条匹配的规则
+#[derive(Default)]
+pub struct StrategyRules {
+    rules: Vec<(StrategyRule, Pathspec)>,
+}
+
+impl StrategyRules {
+    /// 读取规则文件，格式由扩展名决定（.toml 或 .json）
+    pub fn load(path: &Path) -> Result<Self> {
+        let file: RulesFile = Config::builder()
+            .add_source(File::from(path))
+            .build()
+            .and_then(|config| config.try_deserialize())
+            .map_err(|e| anyhow!("Failed to load rules file {}: {}", path.display(), e))?;
+
+        let rules = file
+            .rules
+            .into_iter()
+            .map(|rule| {
+                let pathspec = Pathspec::new([rule.pattern.as_str()])
+                    .map_err(|e| anyhow!("Invalid pattern '{}': {}", rule.pattern, e))?;
+                Ok((rule, pathspec))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self { rules })
+    }
+
+    /// 返回第一条匹配该路径的规则的策略，没有匹配的规则时返回 None
+    pub fn strategy_for(&self, path: &str) -> Option<ConflictStrategy> {
+        let path = Path::new(path);
+        self.rules
+            .iter()
+            .find(|(rule, pathspec)| {
+                // 与 .gitignore 一样，不含 `/` 的模式只匹配文件名
+                let candidate = if rule.pattern.contains('/') {
+                    Some(path)
+                } else {
+                    path.file_name().map(Path::new)
+                };
+                candidate.is_some_and(|candidate| {
+                    pathspec.matches_path(candidate, PathspecFlags::DEFAULT)
+                })
+            })
+            .map(|(rule, _)| rule.strategy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_strategy_for() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("rules.toml");
+        std::fs::write(
+            &path,
+            r#"
+[[rules]]
+pattern = "package-lock.json"
+strategy = "ours"
+
+[[rules]]
+pattern = "CHANGELOG.md"
+strategy = "theirs"
+
+[[rules]]
+pattern = "generated/*"
+strategy = "theirs"
+
+[[rules]]
+pattern = "*.lock"
+strategy = "ours"
+"#,
+        )?;
+
+        let rules = StrategyRules::load(&path)?;
+        assert_eq!(
+            rules.strategy_for("package-lock.json"),
+            Some(ConflictStrategy::Ours)
+        );
+        assert_eq!(
+            rules.strategy_for("web/package-lock.json"),
+            Some(ConflictStrategy::Ours)
+        );
+        assert_eq!(
+            rules.strategy_for("CHANGELOG.md"),
+            Some(ConflictStrategy::Theirs)
+        );
+        assert_eq!(
+            rules.strategy_for("generated/api/client.rs"),
+            Some(ConflictStrategy::Theirs)
+        );
+        assert_eq!(
+            rules.strategy_for("Cargo.lock"),
+            Some(ConflictStrategy::Ours)
+        );
+        assert_eq!(rules.strategy_for("src/main.rs"), None);
+        assert_eq!(rules.strategy_for("src/generated/mod.rs"), None);
+
+        // 规则文件不存在或格式错误时报错
+        assert!(StrategyRules::load(&temp_dir.path().join("missing.toml")).is_err());
+        std::fs::write(
+            &path,
+            "[[rules]]\npattern = \"*.md\"\nstrategy = \"mine\"\n",
+        )?;
+        assert!(StrategyRules::load(&path).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,198 @@
+//! 终端输入输出
+//!
+//! 进度和状态信息写入标准错误，只有命令的结果（提交列表、JSON、解决计划等）
+//! 写入标准输出，这样通过管道处理结果时不会混入进度信息。交互式提示同样写入标准错误。
+//! 使用 `--quiet` 时不输出进度和状态信息，但交互式提示和命令结果不受影响。
+//!
+//! 进度条占用标准错误的最后一行，状态信息、日志和交互式提示都通过 [`suspend_progress`]
+//! 输出，输出时暂时隐藏进度条，之后重新绘制。
+
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::io::{BufRead, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// 当前显示的进度条
+static PROGRESS: Mutex<Option<ProgressBar>> = Mutex::new(None);
+
+/// 设置是否隐藏进度和状态信息
+pub(crate) fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+/// 是否隐藏进度和状态信息
+pub(crate) fn quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// 隐藏进度条后执行 f 并重新绘制，用于向标准错误输出内容
+///
+/// 执行期间取出进度条，f 中再次输出时直接写入，不会重复等待进度条的锁
+pub(crate) fn suspend_progress<R>(f: impl FnOnce() -> R) -> R {
+    let bar = PROGRESS.lock().unwrap().take();
+    match bar {
+        Some(bar) => {
+            let result = bar.suspend(f);
+            PROGRESS.lock().unwrap().get_or_insert(bar);
+            result
+        }
+        None => f(),
+    }
+}
+
+/// 写入标准错误时暂时隐藏进度条，用作日志的输出目标
+pub(crate) struct StderrWriter;
+
+impl Write for StderrWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        suspend_progress(|| std::io::stderr().write(buf))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        std::io::stderr().flush()
+    }
+}
+
+/// 输出进度和状态信息，写入标准错误，`--quiet` 时不输出
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if !$crate::ui::quiet() {
+            $crate::ui::suspend_progress(|| eprintln!($($arg)*))
+        }
+    };
+}
+
+/// 输出不换行的进度信息（例如流式输出的片段），写入标准错误，`--quiet` 时不输出
+macro_rules! status_inline {
+    ($($arg:tt)*) => {
+        if !$crate::ui::quiet() {
+            $crate::ui::suspend_progress(|| eprint!($($arg)*))
+        }
+    };
+}
+
+/// 输出命令结果，写入标准输出
+macro_rules! output {
+    ($($arg:tt)*) => {
+        println!($($arg)*)
+    };
+}
+
+pub(crate) use output;
+pub(crate) use status;
+pub(crate) use status_inline;
+
+/// 处理多个文件时显示的进度条，只在标准错误是终端且未使用 `--quiet` 时显示，离开作用域时清除
+pub(crate) struct Progress {
+    bar: ProgressBar,
+}
+
+impl Progress {
+    pub(crate) fn new(total: usize) -> Self {
+        let target = if quiet() || !std::io::stderr().is_terminal() {
+            ProgressDrawTarget::hidden()
+        } else {
+            ProgressDrawTarget::stderr()
+        };
+        Self::with_draw_target(total, target)
+    }
+
+    /// 不显示的进度条，例如流式输出 AI 回复的片段时
+    pub(crate) fn hidden(total: usize) -> Self {
+        Self::with_draw_target(total, ProgressDrawTarget::hidden())
+    }
+
+    fn with_draw_target(total: usize, target: ProgressDrawTarget) -> Self {
+        let style = ProgressStyle::with_template("[{bar:20}] {pos}/{len} {msg}")
+            .expect("valid progress template")
+            .progress_chars("#>-");
+        let bar = ProgressBar::with_draw_target(Some(total as u64), target).with_style(style);
+        *PROGRESS.lock().unwrap() = Some(bar.clone());
+        Self { bar }
+    }
+
+    /// 已处理 completed 个文件，message 为正在处理或刚处理完的文件
+    pub(crate) fn set(&self, completed: usize, message: &str) {
+        self.bar.set_position(completed as u64);
+        self.bar.set_message(message.to_string());
+    }
+
+    /// 又处理完一个文件
+    pub(crate) fn inc(&self, message: &str) {
+        self.bar.inc(1);
+        self.bar.set_message(message.to_string());
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        PROGRESS.lock().unwrap().take();
+        self.bar.finish_and_clear();
+    }
+}
+
+/// 读取一行输入并去掉首尾空白，输入结束时返回错误
+fn read_line() -> std::io::Result<String> {
+    let mut input = String::new();
+    if std::io::stdin().lock().read_line(&mut input)? == 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            "输入已结束",
+        ));
+    }
+    Ok(input.trim().to_string())
+}
+
+/// 显示提示并读取一行输入，直接回车时使用默认值
+pub(crate) fn prompt(label: &str, default: &str) -> std::io::Result<String> {
+    suspend_progress(|| prompt_line(label, default))
+}
+
+fn prompt_line(label: &str, default: &str) -> std::io::Result<String> {
+    if default.is_empty() {
+        eprint!("{}: ", label);
+    } else {
+        eprint!("{} [{}]: ", label, default);
+    }
+    std::io::stderr().flush()?;
+
+    let input = read_line()?;
+    Ok(if input.is_empty() {
+        default.to_string()
+    } else {
+        input
+    })
+}
+
+/// 显示提示并读取不回显的输入，用于 API 密钥等敏感信息
+pub(crate) fn prompt_secret(label: &str) -> std::io::Result<String> {
+    suspend_progress(|| prompt_secret_line(label))
+}
+
+fn prompt_secret_line(label: &str) -> std::io::Result<String> {
+    eprint!("{}: ", label);
+    std::io::stderr().flush()?;
+
+    // 通过 stty 关闭终端回显，读取结束后无论成功与否都恢复
+    let terminal = cfg!(unix) && std::io::stdin().is_terminal();
+    let stty = |arg: &str| {
+        let _ = std::process::Command::new("stty").arg(arg).status();
+    };
+    if terminal {
+        stty("-echo");
+    }
+    let input = read_line();
+    if terminal {
+        stty("echo");
+        eprintln!();
+    }
+    input
+}
+
+/// 询问是或否，直接回车时返回 false
+pub(crate) fn confirm(label: &str) -> std::io::Result<bool> {
+    let answer = prompt(&format!("{} [y/N]", label), "")?;
+    Ok(matches!(answer.to_lowercase().as_str(), "y" | "yes"))
+}